@@ -0,0 +1,58 @@
+// Latency of a read_at() over a range with and without a prefetch() of it
+// first, run with `cargo bench --bench prefetch -- <path>`. Two ranges far
+// apart in the file are read, one straight off and one after prefetching, so
+// for the difference to show the file has to be out of the cache beforehand,
+// e.g. freshly copied to another machine or after dropping the OS cache. With
+// no path a file is generated, which leaves it cached and both reads warm.
+use async_file::{AsyncFile, AsyncFileError, Result};
+use std::time::{Duration, Instant};
+
+const FILE_LEN: usize = 64 * 1024 * 1024;
+const RANGE_LEN: usize = 4 * 1024 * 1024;
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let generated = std::env::temp_dir().join("async_file_prefetch.bin");
+    // cargo bench passes --bench along to the binary.
+    let path = match std::env::args().skip(1).find(|arg| !arg.starts_with("--")) {
+        Some(path) => path,
+        None => {
+            let data: Vec<u8> = (0..FILE_LEN).map(|i| (i % 251) as u8).collect();
+            std::fs::write(&generated, data).map_err(AsyncFileError::Io)?;
+            generated.to_string_lossy().into_owned()
+        }
+    };
+
+    let file = AsyncFile::open_for_read(&path).await?;
+    let file_len = std::fs::metadata(&path).map_err(AsyncFileError::Io)?.len();
+    let range_len = RANGE_LEN.min(file_len as usize / 2);
+    let cold_at = 0;
+    let warm_at = file_len - range_len as u64;
+
+    let cold = timed_read(&file, cold_at, range_len).await?;
+    let start = Instant::now();
+    file.prefetch(warm_at, range_len as u64).await?;
+    let prefetch = start.elapsed();
+    let warm = timed_read(&file, warm_at, range_len).await?;
+
+    println!("{:>10} {:>12}", "read", "ms");
+    println!("{:>10} {:>12.3}", "cold", ms(cold));
+    println!("{:>10} {:>12.3}", "prefetch", ms(prefetch));
+    println!("{:>10} {:>12.3}", "warm", ms(warm));
+
+    file.close()?;
+    let _ = std::fs::remove_file(&generated);
+    Ok(())
+}
+
+// How long a single read_at() of len bytes at offset takes.
+async fn timed_read(file: &AsyncFile, offset: u64, len: usize) -> Result<Duration> {
+    let mut buf = vec![0; len];
+    let start = Instant::now();
+    file.read_at(&mut buf, offset).await?;
+    Ok(start.elapsed())
+}
+
+fn ms(elapsed: Duration) -> f64 {
+    elapsed.as_secs_f64() * 1e3
+}
@@ -0,0 +1,53 @@
+// Throughput of read_at() over a generated file at a range of queue depths
+// and block sizes, run with `cargo bench`. The file is written just before
+// the runs, so it mostly comes from the page cache and the numbers measure
+// the cost of submitting and completing reads more than the disk.
+use async_file::{AsyncFile, AsyncFileError, Result};
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use std::time::Instant;
+
+const FILE_LEN: usize = 256 * 1024 * 1024;
+const BLOCK_SIZES: [usize; 3] = [4 * 1024, 64 * 1024, 1024 * 1024];
+const DEPTHS: [usize; 4] = [1, 4, 16, 64];
+
+#[tokio::main]
+async fn main() -> Result<()> {
+    let path = std::env::temp_dir().join("async_file_queue_depth.bin");
+    let data: Vec<u8> = (0..FILE_LEN).map(|i| (i % 251) as u8).collect();
+    std::fs::write(&path, data).map_err(AsyncFileError::Io)?;
+
+    let file = AsyncFile::open_for_read(&path.to_string_lossy()).await?;
+    println!("{:>8} {:>6} {:>10}", "block", "depth", "MB/s");
+    for block_size in BLOCK_SIZES {
+        for depth in DEPTHS {
+            let rate = throughput(&file, block_size, depth).await?;
+            println!("{:>7}K {depth:>6} {rate:>10.1}", block_size / 1024);
+        }
+    }
+
+    file.close()?;
+    let _ = std::fs::remove_file(&path);
+    Ok(())
+}
+
+// Reads the whole file once in block_size pieces, keeping depth reads in
+// flight until the last ones, and returns the rate in MB/s.
+async fn throughput(file: &AsyncFile, block_size: usize, depth: usize) -> Result<f64> {
+    let mut offsets = (0..FILE_LEN / block_size).map(|i| (i * block_size) as u64);
+    let mut in_flight = FuturesUnordered::new();
+    let start = Instant::now();
+
+    for offset in offsets.by_ref().take(depth) {
+        in_flight.push(file.read_at_owned(vec![0; block_size], offset));
+    }
+    let mut total = 0;
+    while let Some(result) = in_flight.next().await {
+        let (buf, n) = result?;
+        total += n;
+        if let Some(offset) = offsets.next() {
+            in_flight.push(file.read_at_owned(buf, offset));
+        }
+    }
+
+    Ok(total as f64 / 1e6 / start.elapsed().as_secs_f64())
+}
@@ -0,0 +1,60 @@
+#[cfg(windows)]
+use crate::AsyncFile;
+use std::alloc::{self, Layout};
+use std::ops::{Deref, DerefMut};
+use std::ptr::NonNull;
+
+// A zeroed heap buffer whose address and length are multiples of a given
+// alignment, as unbuffered I/O needs. A Vec<u8> only promises byte alignment.
+pub struct AlignedBuffer {
+    ptr: NonNull<u8>,
+    layout: Layout,
+}
+
+// It owns its allocation outright, just as a Vec<u8> would.
+unsafe impl Send for AlignedBuffer {}
+unsafe impl Sync for AlignedBuffer {}
+
+impl AlignedBuffer {
+    // len is rounded up to a whole number of align-sized blocks, and to at
+    // least one. align must be a power of two.
+    pub fn new(len: usize, align: usize) -> Self {
+        let size = len.max(1).next_multiple_of(align);
+        let layout = Layout::from_size_align(size, align).expect("invalid buffer alignment");
+        let ptr = unsafe { alloc::alloc_zeroed(layout) };
+        let ptr = NonNull::new(ptr).unwrap_or_else(|| alloc::handle_alloc_error(layout));
+        Self { ptr, layout }
+    }
+
+    // At least len bytes that any read or write on file accepts: sector
+    // aligned and rounded up to whole sectors if it's unbuffered, otherwise
+    // just len. Same as file.aligned_buffer(len).
+    #[cfg(windows)]
+    pub fn with_capacity(file: &AsyncFile, len: usize) -> Self {
+        file.aligned_buffer(len)
+    }
+
+    pub fn align(&self) -> usize {
+        self.layout.align()
+    }
+}
+
+impl Deref for AlignedBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        unsafe { std::slice::from_raw_parts(self.ptr.as_ptr(), self.layout.size()) }
+    }
+}
+
+impl DerefMut for AlignedBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        unsafe { std::slice::from_raw_parts_mut(self.ptr.as_ptr(), self.layout.size()) }
+    }
+}
+
+impl Drop for AlignedBuffer {
+    fn drop(&mut self) {
+        unsafe { alloc::dealloc(self.ptr.as_ptr(), self.layout) }
+    }
+}
@@ -0,0 +1,75 @@
+use crate::{AsyncFile, Result};
+use futures_util::future::join_all;
+
+impl AsyncFile {
+    // Reads each (offset, len) of reqs, all of them in flight together, and
+    // returns their results in the same order once every one has finished.
+    // Each read stands alone: one failing doesn't stop or fail the others,
+    // and each is a single read_at(), so one that runs into EOF comes back
+    // short, or empty past it. Reads wait their turn with the file's limiter,
+    // if it has one, so a batch larger than the limit goes out as permits
+    // free up.
+    pub async fn submit_batch(&self, reqs: Vec<(u64, usize)>) -> Vec<Result<Vec<u8>>> {
+        join_all(reqs.into_iter().map(|(offset, len)| async move {
+            let (mut buf, n) = self.read_at_owned(vec![0; len], offset).await?;
+            buf.truncate(n);
+            Ok(buf)
+        }))
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{pattern, TempFile};
+    use crate::AsyncFile;
+
+    #[tokio::test]
+    async fn results_come_back_in_request_order() {
+        let data = pattern(100_000);
+        let temp = TempFile::new(&data);
+        let file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+
+        // Out of offset order, overlapping, running into the end and past it.
+        let reqs = vec![
+            (90_000, 4096),
+            (0, 10),
+            (5, 50_000),
+            (99_990, 100),
+            (200_000, 8),
+        ];
+        let results = file.submit_batch(reqs.clone()).await;
+        assert_eq!(results.len(), reqs.len());
+        for ((offset, len), result) in reqs.into_iter().zip(results) {
+            let start = (offset as usize).min(data.len());
+            let end = (start + len).min(data.len());
+            assert_eq!(result.unwrap(), data[start..end]);
+        }
+    }
+
+    // A read that fails takes its own slot and no other.
+    #[cfg(windows)]
+    #[tokio::test]
+    async fn one_failure_leaves_the_rest_alone() {
+        use crate::win::mock::{MockCompletion, MockIo};
+        use crate::AsyncFileError;
+        use std::time::Duration;
+        use windows::Win32::Foundation::ERROR_CRC;
+
+        let temp = TempFile::new(&pattern(1000));
+        let mock = MockIo::new([
+            MockCompletion::Inline(b"first".to_vec()),
+            MockCompletion::Failed(ERROR_CRC.0, Duration::from_millis(5)),
+            MockCompletion::Callback(b"third".to_vec(), Duration::from_millis(10)),
+        ]);
+        let file = AsyncFile::open_for_read(temp.path())
+            .await
+            .unwrap()
+            .with_mock(mock);
+
+        let results = file.submit_batch(vec![(0, 16), (16, 16), (32, 16)]).await;
+        assert_eq!(results[0].as_ref().unwrap(), b"first");
+        assert!(matches!(results[1], Err(AsyncFileError::Read(ERROR_CRC))));
+        assert_eq!(results[2].as_ref().unwrap(), b"third");
+    }
+}
@@ -0,0 +1,96 @@
+use crate::{AsyncFileError, Result};
+use futures_core::Stream;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+
+// Runs f on tokio's blocking pool, for the calls that have no asynchronous
+// form. A panic in f carries on in the caller. Outside a tokio runtime, say
+// under another executor or without the tokio feature, f gets a thread of its
+// own instead; the channel that brings back the result works on any executor.
+pub(crate) async fn blocking<T, F>(f: F) -> Result<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    spawn_blocking(f).await
+}
+
+// blocking() as a future with a name, for futures of the crate's own to hold
+// on to. f is on its way by the time this returns, and runs to the end
+// whether or not the future is ever polled.
+pub(crate) fn spawn_blocking<T, F>(f: F) -> Blocking<T>
+where
+    F: FnOnce() -> T + Send + 'static,
+    T: Send + 'static,
+{
+    #[cfg(feature = "tokio")]
+    if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+        return Blocking::Task(runtime.spawn_blocking(f));
+    }
+
+    let (tx, rx) = async_channel::bounded(1);
+    std::thread::spawn(move || {
+        let _ = tx.send_blocking(panic::catch_unwind(AssertUnwindSafe(f)));
+    });
+    Blocking::Thread(Box::pin(rx))
+}
+
+pub(crate) enum Blocking<T> {
+    #[cfg(feature = "tokio")]
+    Task(tokio::task::JoinHandle<T>),
+    // A receiver has to stay put while it's waited on. Next to the thread
+    // it's the result of, the box costs nothing to speak of.
+    Thread(Pin<Box<async_channel::Receiver<std::thread::Result<T>>>>),
+}
+
+impl<T> Future for Blocking<T> {
+    type Output = Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Poll::Ready(match self.get_mut() {
+            #[cfg(feature = "tokio")]
+            Blocking::Task(task) => match ready!(Pin::new(task).poll(cx)) {
+                Ok(value) => Ok(value),
+                Err(e) if e.is_panic() => panic::resume_unwind(e.into_panic()),
+                Err(_) => Err(AsyncFileError::Cancelled),
+            },
+            Blocking::Thread(result) => match ready!(result.as_mut().poll_next(cx)) {
+                Some(Ok(value)) => Ok(value),
+                Some(Err(panic)) => panic::resume_unwind(panic),
+                None => Err(AsyncFileError::Cancelled),
+            },
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{block_on, pattern, TempFile};
+    use crate::AsyncFile;
+
+    // Opening goes through blocking(), and reading through whichever backend
+    // is built, all without a tokio runtime to lean on.
+    #[test]
+    fn reads_without_a_runtime() {
+        let data = pattern(100_000);
+        let temp = TempFile::new(&data);
+        block_on(async {
+            let mut file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+            let mut out = Vec::new();
+            file.read_to_end(&mut out).await.unwrap();
+            assert_eq!(out, data);
+
+            let mut buf = [0; 10];
+            assert_eq!(file.read_at(&mut buf, 5).await.unwrap(), 10);
+            assert_eq!(buf, data[5..15]);
+        });
+    }
+
+    #[test]
+    fn panic_carries_on_in_the_caller() {
+        let result = std::panic::catch_unwind(|| block_on(super::blocking(|| panic!("boom"))));
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,208 @@
+use crate::{AsyncFile, AsyncFileError, Result};
+use futures_core::Stream;
+use std::io;
+
+// Size of the buffer new() gives an AsyncBufReader.
+const DEFAULT_BUF_SIZE: usize = 1024 * 64;
+
+// Serves small reads out of one large read of the file, like std's BufReader,
+// so e.g. a run of read_exact() calls for a few bytes each costs one ReadFile
+// per buffer rather than one each.
+pub struct AsyncBufReader {
+    file: AsyncFile,
+    // Bytes in buf[pos..filled] have been read from the file but not yet
+    // handed out.
+    buf: Vec<u8>,
+    pos: usize,
+    filled: usize,
+    // Bytes handed out so far.
+    position: u64,
+}
+
+impl AsyncBufReader {
+    pub fn new(file: AsyncFile) -> Self {
+        Self::with_capacity(DEFAULT_BUF_SIZE, file)
+    }
+
+    pub fn with_capacity(capacity: usize, file: AsyncFile) -> Self {
+        assert!(capacity != 0, "capacity must be non-zero");
+        Self {
+            file,
+            buf: vec![0; capacity],
+            pos: 0,
+            filled: 0,
+            position: 0,
+        }
+    }
+
+    // Bytes handed out so far, i.e. how far the reader has got from where the
+    // file's cursor was when it was wrapped. The cursor itself is further on
+    // by whatever is buffered.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    // Up to buf.len() bytes, returning 0 at EOF. Reads at least as big as the
+    // buffer go straight to the file once the buffer is empty.
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if self.pos == self.filled && buf.len() >= self.buf.len() {
+            let n = self.file.read(buf).await?;
+            self.position += n as u64;
+            return Ok(n);
+        }
+
+        let available = self.fill_buf().await?;
+        let n = buf.len().min(available.len());
+        buf[..n].copy_from_slice(&available[..n]);
+        self.consume(n);
+        Ok(n)
+    }
+
+    // Keeps reading until buf is full, failing with Eof if the file ends first.
+    pub async fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.read(&mut buf[filled..]).await? {
+                0 => return Err(AsyncFileError::Eof),
+                n => filled += n,
+            }
+        }
+        Ok(())
+    }
+
+    // Appends bytes to out up to and including the next byte equal to delim,
+    // or up to EOF, returning how many were added. 0 means EOF.
+    pub async fn read_until(&mut self, delim: u8, out: &mut Vec<u8>) -> Result<usize> {
+        let mut total = 0;
+        loop {
+            let available = self.fill_buf().await?;
+            if available.is_empty() {
+                return Ok(total);
+            }
+
+            let (n, found) = match available.iter().position(|&b| b == delim) {
+                Some(i) => (i + 1, true),
+                None => (available.len(), false),
+            };
+            out.extend_from_slice(&available[..n]);
+            self.consume(n);
+            total += n;
+            if found {
+                return Ok(total);
+            }
+        }
+    }
+
+    // Appends the next line to out, newline included, returning how many
+    // bytes were added. 0 means EOF. A line that isn't valid UTF-8 fails with
+    // an InvalidData Io error and leaves out as it was, though the line has
+    // still been read.
+    pub async fn read_line(&mut self, out: &mut String) -> Result<usize> {
+        let mut line = Vec::new();
+        let n = self.read_until(b'\n', &mut line).await?;
+        let line = String::from_utf8(line)
+            .map_err(|e| AsyncFileError::Io(io::Error::new(io::ErrorKind::InvalidData, e)))?;
+        out.push_str(&line);
+        Ok(n)
+    }
+
+    // The remaining lines as a stream, each without its "\n" or "\r\n". A last
+    // line with no newline after it still counts. The stream ends after the
+    // first error.
+    pub fn lines(self) -> impl Stream<Item = Result<String>> {
+        futures_util::stream::unfold(Some(self), |state| async move {
+            let mut reader = state?;
+            let mut line = String::new();
+            match reader.read_line(&mut line).await {
+                Ok(0) => None,
+                Ok(_) => {
+                    if line.ends_with('\n') {
+                        line.pop();
+                        if line.ends_with('\r') {
+                            line.pop();
+                        }
+                    }
+                    Some((Ok(line), Some(reader)))
+                }
+                Err(e) => Some((Err(e), None)),
+            }
+        })
+    }
+
+    // The buffered bytes, refilled from the file first if there are none.
+    // Empty at EOF.
+    async fn fill_buf(&mut self) -> Result<&[u8]> {
+        if self.pos == self.filled {
+            self.filled = self.file.read(&mut self.buf).await?;
+            self.pos = 0;
+        }
+        Ok(&self.buf[self.pos..self.filled])
+    }
+
+    fn consume(&mut self, n: usize) {
+        self.pos += n;
+        self.position += n as u64;
+    }
+
+    // The file back. Anything still buffered is dropped, so its cursor may be
+    // ahead of position().
+    pub fn into_inner(self) -> AsyncFile {
+        self.file
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{pattern, TempFile};
+    use futures_util::StreamExt;
+
+    // A byte at a time out of a 4 KiB buffer is one read of the file per
+    // 4 KiB, plus the one that finds EOF.
+    #[tokio::test]
+    async fn small_reads_are_coalesced() {
+        let data = pattern(100_000);
+        let temp = TempFile::new(&data);
+        let file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+        let mut reader = AsyncBufReader::with_capacity(4096, file);
+
+        let mut out = Vec::new();
+        let mut byte = [0];
+        while reader.read(&mut byte).await.unwrap() == 1 {
+            out.push(byte[0]);
+        }
+        assert_eq!(out, data);
+        assert_eq!(reader.position(), 100_000);
+        assert_eq!(reader.into_inner().stats().reads(), 100_000 / 4096 + 2);
+    }
+
+    #[tokio::test]
+    async fn read_exact_runs_across_refills() {
+        let data = pattern(10_000);
+        let temp = TempFile::new(&data);
+        let file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+        let mut reader = AsyncBufReader::with_capacity(1000, file);
+
+        let mut buf = [0; 3];
+        reader.read_exact(&mut buf).await.unwrap();
+        let mut rest = vec![0; 9_997];
+        reader.read_exact(&mut rest).await.unwrap();
+        assert_eq!([&buf[..], &rest].concat(), data);
+        assert!(matches!(
+            reader.read_exact(&mut buf).await,
+            Err(AsyncFileError::Eof)
+        ));
+    }
+
+    #[tokio::test]
+    async fn lines_split_on_either_ending() {
+        let temp = TempFile::new(b"one\ntwo\r\n\nlast");
+        let file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+        let lines: Vec<_> = AsyncBufReader::with_capacity(3, file)
+            .lines()
+            .map(Result::unwrap)
+            .collect()
+            .await;
+        assert_eq!(lines, ["one", "two", "", "last"]);
+    }
+}
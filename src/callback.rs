@@ -0,0 +1,22 @@
+// The callback a file keeps for read_all_default(), held the same way by
+// every backend. Until one is set, calling it does nothing.
+#[derive(Default)]
+pub(crate) struct ReadCallback(Option<Box<Callback>>);
+
+type Callback = dyn Fn(&[u8]) + Send + Sync;
+
+impl ReadCallback {
+    pub(crate) fn set(&mut self, callback: impl Fn(&[u8]) + Send + Sync + 'static) {
+        self.0 = Some(Box::new(callback));
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.0 = None;
+    }
+
+    pub(crate) fn call(&self, bytes: &[u8]) {
+        if let Some(callback) = &self.0 {
+            callback(bytes);
+        }
+    }
+}
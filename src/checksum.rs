@@ -0,0 +1,116 @@
+use crate::{AsyncFile, AsyncFileError, Result};
+
+// Bytes of the checksum stored after each block, a little-endian u32.
+const CHECKSUM_LEN: usize = 4;
+
+// Reads a file laid out as blocks of block_size bytes, each followed by a
+// checksum of it, and hands back only the blocks whose checksum holds. The
+// last block may be short, with its checksum straight after it. checksum is
+// whatever the format uses, CRC-32 or otherwise, given the block's bytes.
+pub struct ChecksummedBlockReader<'a, F> {
+    file: &'a AsyncFile,
+    block_size: usize,
+    checksum: F,
+}
+
+impl<'a, F: Fn(&[u8]) -> u32> ChecksummedBlockReader<'a, F> {
+    pub fn new(file: &'a AsyncFile, block_size: usize, checksum: F) -> Self {
+        assert!(block_size != 0, "block size must be non-zero");
+        Self {
+            file,
+            block_size,
+            checksum,
+        }
+    }
+
+    // The data of block index, counting from 0, read along with its checksum
+    // in one read_at(). Fails with Eof past the last block, and with
+    // ChecksumMismatch if the data doesn't match what was stored.
+    pub async fn read_block(&self, index: u64) -> Result<Vec<u8>> {
+        let stride = self.block_size + CHECKSUM_LEN;
+        let offset = index
+            .checked_mul(stride as u64)
+            .ok_or(AsyncFileError::Eof)?;
+        let mut buf = vec![0; stride];
+        let n = read_full(self.file, &mut buf, offset).await?;
+        if n <= CHECKSUM_LEN {
+            return Err(AsyncFileError::Eof);
+        }
+
+        let (data, stored) = buf[..n].split_at(n - CHECKSUM_LEN);
+        let stored = u32::from_le_bytes(stored.try_into().unwrap());
+        if (self.checksum)(data) != stored {
+            return Err(AsyncFileError::ChecksumMismatch(index));
+        }
+
+        buf.truncate(n - CHECKSUM_LEN);
+        Ok(buf)
+    }
+}
+
+// read_at() until buf is full or the file ends, returning how much was read.
+async fn read_full(file: &AsyncFile, buf: &mut [u8], offset: u64) -> Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match file
+            .read_at(&mut buf[filled..], offset + filled as u64)
+            .await?
+        {
+            0 => break,
+            n => filled += n,
+        }
+    }
+    Ok(filled)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{pattern, TempFile};
+
+    // CRC-32 as zip and gzip have it, a bit at a time.
+    fn crc32(data: &[u8]) -> u32 {
+        let mut crc = !0u32;
+        for &byte in data {
+            crc ^= byte as u32;
+            for _ in 0..8 {
+                crc = (crc >> 1) ^ (0xedb8_8320 & (crc & 1).wrapping_neg());
+            }
+        }
+        !crc
+    }
+
+    // Three whole 4 KiB blocks and a short one, each with its CRC after it.
+    fn blocks(data: &[u8]) -> Vec<u8> {
+        data.chunks(4096)
+            .flat_map(|block| [block, &crc32(block).to_le_bytes()].concat())
+            .collect()
+    }
+
+    #[test]
+    fn crc32_of_check_string() {
+        assert_eq!(crc32(b"123456789"), 0xcbf4_3926);
+    }
+
+    #[tokio::test]
+    async fn corrupt_block_is_reported_by_index() {
+        let data = pattern(3 * 4096 + 100);
+        let mut stored = blocks(&data);
+        stored[2 * (4096 + 4) + 17] ^= 0x40;
+        let temp = TempFile::new(&stored);
+        let file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+        let reader = ChecksummedBlockReader::new(&file, 4096, crc32);
+
+        assert_eq!(reader.read_block(0).await.unwrap(), data[..4096]);
+        assert_eq!(reader.read_block(1).await.unwrap(), data[4096..8192]);
+        assert!(matches!(
+            reader.read_block(2).await,
+            Err(AsyncFileError::ChecksumMismatch(2))
+        ));
+        assert_eq!(reader.read_block(3).await.unwrap(), data[3 * 4096..]);
+        assert!(matches!(
+            reader.read_block(4).await,
+            Err(AsyncFileError::Eof)
+        ));
+    }
+}
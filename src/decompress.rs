@@ -0,0 +1,116 @@
+use crate::{AsyncFile, AsyncFileError, Result};
+use std::io;
+
+// Size of each read of compressed bytes from the file.
+const INPUT_CHUNK: usize = 1024 * 64;
+
+// One streaming decompressor. With the flate2 feature, flate2's Decompress
+// (raw deflate or zlib) is one and GzipDecoder reads .gz files. Others, zstd's
+// raw Decoder say, take a few lines of adapter, which leaves the choice of
+// codec, and the dependency on it, with the caller.
+pub trait Decoder {
+    // Decodes what it can of input into output.
+    fn decode(&mut self, input: &[u8], output: &mut [u8]) -> io::Result<DecodeStatus>;
+}
+
+// What one Decoder::decode() call got through.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct DecodeStatus {
+    // Bytes of input used up.
+    pub consumed: usize,
+    // Bytes written to the start of output.
+    pub produced: usize,
+    // Whether the end of the compressed stream has been reached.
+    pub finished: bool,
+}
+
+// Wraps an AsyncFile holding a compressed stream and reads it back
+// decompressed, a chunk of compressed bytes at a time, so the whole thing is
+// never held in memory.
+pub struct DecompressingRead<D> {
+    file: AsyncFile,
+    decoder: D,
+    // Compressed bytes in input[pos..filled] are read but not yet decoded.
+    input: Vec<u8>,
+    pos: usize,
+    filled: usize,
+    // The file has returned 0.
+    eof: bool,
+    // The decoder has seen the end of the stream.
+    finished: bool,
+}
+
+impl<D: Decoder> DecompressingRead<D> {
+    pub fn new(file: AsyncFile, decoder: D) -> Self {
+        Self {
+            file,
+            decoder,
+            input: vec![0; INPUT_CHUNK],
+            pos: 0,
+            filled: 0,
+            eof: false,
+            finished: false,
+        }
+    }
+
+    // Up to buf.len() decompressed bytes, returning 0 once the compressed
+    // stream has ended. Reads more from the file whenever the decoder can't
+    // get any further with what it has. A file that runs out before the
+    // stream ends fails with Eof.
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        if buf.is_empty() || self.finished {
+            return Ok(0);
+        }
+
+        loop {
+            if self.pos == self.filled && !self.eof {
+                self.fill().await?;
+            }
+
+            let status = self
+                .decoder
+                .decode(&self.input[self.pos..self.filled], buf)
+                .map_err(AsyncFileError::Io)?;
+            self.pos += status.consumed;
+
+            if status.finished {
+                self.finished = true;
+                return Ok(status.produced);
+            }
+            if status.produced != 0 {
+                return Ok(status.produced);
+            }
+            if status.consumed == 0 {
+                // The decoder needs more than is buffered to make progress.
+                if self.eof {
+                    return Err(AsyncFileError::Eof);
+                }
+                self.fill().await?;
+            }
+        }
+    }
+
+    // Moves any undecoded bytes to the front and reads more after them,
+    // growing the buffer if there's no room left.
+    async fn fill(&mut self) -> Result<()> {
+        self.input.copy_within(self.pos..self.filled, 0);
+        self.filled -= self.pos;
+        self.pos = 0;
+        if self.filled == self.input.len() {
+            self.input.resize(self.input.len() + INPUT_CHUNK, 0);
+        }
+
+        let n = self.file.read(&mut self.input[self.filled..]).await?;
+        if n == 0 {
+            self.eof = true;
+        }
+        self.filled += n;
+        Ok(())
+    }
+
+    // The file back, positioned after whatever compressed bytes have been read
+    // so far, decoded or not.
+    pub fn into_inner(self) -> AsyncFile {
+        self.file
+    }
+}
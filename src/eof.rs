@@ -0,0 +1,48 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// No offset can be this far into a file, so it stands for no EOF found.
+const NOT_FOUND: u64 = u64::MAX;
+
+// Where a read at the cursor last found the end of the file, for is_eof(),
+// held the same way by every backend. It's atomic because read_at() only gets
+// &self and still has to be able to forget it.
+pub(crate) struct EofMark(AtomicU64);
+
+impl Default for EofMark {
+    fn default() -> Self {
+        Self(AtomicU64::new(NOT_FOUND))
+    }
+}
+
+impl EofMark {
+    pub(crate) fn set(&self, offset: u64) {
+        self.0.store(offset, Ordering::Relaxed);
+    }
+
+    pub(crate) fn clear(&self) {
+        self.0.store(NOT_FOUND, Ordering::Relaxed);
+    }
+
+    pub(crate) fn is_at(&self, offset: u64) -> bool {
+        self.0.load(Ordering::Relaxed) == offset
+    }
+
+    // A read of len bytes at the cursor came back with n. None at all is EOF,
+    // while any data means the file has more than was last found.
+    pub(crate) fn cursor_read(&self, offset: u64, len: usize, n: usize) {
+        if n == 0 && len != 0 {
+            self.set(offset);
+        } else if n != 0 {
+            self.clear();
+        }
+    }
+
+    // A read anywhere, read_at() included, came back with n bytes. Data from
+    // before the end that was found means the reader has gone back over the
+    // file, so it no longer counts as being at EOF.
+    pub(crate) fn read_returned(&self, offset: u64, n: usize) {
+        if n != 0 && offset < self.0.load(Ordering::Relaxed) {
+            self.clear();
+        }
+    }
+}
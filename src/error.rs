@@ -0,0 +1,336 @@
+use std::fmt;
+use std::io;
+#[cfg(windows)]
+use windows::Win32::Foundation::{
+    RtlNtStatusToDosError, ERROR_ACCESS_DENIED, ERROR_ALREADY_EXISTS, ERROR_BAD_NETPATH,
+    ERROR_BAD_NET_NAME, ERROR_BROKEN_PIPE, ERROR_DISK_FULL, ERROR_FILE_EXISTS,
+    ERROR_FILE_NOT_FOUND, ERROR_HANDLE_DISK_FULL, ERROR_HANDLE_EOF, ERROR_INVALID_DRIVE,
+    ERROR_INVALID_PARAMETER, ERROR_LOCK_VIOLATION, ERROR_NOT_ENOUGH_MEMORY, ERROR_NOT_SUPPORTED,
+    ERROR_NO_DATA, ERROR_OPERATION_ABORTED, ERROR_OUTOFMEMORY, ERROR_PATH_NOT_FOUND,
+    ERROR_PIPE_BUSY, ERROR_SEM_TIMEOUT, ERROR_SHARING_VIOLATION, ERROR_TIMEOUT,
+    ERROR_WRITE_PROTECT, NTSTATUS, WIN32_ERROR,
+};
+
+pub type Result<T> = std::result::Result<T, AsyncFileError>;
+
+// Everything the crate's API can fail with.
+#[derive(Debug)]
+pub enum AsyncFileError {
+    // Opening or creating the file failed.
+    Open(io::Error),
+    // A read was refused or completed with an error.
+    #[cfg(windows)]
+    Read(WIN32_ERROR),
+    // A write was refused or completed with an error.
+    #[cfg(windows)]
+    Write(WIN32_ERROR),
+    // The operation was cancelled before it completed.
+    Cancelled,
+    // The operation didn't complete in the time it was given.
+    TimedOut,
+    // The file ended before the request could be satisfied.
+    Eof,
+    // A write ran out of room on the disk.
+    DiskFull,
+    // A write to a pipe found the reading end closed.
+    PipeClosed,
+    // The file held more than the given number of bytes, the most a
+    // read_to_end_limited() would take.
+    FileTooLarge(usize),
+    // The operation couldn't complete straight away and wasn't asked to wait.
+    WouldBlock,
+    // Any other Win32 call failed.
+    #[cfg(windows)]
+    Win32(WIN32_ERROR),
+    // A handle passed in wasn't opened with FILE_FLAG_OVERLAPPED.
+    NotOverlapped,
+    // A handle passed in is already bound to the thread pool or to a
+    // completion port, and a handle can only ever be bound once.
+    DoubleBind,
+    // Opening a disk or volume directly was refused, as only an elevated
+    // process may.
+    ElevationRequired,
+    // A std::io call made on the file's behalf failed.
+    Io(io::Error),
+    // An unbuffered read or write's buffer, length or offset wasn't a multiple
+    // of the sector size, which is given.
+    Unaligned(u32),
+    // Data read back after a write_verified() differed from what was written,
+    // starting at the given offset.
+    VerificationFailed(u64),
+    // The block with the given index didn't match the checksum stored with
+    // it, see ChecksummedBlockReader.
+    ChecksumMismatch(u64),
+    // Something went wrong with the file at the given path, for helpers such
+    // as read_all() that work through several.
+    AtPath(String, Box<AsyncFileError>),
+}
+
+#[cfg(windows)]
+impl AsyncFileError {
+    // A failed read, noting when it was because the I/O was cancelled.
+    pub(crate) fn read(code: WIN32_ERROR) -> Self {
+        if code == ERROR_OPERATION_ABORTED {
+            Self::Cancelled
+        } else {
+            Self::Read(code)
+        }
+    }
+
+    // A failed write, noting when it was because the I/O was cancelled, the
+    // disk is full or the pipe written to has no reader any more. A pipe
+    // whose reader has gone fails with ERROR_NO_DATA, and
+    // ERROR_BROKEN_PIPE once it has been closed.
+    pub(crate) fn write(code: WIN32_ERROR) -> Self {
+        match code {
+            ERROR_OPERATION_ABORTED => Self::Cancelled,
+            ERROR_DISK_FULL | ERROR_HANDLE_DISK_FULL => Self::DiskFull,
+            ERROR_BROKEN_PIPE | ERROR_NO_DATA => Self::PipeClosed,
+            _ => Self::Write(code),
+        }
+    }
+}
+
+// The completion callback reports some failures as an NTSTATUS rather than a
+// Win32 error code, so those are translated before going any further.
+#[cfg(windows)]
+pub(crate) fn completion_code(err: u32) -> WIN32_ERROR {
+    if err & 0xC000_0000 == 0xC000_0000 {
+        WIN32_ERROR(unsafe { RtlNtStatusToDosError(NTSTATUS(err as i32)) })
+    } else {
+        WIN32_ERROR(err)
+    }
+}
+
+// HRESULTs that don't wrap a Win32 error are kept as they are.
+#[cfg(windows)]
+pub(crate) fn win32_code(error: &windows::core::Error) -> WIN32_ERROR {
+    WIN32_ERROR::from_error(error).unwrap_or(WIN32_ERROR(error.code().0 as u32))
+}
+
+// std knows the system message for a raw Win32 code.
+#[cfg(windows)]
+fn os_error(code: WIN32_ERROR) -> io::Error {
+    io::Error::from_raw_os_error(code.0 as i32)
+}
+
+// The ErrorKind a Win32 code comes out as. std decodes most codes itself, but
+// misses some callers match on (sharing violations, EOF) and files
+// ERROR_OPERATION_ABORTED under TimedOut, so the common ones are settled here.
+#[cfg(windows)]
+fn win32_kind(code: WIN32_ERROR) -> io::ErrorKind {
+    match code {
+        ERROR_FILE_NOT_FOUND | ERROR_PATH_NOT_FOUND | ERROR_INVALID_DRIVE | ERROR_BAD_NETPATH
+        | ERROR_BAD_NET_NAME => io::ErrorKind::NotFound,
+        ERROR_ACCESS_DENIED | ERROR_WRITE_PROTECT => io::ErrorKind::PermissionDenied,
+        ERROR_SHARING_VIOLATION | ERROR_PIPE_BUSY => io::ErrorKind::ResourceBusy,
+        ERROR_LOCK_VIOLATION => io::ErrorKind::WouldBlock,
+        ERROR_FILE_EXISTS | ERROR_ALREADY_EXISTS => io::ErrorKind::AlreadyExists,
+        ERROR_HANDLE_EOF => io::ErrorKind::UnexpectedEof,
+        ERROR_OPERATION_ABORTED => io::ErrorKind::Other,
+        ERROR_DISK_FULL | ERROR_HANDLE_DISK_FULL => io::ErrorKind::StorageFull,
+        ERROR_BROKEN_PIPE | ERROR_NO_DATA => io::ErrorKind::BrokenPipe,
+        ERROR_INVALID_PARAMETER => io::ErrorKind::InvalidInput,
+        ERROR_NOT_ENOUGH_MEMORY | ERROR_OUTOFMEMORY => io::ErrorKind::OutOfMemory,
+        ERROR_SEM_TIMEOUT | ERROR_TIMEOUT => io::ErrorKind::TimedOut,
+        ERROR_NOT_SUPPORTED => io::ErrorKind::Unsupported,
+        _ => os_error(code).kind(),
+    }
+}
+
+// An io::Error for a Win32 code with win32_kind()'s ErrorKind. Where that
+// differs from std's own the raw error is wrapped rather than lost, so it can
+// still be got at through get_ref().
+#[cfg(windows)]
+fn win32_io_error(code: WIN32_ERROR) -> io::Error {
+    let error = os_error(code);
+    let kind = win32_kind(code);
+    if error.kind() == kind {
+        error
+    } else {
+        io::Error::new(kind, error)
+    }
+}
+
+// What seek() fails with for a position before the start of the file.
+pub(crate) fn invalid_seek() -> AsyncFileError {
+    AsyncFileError::Io(io::Error::new(
+        io::ErrorKind::InvalidInput,
+        "invalid seek to a negative or overflowing position",
+    ))
+}
+
+#[cfg(unix)]
+impl AsyncFileError {
+    // A failed write, picking out a full disk and a pipe with no reader.
+    pub(crate) fn write(error: io::Error) -> Self {
+        match error.kind() {
+            io::ErrorKind::StorageFull => Self::DiskFull,
+            io::ErrorKind::BrokenPipe => Self::PipeClosed,
+            _ => Self::Io(error),
+        }
+    }
+}
+
+impl AsyncFileError {
+    // The ErrorKind this comes out as when converted to an io::Error, so
+    // callers can match on NotFound and the like without converting.
+    pub fn kind(&self) -> io::ErrorKind {
+        match self {
+            Self::Open(e) | Self::Io(e) => e.kind(),
+            #[cfg(windows)]
+            Self::Read(code) | Self::Write(code) | Self::Win32(code) => win32_kind(*code),
+            Self::Cancelled => io::ErrorKind::Other,
+            Self::TimedOut => io::ErrorKind::TimedOut,
+            Self::Eof => io::ErrorKind::UnexpectedEof,
+            Self::DiskFull => io::ErrorKind::StorageFull,
+            Self::PipeClosed => io::ErrorKind::BrokenPipe,
+            Self::FileTooLarge(_) => io::ErrorKind::FileTooLarge,
+            Self::WouldBlock => io::ErrorKind::WouldBlock,
+            Self::ElevationRequired => io::ErrorKind::PermissionDenied,
+            Self::NotOverlapped | Self::DoubleBind | Self::Unaligned(_) => {
+                io::ErrorKind::InvalidInput
+            }
+            Self::VerificationFailed(_) | Self::ChecksumMismatch(_) => io::ErrorKind::InvalidData,
+            Self::AtPath(_, e) => e.kind(),
+        }
+    }
+}
+
+impl fmt::Display for AsyncFileError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Open(e) => write!(f, "failed to open file: {e}"),
+            #[cfg(windows)]
+            Self::Read(code) => write!(f, "read failed: {}", os_error(*code)),
+            #[cfg(windows)]
+            Self::Write(code) => write!(f, "write failed: {}", os_error(*code)),
+            Self::Cancelled => f.write_str("operation was cancelled"),
+            Self::TimedOut => f.write_str("operation timed out"),
+            Self::Eof => f.write_str("unexpected end of file"),
+            Self::DiskFull => f.write_str("not enough space on the disk"),
+            Self::PipeClosed => f.write_str("the pipe's reading end has been closed"),
+            Self::FileTooLarge(max) => write!(f, "file is larger than the {max} byte limit"),
+            Self::WouldBlock => f.write_str("operation would block"),
+            #[cfg(windows)]
+            Self::Win32(code) => write!(f, "{}", os_error(*code)),
+            Self::NotOverlapped => f.write_str("handle was not opened with FILE_FLAG_OVERLAPPED"),
+            Self::DoubleBind => {
+                f.write_str("handle is already bound to the thread pool or a completion port")
+            }
+            Self::ElevationRequired => f.write_str(
+                "opening a disk or volume directly needs administrator rights; run elevated",
+            ),
+            Self::Io(e) => write!(f, "{e}"),
+            Self::Unaligned(sector_size) => write!(
+                f,
+                "unbuffered I/O must be aligned to the {sector_size} byte sector size"
+            ),
+            Self::VerificationFailed(offset) => {
+                write!(
+                    f,
+                    "data read back at offset {offset} differs from what was written"
+                )
+            }
+            Self::ChecksumMismatch(index) => {
+                write!(f, "block {index} doesn't match its stored checksum")
+            }
+            Self::AtPath(path, e) => write!(f, "{path}: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for AsyncFileError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Open(e) | Self::Io(e) => Some(e),
+            Self::AtPath(_, e) => Some(e.as_ref()),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(windows)]
+impl From<windows::core::Error> for AsyncFileError {
+    fn from(error: windows::core::Error) -> Self {
+        Self::Win32(win32_code(&error))
+    }
+}
+
+impl From<AsyncFileError> for io::Error {
+    fn from(error: AsyncFileError) -> Self {
+        match error {
+            AsyncFileError::Open(e) | AsyncFileError::Io(e) => e,
+            #[cfg(windows)]
+            AsyncFileError::Read(code)
+            | AsyncFileError::Write(code)
+            | AsyncFileError::Win32(code) => win32_io_error(code),
+            #[cfg(windows)]
+            AsyncFileError::Cancelled => win32_io_error(ERROR_OPERATION_ABORTED),
+            #[cfg(not(windows))]
+            AsyncFileError::Cancelled => io::Error::other(error),
+            AsyncFileError::TimedOut => io::Error::from(io::ErrorKind::TimedOut),
+            AsyncFileError::Eof => io::Error::from(io::ErrorKind::UnexpectedEof),
+            AsyncFileError::DiskFull => io::Error::from(io::ErrorKind::StorageFull),
+            AsyncFileError::PipeClosed => io::Error::from(io::ErrorKind::BrokenPipe),
+            AsyncFileError::FileTooLarge(_) => io::Error::new(io::ErrorKind::FileTooLarge, error),
+            AsyncFileError::WouldBlock => io::Error::from(io::ErrorKind::WouldBlock),
+            AsyncFileError::ElevationRequired => {
+                io::Error::new(io::ErrorKind::PermissionDenied, error)
+            }
+            AsyncFileError::NotOverlapped
+            | AsyncFileError::DoubleBind
+            | AsyncFileError::Unaligned(_) => io::Error::new(io::ErrorKind::InvalidInput, error),
+            AsyncFileError::VerificationFailed(_) | AsyncFileError::ChecksumMismatch(_) => {
+                io::Error::new(io::ErrorKind::InvalidData, error)
+            }
+            AsyncFileError::AtPath(_, ref e) => io::Error::new(e.kind(), error),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::AsyncFile;
+
+    #[tokio::test]
+    async fn missing_file_is_not_found() {
+        let path = std::env::temp_dir().join("async_file_test_does_not_exist");
+        let Err(error) = AsyncFile::open_for_read(path.to_str().unwrap()).await else {
+            panic!("opened a file that isn't there");
+        };
+        assert_eq!(error.kind(), io::ErrorKind::NotFound);
+        assert_eq!(io::Error::from(error).kind(), io::ErrorKind::NotFound);
+    }
+
+    // std has no kind of its own for these, and the raw code has to survive
+    // being given one.
+    #[cfg(windows)]
+    #[test]
+    fn win32_codes_keep_their_code() {
+        for (code, kind) in [
+            (ERROR_SHARING_VIOLATION, io::ErrorKind::ResourceBusy),
+            (ERROR_HANDLE_EOF, io::ErrorKind::UnexpectedEof),
+            (ERROR_ACCESS_DENIED, io::ErrorKind::PermissionDenied),
+        ] {
+            let error = io::Error::from(AsyncFileError::Read(code));
+            assert_eq!(error.kind(), kind);
+            let raw = error.raw_os_error().or_else(|| {
+                let inner = error.get_ref()?.downcast_ref::<io::Error>()?;
+                inner.raw_os_error()
+            });
+            assert_eq!(raw, Some(code.0 as i32));
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn full_disk_is_its_own_error() {
+        for code in [ERROR_DISK_FULL, ERROR_HANDLE_DISK_FULL] {
+            let error = AsyncFileError::write(code);
+            assert!(matches!(error, AsyncFileError::DiskFull));
+            assert_eq!(error.kind(), io::ErrorKind::StorageFull);
+        }
+    }
+}
@@ -0,0 +1,302 @@
+use crate::blocking::{blocking, spawn_blocking, Blocking};
+use crate::callback::ReadCallback;
+use crate::eof::EofMark;
+use crate::error::invalid_seek;
+use crate::stats::IoStats;
+use crate::{AsyncFileError, FileStats, Result};
+use std::fs::File;
+use std::future::Future;
+use std::io::{self, SeekFrom};
+use std::os::unix::fs::FileExt;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll};
+
+// Asynchronous file I/O anywhere else, as blocking calls on tokio's blocking
+// pool, or on threads of their own without one (see blocking()).
+// tokio::fs::File only has a cursor, so this does the same thing it does
+// underneath but with pread/pwrite, which read_at() needs. Each call
+// goes through a buffer of its own that's copied to or from the caller's.
+pub struct AsyncFile {
+    file: Arc<File>,
+    // Cursor shared by read() and write().
+    offset: u64,
+    // Where the cursor was when a read there last found EOF.
+    eof_at: EofMark,
+    stats: IoStats,
+    // Run by read_all_default() for each completed read.
+    read_callback: ReadCallback,
+}
+
+impl AsyncFile {
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", err))]
+    pub async fn open_for_read(path: &str) -> Result<Self> {
+        let path = path.to_owned();
+        let file = blocking(move || File::open(path))
+            .await?
+            .map_err(AsyncFileError::Open)?;
+        Ok(Self::new(file))
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", err))]
+    pub async fn open_for_write(path: &str) -> Result<Self> {
+        let path = path.to_owned();
+        let file = blocking(move || {
+            std::fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)
+        })
+        .await?
+        .map_err(AsyncFileError::Open)?;
+        Ok(Self::new(file))
+    }
+
+    // Opens for reading and writing, creating the file if need be. Every
+    // read_at() and write_at() is a pread or pwrite of its own, so reads and
+    // writes at different offsets can be in flight together on the one file.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", err))]
+    pub async fn open_for_read_write(path: &str) -> Result<Self> {
+        let path = path.to_owned();
+        let file = blocking(move || {
+            std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)
+        })
+        .await?
+        .map_err(AsyncFileError::Open)?;
+        Ok(Self::new(file))
+    }
+
+    fn new(file: File) -> Self {
+        Self {
+            file: Arc::new(file),
+            offset: 0,
+            eof_at: EofMark::default(),
+            stats: IoStats::default(),
+            read_callback: ReadCallback::default(),
+        }
+    }
+
+    // Reads the file from offset 0 to the end, whatever the cursor, one pread
+    // on the blocking pool at a time into buf, and returns the total.
+    // callback is given the bytes of each read on the task awaiting this,
+    // before the next pread is sent off. An empty buf fails with InvalidInput.
+    pub async fn read_all<F>(&self, buf: &mut [u8], mut callback: F) -> Result<usize>
+    where
+        F: FnMut(&[u8]),
+    {
+        // Reads of nothing would never reach EOF.
+        if buf.is_empty() {
+            return Err(AsyncFileError::Io(io::ErrorKind::InvalidInput.into()));
+        }
+        let mut offset = 0;
+        loop {
+            let n = self.read_at(buf, offset).await?;
+            if n == 0 {
+                return Ok(offset as usize);
+            }
+            callback(&buf[..n]);
+            offset += n as u64;
+        }
+    }
+
+    // A callback for read_all_default() to run once per completed read, given
+    // just the bytes that read brought in, as read_all() runs the one it's
+    // handed. It replaces any set before, and a read_all() call's own
+    // callback still stands in for it there.
+    pub fn set_read_callback(&mut self, callback: impl Fn(&[u8]) + Send + Sync + 'static) {
+        self.read_callback.set(callback);
+    }
+
+    pub fn clear_read_callback(&mut self) {
+        self.read_callback.clear();
+    }
+
+    // read_all() with the callback from set_read_callback(), or with none if
+    // it hasn't been set.
+    pub async fn read_all_default(&self, buf: &mut [u8]) -> Result<usize> {
+        self.read_all(buf, |bytes| self.read_callback.call(bytes))
+            .await
+    }
+
+    // Single read of up to buf.len() bytes at the cursor, which then advances
+    // past them. Returns 0 at EOF.
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = self.read_at(buf, self.offset).await?;
+        self.eof_at.cursor_read(self.offset, buf.len(), n);
+        self.offset += n as u64;
+        Ok(n)
+    }
+
+    // True once a pread at the cursor, through read(), has come back with 0,
+    // until the cursor moves: a seek or a write clears it, as does a later
+    // read() that finds the file has grown. So does a read_at() that gets
+    // data from before where EOF was found.
+    pub fn is_eof(&self) -> bool {
+        self.eof_at.is_at(self.offset)
+    }
+
+    // read() at the cursor, a pread each, until buf is full. If the file ends
+    // first it fails with Eof, with the cursor past whatever was read.
+    pub async fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.read(&mut buf[filled..]).await? {
+                0 => return Err(AsyncFileError::Eof),
+                n => filled += n,
+            }
+        }
+        Ok(())
+    }
+
+    // Bytes between the cursor and the end of the file, if its size can be
+    // had, so read_to_end() can make room for them up front.
+    pub(crate) async fn remaining_len(&self) -> Option<u64> {
+        let len = self.len().await.ok()?;
+        Some(len.saturating_sub(self.offset))
+    }
+
+    // Single read of up to buf.len() bytes at offset, returning 0 at EOF. It
+    // leaves the cursor alone, so any number can be in flight at once.
+    // The blocking call reads into a buffer of its own that's copied into buf
+    // at the end, so dropping the future early leaves buf alone, though the
+    // read itself runs to completion on the pool.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, buf), fields(len = buf.len()), ret, err)
+    )]
+    pub async fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        self.read_scoped(buf, offset).await
+    }
+
+    // read_at() as a ReadOp, a future with a name that can be kept in a struct
+    // or passed around before it's awaited.
+    pub fn read_scoped<'a>(&'a self, buf: &'a mut [u8], offset: u64) -> ReadOp<'a> {
+        ReadOp {
+            file: self,
+            buf,
+            offset,
+            read: None,
+        }
+    }
+
+    // Totals of the reads and writes done for this file so far.
+    pub fn stats(&self) -> FileStats {
+        self.stats.snapshot()
+    }
+
+    // Writes all of buf at the current write position, which then advances past it.
+    pub async fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let written = self.write_at(buf, self.offset).await?;
+        self.offset += written as u64;
+        Ok(written)
+    }
+
+    // Writes all of buf at offset. The cursor is left alone, so writes to
+    // disjoint regions can be in flight at once.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, buf), fields(len = buf.len()), ret, err)
+    )]
+    pub async fn write_at(&self, buf: &[u8], offset: u64) -> Result<usize> {
+        let file = self.file.clone();
+        let bytes = buf.to_vec();
+        self.stats.write_issued();
+        let result = blocking(move || file.write_all_at(&bytes, offset))
+            .await
+            .and_then(|r| r.map_err(AsyncFileError::write))
+            .map(|()| buf.len());
+        self.stats.record_write(&result);
+        result
+    }
+
+    // Moves the cursor that read() and write() use, returning where it ends
+    // up. Seeking from the end asks for the file's size first. A position
+    // before the start of the file fails with InvalidInput.
+    pub async fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let offset = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => self.offset.checked_add_signed(delta),
+            SeekFrom::End(delta) => self.len().await?.checked_add_signed(delta),
+        };
+        self.offset = offset.ok_or_else(invalid_seek)?;
+        Ok(self.offset)
+    }
+
+    async fn len(&self) -> Result<u64> {
+        let file = self.file.clone();
+        let len = blocking(move || file.metadata())
+            .await?
+            .map_err(AsyncFileError::Io)?
+            .len();
+        Ok(len)
+    }
+
+    // Waits until everything written so far has reached the disk.
+    pub async fn flush(&self) -> Result<()> {
+        let file = self.file.clone();
+        blocking(move || file.sync_all())
+            .await?
+            .map_err(AsyncFileError::write)
+    }
+
+    // Grows or shrinks the file to size bytes. Growing fills with zeros and
+    // shrinking discards the tail.
+    pub async fn set_len(&self, size: u64) -> Result<()> {
+        let file = self.file.clone();
+        blocking(move || file.set_len(size))
+            .await?
+            .map_err(AsyncFileError::write)
+    }
+
+    pub fn close(self) -> Result<()> {
+        drop(self);
+        Ok(())
+    }
+}
+
+// A read_at() of buf from read_scoped(), issued at the first poll. The pread
+// fills a buffer of its own, which is only copied into buf once it's done, so
+// dropping this early has nothing to cancel or wait for: the pread carries on
+// by itself on the pool and its bytes go nowhere.
+pub struct ReadOp<'a> {
+    file: &'a AsyncFile,
+    buf: &'a mut [u8],
+    offset: u64,
+    read: Option<Blocking<io::Result<Vec<u8>>>>,
+}
+
+impl Future for ReadOp<'_> {
+    type Output = Result<usize>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        if this.buf.is_empty() {
+            return Poll::Ready(Ok(0));
+        }
+
+        let read = this.read.get_or_insert_with(|| {
+            let file = this.file.file.clone();
+            let (len, offset) = (this.buf.len(), this.offset);
+            this.file.stats.read_issued();
+            spawn_blocking(move || {
+                let mut bytes = vec![0; len];
+                let n = file.read_at(&mut bytes, offset)?;
+                bytes.truncate(n);
+                Ok(bytes)
+            })
+        });
+        let result = ready!(Pin::new(read).poll(cx)).and_then(|r| r.map_err(AsyncFileError::Io));
+        this.file.stats.record_read(&result.as_ref().map(Vec::len));
+        let bytes = result?;
+
+        this.buf[..bytes.len()].copy_from_slice(&bytes);
+        this.file.eof_at.read_returned(this.offset, bytes.len());
+        Poll::Ready(Ok(bytes.len()))
+    }
+}
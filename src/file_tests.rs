@@ -0,0 +1,348 @@
+// Tests of the AsyncFile API as every backend presents it, so they run
+// against whichever one is built.
+
+use crate::test_util::{block_on, pattern, TempFile};
+use crate::{AsyncFile, AsyncFileError};
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use futures_util::FutureExt;
+use std::io::{self, SeekFrom};
+
+async fn contents(path: &str) -> Vec<u8> {
+    let mut file = AsyncFile::open_for_read(path).await.unwrap();
+    let mut data = Vec::new();
+    file.read_to_end(&mut data).await.unwrap();
+    data
+}
+
+// In two writes, the second picking up where the first left off.
+#[tokio::test]
+async fn write_then_read_back() {
+    let temp = TempFile::new(&[]);
+    let data = pattern(64 * 1024);
+    let mut file = AsyncFile::open_for_write(temp.path()).await.unwrap();
+    let (first, second) = data.split_at(20_000);
+    assert_eq!(file.write(first).await.unwrap(), first.len());
+    assert_eq!(file.write(second).await.unwrap(), second.len());
+    file.close().unwrap();
+
+    assert_eq!(contents(temp.path()).await, data);
+}
+
+#[tokio::test]
+async fn read_exact_fills_from_a_file_just_big_enough() {
+    let data = pattern(10_000);
+    let temp = TempFile::new(&data);
+    let mut file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+    let mut buf = vec![0; 10_000];
+    file.read_exact(&mut buf).await.unwrap();
+    assert_eq!(buf, data);
+}
+
+#[tokio::test]
+async fn read_exact_one_short_is_eof() {
+    let temp = TempFile::new(&pattern(9_999));
+    let mut file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+    let mut buf = vec![0; 10_000];
+    let error = file.read_exact(&mut buf).await.unwrap_err();
+    assert!(matches!(error, AsyncFileError::Eof));
+    assert_eq!(error.kind(), io::ErrorKind::UnexpectedEof);
+}
+
+// All submitted before any is awaited, over overlapping ranges, and gathered
+// in whatever order they complete.
+#[tokio::test]
+async fn concurrent_reads_each_get_their_own_bytes() {
+    let data = pattern(1 << 20);
+    let temp = TempFile::new(&data);
+    let file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+
+    let mut reads: FuturesUnordered<_> = (0..16u64)
+        .map(|i| {
+            let file = &file;
+            async move {
+                let offset = i * 40_000;
+                let mut buf = vec![0; 64 * 1024];
+                let n = file.read_at(&mut buf, offset).await.unwrap();
+                buf.truncate(n);
+                (offset as usize, buf)
+            }
+        })
+        .collect();
+
+    let mut seen = 0;
+    while let Some((offset, buf)) = reads.next().await {
+        assert_eq!(buf, data[offset..offset + 64 * 1024]);
+        seen += 1;
+    }
+    assert_eq!(seen, 16);
+}
+
+// Both in flight at once, through the one file.
+#[tokio::test]
+async fn positioned_writes_land_where_they_were_put() {
+    let temp = TempFile::new(&[]);
+    let file = AsyncFile::open_for_read_write(temp.path()).await.unwrap();
+    let first = vec![1; 4096];
+    let second = vec![2; 4096];
+
+    let (a, b) =
+        futures_util::future::join(file.write_at(&first, 0), file.write_at(&second, 8192)).await;
+    assert_eq!((a.unwrap(), b.unwrap()), (4096, 4096));
+
+    let data = contents(temp.path()).await;
+    assert_eq!(data.len(), 8192 + 4096);
+    assert_eq!(data[..4096], first);
+    assert!(data[4096..8192].iter().all(|&b| b == 0));
+    assert_eq!(data[8192..], second);
+}
+
+#[tokio::test]
+async fn flushed_write_is_there_on_reopening() {
+    let temp = TempFile::new(&[]);
+    let data = pattern(50_000);
+    let mut file = AsyncFile::open_for_write(temp.path()).await.unwrap();
+    file.write(&data).await.unwrap();
+    file.flush().await.unwrap();
+    drop(file);
+
+    assert_eq!(contents(temp.path()).await, data);
+}
+
+#[tokio::test]
+async fn set_len_grows_with_zeros_and_shrinks() {
+    let temp = TempFile::new(b"start");
+    let file = AsyncFile::open_for_write(temp.path()).await.unwrap();
+
+    file.set_len(1 << 20).await.unwrap();
+    assert_eq!(std::fs::metadata(temp.path()).unwrap().len(), 1 << 20);
+    let data = contents(temp.path()).await;
+    assert_eq!(&data[..5], b"start");
+    assert!(data[5..].iter().all(|&b| b == 0));
+
+    file.set_len(3).await.unwrap();
+    assert_eq!(contents(temp.path()).await, b"sta");
+}
+
+#[tokio::test]
+async fn read_follows_the_seek_cursor() {
+    let data = pattern(1000);
+    let temp = TempFile::new(&data);
+    let mut file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+
+    assert_eq!(file.seek(SeekFrom::Start(100)).await.unwrap(), 100);
+    let mut buf = [0; 50];
+    file.read_exact(&mut buf).await.unwrap();
+    assert_eq!(buf, data[100..150]);
+
+    assert_eq!(file.seek(SeekFrom::Current(-20)).await.unwrap(), 130);
+    file.read_exact(&mut buf).await.unwrap();
+    assert_eq!(buf, data[130..180]);
+
+    assert_eq!(file.seek(SeekFrom::End(-50)).await.unwrap(), 950);
+    file.read_exact(&mut buf).await.unwrap();
+    assert_eq!(buf, data[950..]);
+
+    let before_start = file.seek(SeekFrom::Current(-2000)).await.unwrap_err();
+    assert_eq!(before_start.kind(), io::ErrorKind::InvalidInput);
+}
+
+// Counted whether or not they succeed, with EOF an empty success.
+#[tokio::test]
+async fn stats_count_what_was_done() {
+    let temp = TempFile::new(&pattern(10_000));
+    let mut file = AsyncFile::open_for_read_write(temp.path()).await.unwrap();
+    let mut buf = [0; 1000];
+    for i in 0..5 {
+        file.read_at(&mut buf, i * 3000).await.unwrap();
+    }
+    file.write_at(b"written", 0).await.unwrap();
+    file.seek(SeekFrom::End(0)).await.unwrap();
+    assert_eq!(file.read(&mut buf).await.unwrap(), 0);
+
+    let stats = file.stats();
+    assert_eq!(stats.reads(), 6);
+    assert_eq!(stats.writes(), 1);
+    assert_eq!(stats.completions(), 7);
+    assert_eq!(stats.errors(), 0);
+    assert_eq!(stats.bytes_read(), 4000);
+    assert_eq!(stats.bytes_written(), 7);
+}
+
+#[tokio::test]
+async fn is_eof_follows_the_cursor() {
+    let temp = TempFile::new(&pattern(100));
+    let mut file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+    let mut buf = [0; 64];
+    assert!(!file.is_eof());
+
+    while file.read(&mut buf).await.unwrap() != 0 {
+        assert!(!file.is_eof());
+    }
+    assert!(file.is_eof());
+
+    // A read_at() past the end finds nothing, which changes nothing.
+    assert_eq!(file.read_at(&mut buf, 100).await.unwrap(), 0);
+    assert!(file.is_eof());
+
+    file.seek(SeekFrom::Start(50)).await.unwrap();
+    assert!(!file.is_eof());
+    assert_eq!(file.read(&mut buf).await.unwrap(), 50);
+    assert!(!file.is_eof());
+    assert_eq!(file.read(&mut buf).await.unwrap(), 0);
+    assert!(file.is_eof());
+}
+
+// Going back over the file with read_at() means the cursor's EOF no longer
+// says where the reader is, though the next read() there finds it again.
+#[tokio::test]
+async fn read_at_before_the_end_clears_is_eof() {
+    let temp = TempFile::new(&pattern(100));
+    let mut file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+    let mut buf = [0; 128];
+    assert_eq!(file.read(&mut buf).await.unwrap(), 100);
+    assert_eq!(file.read(&mut buf).await.unwrap(), 0);
+    assert!(file.is_eof());
+
+    assert_eq!(file.read_at(&mut buf, 10).await.unwrap(), 90);
+    assert!(!file.is_eof());
+    assert_eq!(file.read(&mut buf).await.unwrap(), 0);
+    assert!(file.is_eof());
+}
+
+#[tokio::test]
+async fn reads_at_or_past_the_end_are_zero() {
+    let temp = TempFile::new(&pattern(5000));
+    let mut file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+    let mut buf = [0; 64];
+    for offset in [5000, 6000, 1 << 33] {
+        assert_eq!(file.read_at(&mut buf, offset).await.unwrap(), 0);
+    }
+
+    // And through the cursor, seeked to past the end.
+    file.seek(SeekFrom::Start(6000)).await.unwrap();
+    assert_eq!(file.read(&mut buf).await.unwrap(), 0);
+    assert!(file.is_eof());
+}
+
+// Nothing is issued for an empty buffer, so there's no completion for the read
+// to misread. read_all() can only refuse one, as it would never find EOF.
+#[tokio::test]
+async fn empty_reads_come_back_at_once() {
+    let temp = TempFile::new(b"data");
+    let mut file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+    assert_eq!(file.read(&mut []).await.unwrap(), 0);
+    assert_eq!(file.read_at(&mut [], 2).await.unwrap(), 0);
+    file.read_exact(&mut []).await.unwrap();
+    assert!(!file.is_eof());
+    assert_eq!(file.stats().reads(), 0);
+    assert!(matches!(
+        file.read_all(&mut [], |_| {}).await,
+        Err(AsyncFileError::Io(e)) if e.kind() == io::ErrorKind::InvalidInput
+    ));
+}
+
+// Only Windows has vectored reads so far.
+#[cfg(windows)]
+#[tokio::test]
+async fn empty_vectored_reads_come_back_at_once() {
+    use std::io::IoSliceMut;
+
+    let temp = TempFile::new(b"data");
+    let mut file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+    assert_eq!(file.read_vectored(&mut []).await.unwrap(), 0);
+    let mut empty = [IoSliceMut::new(&mut []), IoSliceMut::new(&mut [])];
+    assert_eq!(file.read_vectored(&mut empty).await.unwrap(), 0);
+    assert_eq!(file.stats().reads(), 0);
+}
+
+#[cfg(windows)]
+#[tokio::test]
+async fn empty_vectored_writes_come_back_at_once() {
+    use std::io::IoSlice;
+
+    let temp = TempFile::new(b"data");
+    let mut file = AsyncFile::open_for_write(temp.path()).await.unwrap();
+    assert_eq!(file.write_vectored(&[]).await.unwrap(), 0);
+    let empty = [IoSlice::new(&[]), IoSlice::new(&[])];
+    assert_eq!(file.write_vectored(&empty).await.unwrap(), 0);
+    assert_eq!(file.stats().writes(), 0);
+    drop(file);
+    assert_eq!(contents(temp.path()).await, b"data");
+}
+
+// Once for each read that brought something in, so every read but the last,
+// which found EOF.
+#[tokio::test]
+async fn read_callback_runs_once_per_completed_read() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    let data = pattern(10 * 4096 + 100);
+    let temp = TempFile::new(&data);
+    let mut file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+    let (calls, bytes) = (Arc::new(AtomicUsize::new(0)), Arc::new(AtomicUsize::new(0)));
+    let (c, b) = (calls.clone(), bytes.clone());
+    file.set_read_callback(move |chunk| {
+        c.fetch_add(1, Ordering::Relaxed);
+        b.fetch_add(chunk.len(), Ordering::Relaxed);
+    });
+
+    let mut buf = vec![0; 4096];
+    assert_eq!(file.read_all_default(&mut buf).await.unwrap(), data.len());
+    assert_eq!(calls.load(Ordering::Relaxed), 11);
+    assert_eq!(
+        calls.load(Ordering::Relaxed) as u64,
+        file.stats().reads() - 1
+    );
+    assert_eq!(bytes.load(Ordering::Relaxed), data.len());
+
+    // A callback of read_all()'s own runs instead of the default, not as well.
+    let mut overriding = 0;
+    file.read_all(&mut buf, |_| overriding += 1).await.unwrap();
+    assert_eq!(overriding, 11);
+    assert_eq!(calls.load(Ordering::Relaxed), 11);
+
+    file.clear_read_callback();
+    file.read_all_default(&mut buf).await.unwrap();
+    assert_eq!(calls.load(Ordering::Relaxed), 11);
+}
+
+// A ReadOp can be made, kept and awaited later, or never polled at all, in
+// which case nothing is read.
+#[tokio::test]
+async fn read_scoped_reads_once_awaited() {
+    let data = pattern(10_000);
+    let temp = TempFile::new(&data);
+    let file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+    let (mut first, mut second) = (vec![0; 4000], vec![0; 4000]);
+
+    let a = file.read_scoped(&mut first, 0);
+    let b = file.read_scoped(&mut second, 8000);
+    drop(file.read_scoped(&mut [0; 10], 0));
+    assert_eq!(file.stats().reads(), 0);
+    assert_eq!(a.await.unwrap(), 4000);
+    assert_eq!(b.await.unwrap(), 2000);
+    assert_eq!(first, data[..4000]);
+    assert_eq!(second[..2000], data[8000..]);
+    assert_eq!(file.read_scoped(&mut [], 0).await.unwrap(), 0);
+    assert_eq!(file.stats().reads(), 2);
+}
+
+// Each ReadOp is polled once, which issues its read, and dropped, most likely
+// while it's still in flight. If a drop could return while the read might
+// still land in buf, it would overwrite the fill after it.
+#[test]
+fn dropping_reads_mid_flight_leaves_buf_alone() {
+    let temp = TempFile::new(&pattern(1 << 20));
+    let file = block_on(AsyncFile::open_for_read(temp.path())).unwrap();
+    let mut buf = vec![0; 64 * 1024];
+    for i in 0..1000 {
+        let _ = file
+            .read_scoped(&mut buf, (i % 16) * 64 * 1024)
+            .now_or_never();
+        buf.fill(0xaa);
+    }
+    std::thread::sleep(std::time::Duration::from_millis(10));
+    assert!(buf.iter().all(|&b| b == 0xaa));
+    assert_eq!(file.stats().reads(), 1000);
+}
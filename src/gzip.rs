@@ -0,0 +1,266 @@
+use crate::{DecodeStatus, Decoder};
+use flate2::{Crc, Decompress, FlushDecompress, Status};
+use std::io;
+
+// Header flags, from RFC 1952.
+const FHCRC: u8 = 0x02;
+const FEXTRA: u8 = 0x04;
+const FNAME: u8 = 0x08;
+const FCOMMENT: u8 = 0x10;
+
+// CRC-32 and length of the uncompressed data, after the deflate stream.
+const TRAILER_LEN: usize = 8;
+
+// A raw deflate or zlib stream, whichever the Decompress was made for.
+impl Decoder for Decompress {
+    fn decode(&mut self, input: &[u8], output: &mut [u8]) -> io::Result<DecodeStatus> {
+        let (total_in, total_out) = (self.total_in(), self.total_out());
+        let status = self
+            .decompress(input, output, FlushDecompress::None)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+        Ok(DecodeStatus {
+            consumed: (self.total_in() - total_in) as usize,
+            produced: (self.total_out() - total_out) as usize,
+            finished: status == Status::StreamEnd,
+        })
+    }
+}
+
+// A .gz file: the header, a deflate stream and the trailer, all taken in as
+// they arrive, with the trailer's CRC and length checked against what was
+// decoded. Only the first member of a multi-member file is read.
+pub struct GzipDecoder {
+    state: GzipState,
+    inflate: Decompress,
+    crc: Crc,
+    // Header or trailer bytes gathered so far, as either may be split
+    // across reads.
+    pending: Vec<u8>,
+}
+
+enum GzipState {
+    Header,
+    Body,
+    Trailer,
+    Done,
+}
+
+impl GzipDecoder {
+    pub fn new() -> Self {
+        Self {
+            state: GzipState::Header,
+            inflate: Decompress::new(false),
+            crc: Crc::new(),
+            pending: Vec::new(),
+        }
+    }
+
+    fn check_trailer(&self) -> io::Result<()> {
+        let crc = u32::from_le_bytes(self.pending[..4].try_into().unwrap());
+        let len = u32::from_le_bytes(self.pending[4..].try_into().unwrap());
+        if crc != self.crc.sum() || len != self.crc.amount() {
+            return Err(corrupt("gzip trailer doesn't match the data"));
+        }
+        Ok(())
+    }
+}
+
+impl Default for GzipDecoder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Decoder for GzipDecoder {
+    fn decode(&mut self, input: &[u8], output: &mut [u8]) -> io::Result<DecodeStatus> {
+        match self.state {
+            GzipState::Header => {
+                // Buffered whole until it's all there, then whatever came
+                // after it is left for the body.
+                let seen = self.pending.len();
+                self.pending.extend_from_slice(input);
+                let Some(len) = header_len(&self.pending)? else {
+                    return Ok(consumed(input.len()));
+                };
+                self.pending.clear();
+                self.state = GzipState::Body;
+                Ok(consumed(len - seen))
+            }
+            GzipState::Body => {
+                let status = self.inflate.decode(input, output)?;
+                self.crc.update(&output[..status.produced]);
+                if status.finished {
+                    self.state = GzipState::Trailer;
+                }
+                Ok(DecodeStatus {
+                    finished: false,
+                    ..status
+                })
+            }
+            GzipState::Trailer => {
+                let n = input.len().min(TRAILER_LEN - self.pending.len());
+                self.pending.extend_from_slice(&input[..n]);
+                let finished = self.pending.len() == TRAILER_LEN;
+                if finished {
+                    self.check_trailer()?;
+                    self.state = GzipState::Done;
+                }
+                Ok(DecodeStatus {
+                    consumed: n,
+                    produced: 0,
+                    finished,
+                })
+            }
+            GzipState::Done => Ok(DecodeStatus {
+                finished: true,
+                ..DecodeStatus::default()
+            }),
+        }
+    }
+}
+
+fn consumed(n: usize) -> DecodeStatus {
+    DecodeStatus {
+        consumed: n,
+        ..DecodeStatus::default()
+    }
+}
+
+// Length of the gzip header at the start of bytes, or None if more of it is
+// still to come.
+fn header_len(bytes: &[u8]) -> io::Result<Option<usize>> {
+    if bytes.len() < 10 {
+        return Ok(None);
+    }
+    if bytes[..3] != [0x1f, 0x8b, 8] {
+        return Err(corrupt("not a gzip stream"));
+    }
+
+    let flags = bytes[3];
+    let mut len = 10;
+    if flags & FEXTRA != 0 {
+        let Some(xlen) = bytes.get(len..len + 2) else {
+            return Ok(None);
+        };
+        len += 2 + u16::from_le_bytes([xlen[0], xlen[1]]) as usize;
+    }
+    for flag in [FNAME, FCOMMENT] {
+        if flags & flag != 0 {
+            // Zero-terminated.
+            let Some(end) = bytes
+                .get(len..)
+                .and_then(|s| s.iter().position(|&b| b == 0))
+            else {
+                return Ok(None);
+            };
+            len += end + 1;
+        }
+    }
+    if flags & FHCRC != 0 {
+        len += 2;
+    }
+    Ok((bytes.len() >= len).then_some(len))
+}
+
+fn corrupt(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempFile;
+    use crate::{AsyncFile, AsyncFileError, DecompressingRead};
+    use flate2::write::GzEncoder;
+    use flate2::{Compression, GzBuilder};
+    use std::io::Write;
+
+    // Doesn't compress to much, so the compressed file spans several of
+    // DecompressingRead's reads and the decoder keeps running dry.
+    fn incompressible(len: usize) -> Vec<u8> {
+        let mut x = 0x2545_f491_4f6c_dd1d_u64;
+        (0..len)
+            .map(|_| {
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                x as u8
+            })
+            .collect()
+    }
+
+    fn gzip(data: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(data).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    async fn read_back(compressed: &[u8], chunk: usize) -> crate::Result<Vec<u8>> {
+        let temp = TempFile::new(compressed);
+        let file = AsyncFile::open_for_read(temp.path()).await?;
+        let mut reader = DecompressingRead::new(file, GzipDecoder::new());
+        let mut out = Vec::new();
+        let mut buf = vec![0; chunk];
+        loop {
+            match reader.read(&mut buf).await? {
+                0 => return Ok(out),
+                n => out.extend_from_slice(&buf[..n]),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn round_trip() {
+        let data = incompressible(300_000);
+        let compressed = gzip(&data);
+        assert!(compressed.len() > 64 * 1024 * 3);
+        assert_eq!(read_back(&compressed, 4096).await.unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn round_trip_with_optional_header_fields() {
+        let data = b"hello, hello, hello, hello".repeat(100);
+        let mut encoder = GzBuilder::new()
+            .filename("hello.txt")
+            .comment("a comment")
+            .extra(vec![1, 2, 3])
+            .write(Vec::new(), Compression::best());
+        encoder.write_all(&data).unwrap();
+        let compressed = encoder.finish().unwrap();
+        assert_eq!(read_back(&compressed, 7).await.unwrap(), data);
+    }
+
+    #[test]
+    fn header_split_across_decode_calls() {
+        let data = b"split".to_vec();
+        let compressed = gzip(&data);
+        let mut decoder = GzipDecoder::new();
+        let mut out = [0; 16];
+        for byte in &compressed[..10] {
+            let status = decoder
+                .decode(std::slice::from_ref(byte), &mut out)
+                .unwrap();
+            assert_eq!((status.consumed, status.produced), (1, 0));
+        }
+        let status = decoder.decode(&compressed[10..], &mut out).unwrap();
+        assert_eq!(&out[..status.produced], b"split");
+    }
+
+    #[tokio::test]
+    async fn truncated_file_fails_with_eof() {
+        let compressed = gzip(&incompressible(10_000));
+        let result = read_back(&compressed[..compressed.len() / 2], 4096).await;
+        assert!(matches!(result, Err(AsyncFileError::Eof)));
+    }
+
+    #[tokio::test]
+    async fn corrupt_trailer_is_refused() {
+        let mut compressed = gzip(b"checked");
+        let last = compressed.len() - 5;
+        compressed[last] ^= 0xff;
+        let result = read_back(&compressed, 4096).await;
+        assert!(
+            matches!(result, Err(AsyncFileError::Io(e)) if e.kind() == io::ErrorKind::InvalidData)
+        );
+    }
+}
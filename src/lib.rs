@@ -0,0 +1,78 @@
+// Asynchronous file I/O. On Windows this is overlapped I/O woken from the
+// completion callback; on Linux, with the linux feature, it's io_uring.
+// Anywhere else it falls back to blocking calls on a blocking pool. All
+// of them present the same AsyncFile API.
+//
+// Reads and writes only need their task's Waker, so they run on any executor,
+// not just tokio's, and tokio itself is an optional dependency behind the
+// tokio feature (on by default). Without it, or outside a tokio runtime,
+// blocking calls get threads of their own rather than the blocking pool, the
+// pause between retries is slept out on one of those, and polled operations
+// are woken again at once. What only the feature brings is tokio's AsyncRead
+// impls and the timed reads: read_timeout(), read_deadline() and
+// read_exact_or_timeout(), which also need a runtime to run on.
+//
+// With the tracing feature, opening a file is a span, and each read and write
+// is logged through tracing as it's issued and again as it completes, with its
+// offset, length and outcome.
+//
+// With the bytemuck feature, AsyncFile::read_struct() reads any Pod type, a
+// format's header say, straight out of the file.
+
+mod aligned;
+mod batch;
+mod blocking;
+mod bufread;
+mod callback;
+mod checksum;
+mod decompress;
+mod eof;
+mod error;
+#[cfg(all(unix, not(all(target_os = "linux", feature = "linux"))))]
+mod fallback;
+#[cfg(test)]
+mod file_tests;
+#[cfg(feature = "flate2")]
+mod gzip;
+mod limiter;
+mod owned;
+mod pool;
+mod prefetch;
+mod random;
+mod range;
+mod read_files;
+mod read_to_end;
+mod readahead;
+#[cfg(feature = "bytemuck")]
+mod record;
+mod sink;
+mod stats;
+#[cfg(test)]
+mod test_util;
+#[cfg(all(target_os = "linux", feature = "linux"))]
+mod uring;
+mod verify;
+#[cfg(windows)]
+mod win;
+
+pub use aligned::AlignedBuffer;
+pub use bufread::AsyncBufReader;
+pub use checksum::ChecksummedBlockReader;
+pub use decompress::{DecodeStatus, Decoder, DecompressingRead};
+pub use error::{AsyncFileError, Result};
+#[cfg(all(unix, not(all(target_os = "linux", feature = "linux"))))]
+pub use fallback::*;
+#[cfg(feature = "flate2")]
+pub use gzip::GzipDecoder;
+pub use limiter::IoLimiter;
+pub use pool::{BufferPool, PooledBuffer};
+pub use random::{AsyncRandomRead, MemoryFile};
+pub use range::RangeReader;
+pub use read_files::{read_all, read_all_with};
+pub use read_to_end::ReadToEndOptions;
+pub use readahead::ReadAhead;
+pub use stats::FileStats;
+#[cfg(all(target_os = "linux", feature = "linux"))]
+pub use uring::*;
+#[cfg(windows)]
+pub use win::*;
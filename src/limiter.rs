@@ -0,0 +1,91 @@
+use async_lock::{Semaphore, SemaphoreGuard};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// Caps how many reads may be submitted at once across every file sharing it,
+// so a wide fan-out of read_at() calls queues here rather than on the disk.
+// On Windows files take one through AsyncOpenOptions::limiter(), and
+// read_all_with() counts each file it loads against one on every platform.
+// Cloning gives another handle on the same limit.
+#[derive(Clone, Debug)]
+pub struct IoLimiter {
+    inner: Arc<LimiterInner>,
+}
+
+#[derive(Debug)]
+struct LimiterInner {
+    semaphore: Semaphore,
+    limit: usize,
+    // async-lock's semaphore doesn't say how many permits are out.
+    in_flight: AtomicUsize,
+}
+
+// Held from before a read is submitted until it has completed.
+pub(crate) struct IoPermit<'a> {
+    _guard: SemaphoreGuard<'a>,
+    in_flight: &'a AtomicUsize,
+}
+
+impl IoLimiter {
+    // A limit of zero would leave every read waiting for good, so it panics.
+    pub fn new(limit: usize) -> Self {
+        assert!(limit != 0, "limit must be non-zero");
+        Self {
+            inner: Arc::new(LimiterInner {
+                semaphore: Semaphore::new(limit),
+                limit,
+                in_flight: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    // Reads submitted and not yet completed.
+    pub fn in_flight(&self) -> usize {
+        self.inner.in_flight.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn limit(&self) -> usize {
+        self.inner.limit
+    }
+
+    pub(crate) async fn acquire(&self) -> IoPermit<'_> {
+        let guard = self.inner.semaphore.acquire().await;
+        self.inner.in_flight.fetch_add(1, Ordering::Relaxed);
+        IoPermit {
+            _guard: guard,
+            in_flight: &self.inner.in_flight,
+        }
+    }
+}
+
+impl Drop for IoPermit<'_> {
+    fn drop(&mut self) {
+        self.in_flight.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::block_on;
+
+    #[test]
+    #[should_panic(expected = "limit must be non-zero")]
+    fn zero_limit_is_refused() {
+        IoLimiter::new(0);
+    }
+
+    #[test]
+    fn permits_are_counted_until_dropped() {
+        let limiter = IoLimiter::new(2);
+        block_on(async {
+            let a = limiter.acquire().await;
+            let _b = limiter.acquire().await;
+            assert_eq!(limiter.in_flight(), 2);
+            drop(a);
+            assert_eq!(limiter.in_flight(), 1);
+            let _c = limiter.acquire().await;
+            assert_eq!(limiter.in_flight(), 2);
+        });
+    }
+}
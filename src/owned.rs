@@ -0,0 +1,23 @@
+use crate::{AsyncFile, Result};
+
+// Reads that take the buffer by value and hand it back, as tokio-uring does.
+// With a borrowed buffer, a read future that is leaked rather than dropped
+// (mem::forget on a boxed future, say) never cancels its read, and the kernel
+// can go on writing into memory that has since been freed. Here the buffer
+// lives inside the future, so leaking one leaks the buffer with it, and
+// dropping one cancels the read before the buffer is freed. Either way nothing
+// outside can see the buffer until the read is truly over.
+impl AsyncFile {
+    // read() into an owned buffer, returned along with the count read. On
+    // failure the buffer is dropped.
+    pub async fn read_owned(&mut self, mut buf: Vec<u8>) -> Result<(Vec<u8>, usize)> {
+        let n = self.read(&mut buf).await?;
+        Ok((buf, n))
+    }
+
+    // read_at() into an owned buffer, returned along with the count read.
+    pub async fn read_at_owned(&self, mut buf: Vec<u8>, offset: u64) -> Result<(Vec<u8>, usize)> {
+        let n = self.read_at(&mut buf, offset).await?;
+        Ok((buf, n))
+    }
+}
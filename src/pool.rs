@@ -0,0 +1,156 @@
+use crate::{AlignedBuffer, AsyncFile, Result};
+use async_lock::{Semaphore, SemaphoreGuardArc};
+use futures_core::Stream;
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+// A bounded set of equally sized, aligned buffers that are handed out and come
+// back when dropped, so steady streaming reuses the same few allocations.
+// Buffers are only allocated as they're first needed. Once capacity of them
+// are checked out, acquire() waits for one to be returned. Cloning gives
+// another handle on the same pool.
+#[derive(Clone)]
+pub struct BufferPool {
+    inner: Arc<PoolInner>,
+}
+
+struct PoolInner {
+    free: Mutex<Vec<AlignedBuffer>>,
+    // One permit per buffer that may be checked out.
+    permits: Arc<Semaphore>,
+    capacity: usize,
+    buffer_size: usize,
+    align: usize,
+    allocated: AtomicUsize,
+}
+
+impl BufferPool {
+    // Up to capacity buffers of buffer_size bytes, each aligned to align (a
+    // power of two, e.g. a sector size for unbuffered files).
+    pub fn new(capacity: usize, buffer_size: usize, align: usize) -> Self {
+        Self {
+            inner: Arc::new(PoolInner {
+                free: Mutex::new(Vec::with_capacity(capacity)),
+                permits: Arc::new(Semaphore::new(capacity)),
+                capacity,
+                buffer_size,
+                align,
+                allocated: AtomicUsize::new(0),
+            }),
+        }
+    }
+
+    // A buffer from the pool, waiting for one to come back if all are out.
+    pub async fn acquire(&self) -> PooledBuffer {
+        let permit = self.inner.permits.acquire_arc().await;
+        self.checkout(permit)
+    }
+
+    // A buffer from the pool, or None if all are out.
+    pub fn try_acquire(&self) -> Option<PooledBuffer> {
+        let permit = self.inner.permits.try_acquire_arc()?;
+        Some(self.checkout(permit))
+    }
+
+    fn checkout(&self, permit: SemaphoreGuardArc) -> PooledBuffer {
+        let free = self
+            .inner
+            .free
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .pop();
+        let buf = free.unwrap_or_else(|| {
+            self.inner.allocated.fetch_add(1, Ordering::Relaxed);
+            AlignedBuffer::new(self.inner.buffer_size, self.inner.align)
+        });
+        PooledBuffer {
+            len: buf.len(),
+            buf: Some(buf),
+            pool: self.inner.clone(),
+            _permit: permit,
+        }
+    }
+
+    pub fn capacity(&self) -> usize {
+        self.inner.capacity
+    }
+
+    // Buffers allocated so far, never more than capacity.
+    pub fn allocated(&self) -> usize {
+        self.inner.allocated.load(Ordering::Relaxed)
+    }
+
+    // Buffers currently checked out, being every one allocated that isn't
+    // back in the free list.
+    pub fn in_use(&self) -> usize {
+        let free = self
+            .inner
+            .free
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .len();
+        self.allocated() - free
+    }
+}
+
+// A buffer on loan from a BufferPool, returned to it on drop. It derefs to
+// its first len() bytes, which is the whole buffer until truncate() is used.
+pub struct PooledBuffer {
+    buf: Option<AlignedBuffer>,
+    len: usize,
+    pool: Arc<PoolInner>,
+    // Released after the buffer is back in the free list.
+    _permit: SemaphoreGuardArc,
+}
+
+impl PooledBuffer {
+    pub fn truncate(&mut self, len: usize) {
+        self.len = self.len.min(len);
+    }
+}
+
+impl Deref for PooledBuffer {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        &self.buf.as_ref().unwrap()[..self.len]
+    }
+}
+
+impl DerefMut for PooledBuffer {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        &mut self.buf.as_mut().unwrap()[..self.len]
+    }
+}
+
+impl Drop for PooledBuffer {
+    fn drop(&mut self) {
+        if let Some(buf) = self.buf.take() {
+            self.pool
+                .free
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .push(buf);
+        }
+    }
+}
+
+impl AsyncFile {
+    // Like chunks(), but reading into buffers from pool, so a consumer that
+    // holds on to chunks holds up the reading rather than growing memory.
+    pub fn pooled_chunks(self, pool: BufferPool) -> impl Stream<Item = Result<PooledBuffer>> {
+        futures_util::stream::unfold(Some((self, pool)), |state| async move {
+            let (mut file, pool) = state?;
+            let mut buf = pool.acquire().await;
+            match file.read(&mut buf).await {
+                Ok(0) => None,
+                Ok(n) => {
+                    buf.truncate(n);
+                    Some((Ok(buf), Some((file, pool))))
+                }
+                Err(e) => Some((Err(e), None)),
+            }
+        })
+    }
+}
@@ -0,0 +1,31 @@
+use crate::{AsyncFile, Result};
+
+// Size of each of the reads prefetch() makes.
+const PREFETCH_CHUNK: usize = 1024 * 1024;
+
+impl AsyncFile {
+    // Reads len bytes at offset and throws them away, so that the OS cache
+    // holds them and a read_at() over the range soon after needn't wait on
+    // the disk. The reads go one at a time through read_at(), so they take
+    // their turn with the file's limiter, and any of the range past EOF is
+    // skipped. It does nothing for a Windows file opened unbuffered, whose
+    // reads don't go through the cache at all.
+    pub async fn prefetch(&self, offset: u64, len: u64) -> Result<()> {
+        #[cfg(windows)]
+        if self.is_unbuffered() {
+            return Ok(());
+        }
+
+        let end = offset.saturating_add(len);
+        let mut scratch = vec![0; len.min(PREFETCH_CHUNK as u64) as usize];
+        let mut pos = offset;
+        while pos < end {
+            let chunk = (end - pos).min(scratch.len() as u64) as usize;
+            match self.read_at(&mut scratch[..chunk], pos).await? {
+                0 => break,
+                n => pos += n as u64,
+            }
+        }
+        Ok(())
+    }
+}
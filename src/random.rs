@@ -0,0 +1,82 @@
+use crate::{AsyncFile, Result};
+use std::future::Future;
+
+// Positioned reads, as AsyncFile::read_at() does them, for code that wants to
+// be generic over where its bytes come from. AsyncFile implements it, and so
+// does MemoryFile, which lets such code be tested against bytes in memory
+// without any real I/O.
+pub trait AsyncRandomRead {
+    // Up to buf.len() bytes at offset, returning 0 at or past the end.
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> impl Future<Output = Result<usize>>;
+}
+
+impl AsyncRandomRead for AsyncFile {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> impl Future<Output = Result<usize>> {
+        AsyncFile::read_at(self, buf, offset)
+    }
+}
+
+// A file's worth of bytes held in memory. Reads complete on the spot.
+#[derive(Clone, Debug, Default)]
+pub struct MemoryFile {
+    data: Vec<u8>,
+}
+
+impl MemoryFile {
+    pub fn new(data: Vec<u8>) -> Self {
+        Self { data }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.data.len() as u64
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn into_inner(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+impl AsyncRandomRead for MemoryFile {
+    fn read_at(&self, buf: &mut [u8], offset: u64) -> impl Future<Output = Result<usize>> {
+        let start = offset.min(self.len()) as usize;
+        let n = buf.len().min(self.data.len() - start);
+        buf[..n].copy_from_slice(&self.data[start..start + n]);
+        std::future::ready(Ok(n))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{pattern, TempFile};
+
+    // Written once against the trait, with nothing to say which it's given.
+    async fn sum_of<R: AsyncRandomRead>(source: &R, offset: u64, len: usize) -> u64 {
+        let mut buf = vec![0; len];
+        let n = source.read_at(&mut buf, offset).await.unwrap();
+        buf[..n].iter().map(|&b| b as u64).sum()
+    }
+
+    #[tokio::test]
+    async fn same_code_reads_memory_and_files_alike() {
+        let data = pattern(10_000);
+        let temp = TempFile::new(&data);
+        let file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+        let memory = MemoryFile::new(data.clone());
+
+        for (offset, len) in [(0, 100), (4321, 2000), (9990, 100), (20_000, 10)] {
+            assert_eq!(
+                sum_of(&file, offset, len).await,
+                sum_of(&memory, offset, len).await
+            );
+        }
+        assert_eq!(
+            sum_of(&memory, 9990, 100).await,
+            data[9990..].iter().map(|&b| b as u64).sum()
+        );
+    }
+}
@@ -0,0 +1,73 @@
+use crate::{AsyncFile, AsyncFileError, Result};
+
+// A window of a file, [start, start + len), read as though it were the whole
+// file, like std's io::Take but positioned. Offsets are relative to start and
+// reads stop at the end of the window whatever lies beyond it, so a parser
+// given one can't stray into its neighbours. The file's own cursor is left
+// alone.
+pub struct RangeReader<'a> {
+    file: &'a AsyncFile,
+    start: u64,
+    len: u64,
+    // Where read() carries on from, relative to start.
+    position: u64,
+}
+
+impl AsyncFile {
+    // The len bytes of the file from start. The window isn't checked against
+    // the file's size; one that runs past EOF just ends there.
+    pub fn take_range(&self, start: u64, len: u64) -> RangeReader<'_> {
+        RangeReader {
+            file: self,
+            start,
+            len: len.min(u64::MAX - start),
+            position: 0,
+        }
+    }
+}
+
+impl RangeReader<'_> {
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // How far read() has got into the window.
+    pub fn position(&self) -> u64 {
+        self.position
+    }
+
+    // Up to buf.len() bytes from offset into the window, fewer if the window
+    // ends first. Returns 0 at or past its end.
+    pub async fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        let remaining = self.len.saturating_sub(offset);
+        let n = buf.len().min(remaining.try_into().unwrap_or(usize::MAX));
+        if n == 0 {
+            return Ok(0);
+        }
+        self.file.read_at(&mut buf[..n], self.start + offset).await
+    }
+
+    // read_at() from the reader's own position, which it moves on.
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = self.read_at(buf, self.position).await?;
+        self.position += n as u64;
+        Ok(n)
+    }
+
+    // Keeps reading until buf is full, failing with Eof if the window or the
+    // file ends first.
+    pub async fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.read(&mut buf[filled..]).await? {
+                0 => return Err(AsyncFileError::Eof),
+                n => filled += n,
+            }
+        }
+        Ok(())
+    }
+}
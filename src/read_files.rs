@@ -0,0 +1,78 @@
+use crate::{AsyncFile, AsyncFileError, IoLimiter, Result};
+use futures_util::stream::{self, StreamExt};
+
+// Files read_all() has open at once.
+const DEFAULT_CONCURRENCY: usize = 16;
+
+// The whole of each file in paths, in the same order. Up to 16 are read at a
+// time; read_all_with() shares a limit with other I/O instead.
+pub async fn read_all(paths: &[&str]) -> Result<Vec<Vec<u8>>> {
+    read_all_with(paths, &IoLimiter::new(DEFAULT_CONCURRENCY)).await
+}
+
+// read_all() with each file holding one of limiter's permits from opening it
+// until it's been read, so loading them counts against the same budget as
+// whatever else shares the limiter. Files opened with that limiter on Windows
+// wait on it per read, so don't hold a read of one of those across this or
+// they'll wait on each other. The first failure ends the lot, with the reads
+// still going abandoned, and comes back as AtPath naming the file.
+pub async fn read_all_with(paths: &[&str], limiter: &IoLimiter) -> Result<Vec<Vec<u8>>> {
+    let mut reads = stream::iter(paths.iter().map(|&path| async move {
+        let _permit = limiter.acquire().await;
+        read_file(path)
+            .await
+            .map_err(|e| AsyncFileError::AtPath(path.to_owned(), Box::new(e)))
+    }))
+    .buffered(limiter.limit());
+
+    let mut contents = Vec::with_capacity(paths.len());
+    while let Some(data) = reads.next().await {
+        contents.push(data?);
+    }
+    Ok(contents)
+}
+
+async fn read_file(path: &str) -> Result<Vec<u8>> {
+    let mut file = AsyncFile::open_for_read(path).await?;
+    let mut data = Vec::new();
+    file.read_to_end(&mut data).await?;
+    Ok(data)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{pattern, TempFile};
+
+    #[tokio::test]
+    async fn contents_come_back_in_order() {
+        // Different sizes, so any reordering by completion shows.
+        let temps: Vec<_> = (0..40).map(|i| TempFile::new(&pattern(i * 997))).collect();
+        let paths: Vec<_> = temps.iter().map(TempFile::path).collect();
+
+        let contents = read_all(&paths).await.unwrap();
+        assert_eq!(contents.len(), 40);
+        for (i, data) in contents.iter().enumerate() {
+            assert_eq!(*data, pattern(i * 997));
+        }
+    }
+
+    #[tokio::test]
+    async fn shared_limiter_is_given_back() {
+        let temps: Vec<_> = (0..8).map(|_| TempFile::new(b"small")).collect();
+        let paths: Vec<_> = temps.iter().map(TempFile::path).collect();
+        let limiter = IoLimiter::new(3);
+
+        let contents = read_all_with(&paths, &limiter).await.unwrap();
+        assert!(contents.iter().all(|data| data == b"small"));
+        assert_eq!(limiter.in_flight(), 0);
+    }
+
+    #[tokio::test]
+    async fn failure_names_the_path() {
+        let temp = TempFile::new(b"here");
+        let missing = format!("{}.missing", temp.path());
+        let result = read_all(&[temp.path(), &missing]).await;
+        assert!(matches!(result, Err(AsyncFileError::AtPath(path, _)) if path == missing));
+    }
+}
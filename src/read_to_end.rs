@@ -0,0 +1,169 @@
+use crate::{AsyncFile, AsyncFileError, Result};
+
+// Chunk size read_to_end() reads with, start to finish.
+const DEFAULT_CHUNK: usize = 1024 * 64;
+
+// How read_to_end_with() sizes its reads. Each read is initial_chunk bytes to
+// begin with, and every one that comes back full multiplies the next by growth,
+// up to max_chunk. A short read drops back to initial_chunk. The default is a
+// fixed 64 KiB; small chunks that grow suit many small files, where the first
+// read is often the whole thing, while large ones suit few huge files.
+#[derive(Clone, Debug)]
+pub struct ReadToEndOptions {
+    initial_chunk: usize,
+    max_chunk: usize,
+    growth: usize,
+}
+
+impl Default for ReadToEndOptions {
+    fn default() -> Self {
+        Self {
+            initial_chunk: DEFAULT_CHUNK,
+            max_chunk: DEFAULT_CHUNK,
+            growth: 2,
+        }
+    }
+}
+
+impl ReadToEndOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn initial_chunk(&mut self, size: usize) -> &mut Self {
+        assert!(size != 0, "chunk size must be non-zero");
+        self.initial_chunk = size;
+        self
+    }
+
+    // Never below initial_chunk, whatever is set here.
+    pub fn max_chunk(&mut self, size: usize) -> &mut Self {
+        self.max_chunk = size;
+        self
+    }
+
+    // 1 keeps every read at initial_chunk.
+    pub fn growth(&mut self, factor: usize) -> &mut Self {
+        assert!(factor != 0, "growth factor must be non-zero");
+        self.growth = factor;
+        self
+    }
+}
+
+impl AsyncFile {
+    // Reads from the cursor to EOF, appending to out and returning how many
+    // bytes were added, so after seek() past a header it's just the rest. The
+    // cursor ends up at EOF. Chunks are read straight into the tail of out.
+    pub async fn read_to_end(&mut self, out: &mut Vec<u8>) -> Result<usize> {
+        self.read_to_end_with(out, &ReadToEndOptions::default())
+            .await
+    }
+
+    // read_to_end() with the chunk sizes set by options.
+    pub async fn read_to_end_with(
+        &mut self,
+        out: &mut Vec<u8>,
+        options: &ReadToEndOptions,
+    ) -> Result<usize> {
+        let start = out.len();
+        if let Some(remaining) = self.remaining_len().await {
+            // Room for the rest of the file plus the chunk that finds EOF.
+            out.reserve(remaining as usize + options.initial_chunk);
+        }
+
+        let max_chunk = options.max_chunk.max(options.initial_chunk);
+        let mut chunk = options.initial_chunk;
+        loop {
+            let len = out.len();
+            out.resize(len + chunk, 0);
+            match self.read(&mut out[len..]).await {
+                Ok(0) => {
+                    out.truncate(len);
+                    return Ok(len - start);
+                }
+                Ok(n) => {
+                    out.truncate(len + n);
+                    chunk = if n == chunk {
+                        chunk.saturating_mul(options.growth).min(max_chunk)
+                    } else {
+                        options.initial_chunk
+                    };
+                }
+                Err(e) => {
+                    out.truncate(len);
+                    return Err(e);
+                }
+            }
+        }
+    }
+
+    // Reads from the cursor to EOF, failing with FileTooLarge rather than
+    // returning more than max bytes, for files that can't be trusted to be a
+    // sensible size. A file that's too big by its length is refused before
+    // anything is read. As it may grow meanwhile, the reads stop there too,
+    // once they've found a byte past max. A file of exactly max bytes is
+    // fine. After FileTooLarge the cursor is wherever the reads got to.
+    pub async fn read_to_end_limited(&mut self, max: usize) -> Result<Vec<u8>> {
+        let mut out = Vec::new();
+        if let Some(remaining) = self.remaining_len().await {
+            if remaining > max as u64 {
+                return Err(AsyncFileError::FileTooLarge(max));
+            }
+            out.reserve(remaining as usize);
+        }
+
+        loop {
+            // Never more than one byte past max, which is enough to tell.
+            let len = out.len();
+            let chunk = DEFAULT_CHUNK.min(max.saturating_add(1) - len);
+            out.resize(len + chunk, 0);
+            let n = self.read(&mut out[len..]).await?;
+            out.truncate(len + n);
+            if n == 0 {
+                return Ok(out);
+            }
+            if out.len() > max {
+                return Err(AsyncFileError::FileTooLarge(max));
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{pattern, TempFile};
+
+    #[tokio::test]
+    async fn one_byte_over_the_cap_is_refused_up_front() {
+        let temp = TempFile::new(&pattern(100_001));
+        let mut file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+        assert!(matches!(
+            file.read_to_end_limited(100_000).await,
+            Err(AsyncFileError::FileTooLarge(100_000))
+        ));
+        assert_eq!(file.stats().reads(), 0);
+    }
+
+    #[tokio::test]
+    async fn exactly_the_cap_is_read() {
+        let data = pattern(100_000);
+        let temp = TempFile::new(&data);
+        let mut file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+        assert_eq!(file.read_to_end_limited(100_000).await.unwrap(), data);
+
+        let empty = TempFile::new(&[]);
+        let mut file = AsyncFile::open_for_read(empty.path()).await.unwrap();
+        assert!(file.read_to_end_limited(0).await.unwrap().is_empty());
+    }
+
+    // The cap counts from the cursor, like everything else read_to_end() does.
+    #[tokio::test]
+    async fn cap_applies_to_what_is_left() {
+        let data = pattern(1000);
+        let temp = TempFile::new(&data);
+        let mut file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+        file.seek(std::io::SeekFrom::Start(600)).await.unwrap();
+        assert_eq!(file.read_to_end_limited(400).await.unwrap(), data[600..]);
+    }
+}
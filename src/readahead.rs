@@ -0,0 +1,144 @@
+use crate::{AsyncFile, Result};
+use futures_core::future::BoxFuture;
+use futures_util::stream::{FuturesOrdered, StreamExt};
+
+// Sequential reading that keeps the next few blocks of the file in flight
+// while the caller works on the current one, which hides the latency of
+// spinning or networked disks. Reads start at the beginning of the file and
+// use read_at(), so the file's cursor is left alone.
+//
+// The window is also the high-water mark: blocks read but not yet taken count
+// against it, so a slow caller holds at most window * block_size bytes. Once
+// full, the window is only topped up again when what's left drops to the
+// low-water mark, which by default is one below, i.e. straight away.
+pub struct ReadAhead<'a> {
+    file: &'a AsyncFile,
+    reads: FuturesOrdered<BoxFuture<'a, Result<Vec<u8>>>>,
+    block_size: usize,
+    window: usize,
+    low_water: usize,
+    // Where the next read to be issued starts.
+    offset: u64,
+    // Set once EOF or an error has been seen, after which nothing new is issued.
+    done: bool,
+}
+
+impl<'a> ReadAhead<'a> {
+    // Blocks of block_size bytes, with up to window of them reading at once.
+    pub fn new(file: &'a AsyncFile, block_size: usize, window: usize) -> Self {
+        assert!(block_size != 0, "block size must be non-zero");
+        assert!(window != 0, "window must be non-zero");
+        Self {
+            file,
+            reads: FuturesOrdered::new(),
+            block_size,
+            window,
+            low_water: window - 1,
+            offset: 0,
+            done: false,
+        }
+    }
+
+    // Holds off issuing more reads until no more than low blocks are left in
+    // the window, reading or read, so reads go out in batches rather than one
+    // per block taken. Must be below the window.
+    pub fn low_water(&mut self, low: usize) -> &mut Self {
+        assert!(low < self.window, "low-water mark must be below the window");
+        self.low_water = low;
+        self
+    }
+
+    // The next block in file order, or None once the file is exhausted. A
+    // short block is taken to be the last; reads already issued past it are
+    // drained and their (empty) results dropped. After an error the reads
+    // still outstanding are cancelled and None follows.
+    pub async fn next_chunk(&mut self) -> Result<Option<Vec<u8>>> {
+        loop {
+            self.fill();
+            let Some(result) = self.reads.next().await else {
+                return Ok(None);
+            };
+
+            let chunk = match result {
+                Ok(chunk) => chunk,
+                Err(e) => {
+                    self.done = true;
+                    self.reads = FuturesOrdered::new();
+                    return Err(e);
+                }
+            };
+            if chunk.len() < self.block_size {
+                self.done = true;
+            }
+            if !chunk.is_empty() {
+                return Ok(Some(chunk));
+            }
+        }
+    }
+
+    // Tops the window back up with reads further along the file, once it has
+    // drained to the low-water mark.
+    fn fill(&mut self) {
+        if self.reads.len() > self.low_water {
+            return;
+        }
+        while !self.done && self.reads.len() < self.window {
+            let read = read_block(self.file, self.offset, self.block_size);
+            self.reads.push_back(Box::pin(read));
+            self.offset += self.block_size as u64;
+        }
+    }
+}
+
+async fn read_block(file: &AsyncFile, offset: u64, len: usize) -> Result<Vec<u8>> {
+    let mut buf = vec![0; len];
+    let n = file.read_at(&mut buf, offset).await?;
+    buf.truncate(n);
+    Ok(buf)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{pattern, TempFile};
+    use std::time::Duration;
+
+    // The caller dawdles over every block, so reads would run well ahead of
+    // it but for the window: issued and not yet taken never passes 8 blocks.
+    // With a low-water mark of 2 they go out 8 and then 6 at a time.
+    #[tokio::test]
+    async fn slow_consumer_bounds_what_is_read_ahead() {
+        let data = pattern(40 * 1000);
+        let temp = TempFile::new(&data);
+        let file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+        let mut ahead = ReadAhead::new(&file, 1000, 8);
+        ahead.low_water(2);
+
+        let mut out = Vec::new();
+        let mut issued = Vec::new();
+        while let Some(chunk) = ahead.next_chunk().await.unwrap() {
+            out.extend_from_slice(&chunk);
+            let blocks = ahead.offset / 1000;
+            assert!(blocks - (out.len() as u64 / 1000) < 8);
+            issued.push(blocks);
+            tokio::time::sleep(Duration::from_millis(2)).await;
+        }
+        assert_eq!(out, data);
+        assert_eq!(issued[..7], [8, 8, 8, 8, 8, 8, 14]);
+    }
+
+    #[tokio::test]
+    async fn short_last_block_ends_it() {
+        let data = pattern(2500);
+        let temp = TempFile::new(&data);
+        let file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+        let mut ahead = ReadAhead::new(&file, 1000, 4);
+
+        let mut lens = Vec::new();
+        while let Some(chunk) = ahead.next_chunk().await.unwrap() {
+            lens.push(chunk.len());
+        }
+        assert_eq!(lens, [1000, 1000, 500]);
+        assert!(ahead.next_chunk().await.unwrap().is_none());
+    }
+}
@@ -0,0 +1,63 @@
+use crate::{AsyncFile, Result};
+use bytemuck::Pod;
+
+impl AsyncFile {
+    // The next size_of::<T>() bytes at the cursor as a T, e.g. a format's
+    // header, taken in the machine's byte order. Pod rules out anything some
+    // bytes wouldn't make a valid value of, and padding. Fails with Eof if the
+    // file ends first, leaving the cursor after whatever was read.
+    pub async fn read_struct<T: Pod>(&mut self) -> Result<T> {
+        // Read into the T itself, so it's aligned as one whatever T needs.
+        let mut value = T::zeroed();
+        self.read_exact(bytemuck::bytes_of_mut(&mut value)).await?;
+        Ok(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::TempFile;
+    use crate::{AsyncFile, AsyncFileError};
+    use bytemuck::{Pod, Zeroable};
+
+    #[repr(C)]
+    #[derive(Clone, Copy, Debug, PartialEq, Pod, Zeroable)]
+    struct Header {
+        magic: [u8; 4],
+        version: u16,
+        flags: u16,
+        length: u64,
+        scale: f32,
+        crc: u32,
+    }
+
+    const HEADER: Header = Header {
+        magic: *b"ASYF",
+        version: 3,
+        flags: 0x8001,
+        length: 0x0123_4567_89ab_cdef,
+        scale: 1.5,
+        crc: 0xdead_beef,
+    };
+
+    #[tokio::test]
+    async fn round_trip() {
+        let mut contents = bytemuck::bytes_of(&HEADER).to_vec();
+        contents.extend_from_slice(&7u32.to_ne_bytes());
+        let temp = TempFile::new(&contents);
+
+        let mut file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+        assert_eq!(file.read_struct::<Header>().await.unwrap(), HEADER);
+        assert_eq!(file.read_struct::<u32>().await.unwrap(), 7);
+    }
+
+    #[tokio::test]
+    async fn short_file_is_eof() {
+        let temp = TempFile::new(&bytemuck::bytes_of(&HEADER)[..10]);
+        let mut file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+        assert!(matches!(
+            file.read_struct::<Header>().await,
+            Err(AsyncFileError::Eof)
+        ));
+    }
+}
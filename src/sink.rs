@@ -0,0 +1,22 @@
+use crate::{AsyncFile, Result};
+use futures_core::Stream;
+use futures_util::StreamExt;
+use std::pin::pin;
+
+impl AsyncFile {
+    // Writes each buffer the stream yields in turn at the cursor, returning
+    // the total once it ends. The next buffer isn't pulled until the last
+    // write has completed, so a producer feeding the stream through a bounded
+    // channel is held back to the pace of the disk.
+    pub async fn write_from_stream<S>(&mut self, stream: S) -> Result<u64>
+    where
+        S: Stream<Item = Vec<u8>>,
+    {
+        let mut stream = pin!(stream);
+        let mut total = 0;
+        while let Some(buf) = stream.next().await {
+            total += self.write(&buf).await? as u64;
+        }
+        Ok(total)
+    }
+}
@@ -0,0 +1,93 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+
+// Running totals of the I/O done on one file, as stats() reports them. Each
+// read or write the file hands the OS counts as issued, and again as
+// completed once its outcome is known, so issued minus completed is what's in
+// flight. A read that finds EOF completes with no bytes rather than failing.
+// Operations refused on the spot count as issued, completed and failed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FileStats {
+    reads: u64,
+    writes: u64,
+    completions: u64,
+    errors: u64,
+    bytes_read: u64,
+    bytes_written: u64,
+}
+
+impl FileStats {
+    pub fn reads(&self) -> u64 {
+        self.reads
+    }
+
+    pub fn writes(&self) -> u64 {
+        self.writes
+    }
+
+    // Reads and writes that have finished, failed ones included.
+    pub fn completions(&self) -> u64 {
+        self.completions
+    }
+
+    pub fn errors(&self) -> u64 {
+        self.errors
+    }
+
+    pub fn bytes_read(&self) -> u64 {
+        self.bytes_read
+    }
+
+    pub fn bytes_written(&self) -> u64 {
+        self.bytes_written
+    }
+}
+
+// The counters behind FileStats, kept by the file itself. Relaxed atomics, as
+// the totals are only ever read as a rough snapshot.
+#[derive(Debug, Default)]
+pub(crate) struct IoStats {
+    reads: AtomicU64,
+    writes: AtomicU64,
+    completions: AtomicU64,
+    errors: AtomicU64,
+    bytes_read: AtomicU64,
+    bytes_written: AtomicU64,
+}
+
+impl IoStats {
+    pub(crate) fn read_issued(&self) {
+        self.reads.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn write_issued(&self) {
+        self.writes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Counts the outcome of a read issued earlier, given its byte count.
+    pub(crate) fn record_read<E>(&self, result: &std::result::Result<usize, E>) {
+        self.record(result, &self.bytes_read);
+    }
+
+    pub(crate) fn record_write<E>(&self, result: &std::result::Result<usize, E>) {
+        self.record(result, &self.bytes_written);
+    }
+
+    fn record<E>(&self, result: &std::result::Result<usize, E>, bytes: &AtomicU64) {
+        self.completions.fetch_add(1, Ordering::Relaxed);
+        match result {
+            Ok(n) => bytes.fetch_add(*n as u64, Ordering::Relaxed),
+            Err(_) => self.errors.fetch_add(1, Ordering::Relaxed),
+        };
+    }
+
+    pub(crate) fn snapshot(&self) -> FileStats {
+        FileStats {
+            reads: self.reads.load(Ordering::Relaxed),
+            writes: self.writes.load(Ordering::Relaxed),
+            completions: self.completions.load(Ordering::Relaxed),
+            errors: self.errors.load(Ordering::Relaxed),
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            bytes_written: self.bytes_written.load(Ordering::Relaxed),
+        }
+    }
+}
@@ -0,0 +1,72 @@
+// Helpers shared by the unit tests.
+
+use std::future::Future;
+use std::path::PathBuf;
+use std::pin::pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake};
+use std::thread::Thread;
+
+// A file in the temp directory that's removed again when this drops. Every
+// one gets a name of its own, so tests running in parallel don't collide.
+pub(crate) struct TempFile {
+    path: PathBuf,
+}
+
+impl TempFile {
+    pub(crate) fn new(contents: &[u8]) -> Self {
+        let path = std::env::temp_dir().join(unique_name());
+        std::fs::write(&path, contents).unwrap();
+        Self { path }
+    }
+
+    pub(crate) fn path(&self) -> &str {
+        self.path.to_str().unwrap()
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+// A name no other test in any run going at the same time will have, for temp
+// files, pipes and the like.
+pub(crate) fn unique_name() -> String {
+    static NEXT: AtomicUsize = AtomicUsize::new(0);
+    format!(
+        "async_file_test_{}_{}",
+        std::process::id(),
+        NEXT.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+// Bytes that differ from one offset to the next, so a read from the wrong
+// place doesn't pass by accident.
+pub(crate) fn pattern(len: usize) -> Vec<u8> {
+    (0..len).map(|i| (i % 251) as u8).collect()
+}
+
+struct Unpark(Thread);
+
+impl Wake for Unpark {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+// The least an executor can be: polls f on this thread, parking it until
+// woken. No tokio runtime is anywhere near it.
+pub(crate) fn block_on<F: Future>(f: F) -> F::Output {
+    let waker = Arc::new(Unpark(std::thread::current())).into();
+    let mut cx = Context::from_waker(&waker);
+    let mut f = pin!(f);
+    loop {
+        if let Poll::Ready(value) = f.as_mut().poll(&mut cx) {
+            return value;
+        }
+        std::thread::park();
+    }
+}
@@ -0,0 +1,512 @@
+use crate::blocking::blocking;
+use crate::callback::ReadCallback;
+use crate::eof::EofMark;
+use crate::error::invalid_seek;
+use crate::stats::IoStats;
+use crate::{AsyncFileError, FileStats, Result};
+use futures_util::task::AtomicWaker;
+use io_uring::{opcode, squeue, types, IoUring};
+use std::fs::File;
+use std::future::Future;
+use std::io::{self, SeekFrom};
+use std::marker::PhantomData;
+use std::os::fd::AsRawFd;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::task::{ready, Context, Poll};
+
+// Submission queue size of the ring every file shares.
+const RING_ENTRIES: u32 = 256;
+
+// Asynchronous file I/O on Linux, through io_uring
+pub struct AsyncFile {
+    file: File,
+    // Cursor shared by read() and write().
+    offset: u64,
+    // Where the cursor was when a read there last found EOF.
+    eof_at: EofMark,
+    stats: IoStats,
+    // Run by read_all_default() for each completed read.
+    read_callback: ReadCallback,
+}
+
+impl AsyncFile {
+    // Opening has no asynchronous form worth using here, so it happens on the
+    // blocking pool.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", err))]
+    pub async fn open_for_read(path: &str) -> Result<Self> {
+        let path = path.to_owned();
+        let file = blocking(move || File::open(path))
+            .await?
+            .map_err(AsyncFileError::Open)?;
+        Ok(Self {
+            file,
+            offset: 0,
+            eof_at: EofMark::default(),
+            stats: IoStats::default(),
+            read_callback: ReadCallback::default(),
+        })
+    }
+
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", err))]
+    pub async fn open_for_write(path: &str) -> Result<Self> {
+        let path = path.to_owned();
+        let file = blocking(move || {
+            std::fs::OpenOptions::new()
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)
+        })
+        .await?
+        .map_err(AsyncFileError::Open)?;
+        Ok(Self {
+            file,
+            offset: 0,
+            eof_at: EofMark::default(),
+            stats: IoStats::default(),
+            read_callback: ReadCallback::default(),
+        })
+    }
+
+    // Opens for reading and writing, creating the file if need be. Every
+    // read_at() and write_at() is a submission of its own, so reads and writes at
+    // different offsets can be in flight together on the one file.
+    #[cfg_attr(feature = "tracing", tracing::instrument(level = "debug", err))]
+    pub async fn open_for_read_write(path: &str) -> Result<Self> {
+        let path = path.to_owned();
+        let file = blocking(move || {
+            std::fs::OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create(true)
+                .truncate(false)
+                .open(path)
+        })
+        .await?
+        .map_err(AsyncFileError::Open)?;
+        Ok(Self {
+            file,
+            offset: 0,
+            eof_at: EofMark::default(),
+            stats: IoStats::default(),
+            read_callback: ReadCallback::default(),
+        })
+    }
+
+    // Reads the file from offset 0 to the end, whatever the cursor, one read
+    // submitted at a time into buf, and returns the total. callback is given
+    // the bytes of each read once the reactor has passed its completion back
+    // and this task has been woken, so it can take its time without holding
+    // up other files' completions. An empty buf fails with InvalidInput.
+    pub async fn read_all<F>(&self, buf: &mut [u8], mut callback: F) -> Result<usize>
+    where
+        F: FnMut(&[u8]),
+    {
+        // Reads of nothing would never reach EOF.
+        if buf.is_empty() {
+            return Err(AsyncFileError::Io(io::ErrorKind::InvalidInput.into()));
+        }
+        let mut offset = 0;
+        loop {
+            let n = self.read_at(buf, offset).await?;
+            if n == 0 {
+                return Ok(offset as usize);
+            }
+            callback(&buf[..n]);
+            offset += n as u64;
+        }
+    }
+
+    // A callback for read_all_default() to run once per completed read, given
+    // just the bytes that read brought in, as read_all() runs the one it's
+    // handed. It replaces any set before, and a read_all() call's own
+    // callback still stands in for it there.
+    pub fn set_read_callback(&mut self, callback: impl Fn(&[u8]) + Send + Sync + 'static) {
+        self.read_callback.set(callback);
+    }
+
+    pub fn clear_read_callback(&mut self) {
+        self.read_callback.clear();
+    }
+
+    // read_all() with the callback from set_read_callback(), or with none if
+    // it hasn't been set.
+    pub async fn read_all_default(&self, buf: &mut [u8]) -> Result<usize> {
+        self.read_all(buf, |bytes| self.read_callback.call(bytes))
+            .await
+    }
+
+    // Single read of up to buf.len() bytes at the cursor, which then advances
+    // past them. Returns 0 at EOF.
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = self.read_at(buf, self.offset).await?;
+        self.eof_at.cursor_read(self.offset, buf.len(), n);
+        self.offset += n as u64;
+        Ok(n)
+    }
+
+    // True once a read() at the cursor has come back from the ring with
+    // nothing, until the cursor moves: a seek or a write clears it, as does a
+    // later read() that finds the file has grown. So does a read_at() that
+    // gets data from before where EOF was found.
+    pub fn is_eof(&self) -> bool {
+        self.eof_at.is_at(self.offset)
+    }
+
+    // read() at the cursor, a submission each, until buf is full. If the file
+    // ends first it fails with Eof, with the cursor past whatever was read.
+    pub async fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.read(&mut buf[filled..]).await? {
+                0 => return Err(AsyncFileError::Eof),
+                n => filled += n,
+            }
+        }
+        Ok(())
+    }
+
+    // Bytes between the cursor and the end of the file, if its size can be
+    // had, so read_to_end() can make room for them up front.
+    pub(crate) async fn remaining_len(&self) -> Option<u64> {
+        let len = self.len().await.ok()?;
+        Some(len.saturating_sub(self.offset))
+    }
+
+    // Single read of up to buf.len() bytes at offset, returning 0 at EOF. It
+    // leaves the cursor alone, so any number can be in flight at once.
+    // The kernel reads straight into buf, so dropping the future before the
+    // read completes submits a cancel and blocks until the reactor has seen
+    // the read's completion. Once the drop returns, and the borrow of buf
+    // with it, nothing can write into buf any more. A future leaked with
+    // mem::forget never runs that drop, and is what the owned reads are for.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, buf), fields(len = buf.len()), ret, err)
+    )]
+    pub async fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        self.read_scoped(buf, offset).await
+    }
+
+    // read_at() as a ReadOp, a future with a name that can be kept in a struct
+    // or passed around before it's awaited.
+    pub fn read_scoped<'a>(&'a self, buf: &'a mut [u8], offset: u64) -> ReadOp<'a> {
+        let op = (!buf.is_empty()).then(|| {
+            let len = buf.len().min(u32::MAX as usize) as u32;
+            let entry = opcode::Read::new(types::Fd(self.file.as_raw_fd()), buf.as_mut_ptr(), len)
+                .offset(offset)
+                .build();
+            Op::new(entry)
+        });
+        ReadOp {
+            file: self,
+            offset,
+            op,
+            issued: false,
+            _buf: PhantomData,
+        }
+    }
+
+    // Totals of the reads and writes submitted for this file so far.
+    pub fn stats(&self) -> FileStats {
+        self.stats.snapshot()
+    }
+
+    // Writes all of buf at the current write position, which then advances past it.
+    pub async fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        let written = self.write_at(buf, self.offset).await?;
+        self.offset += written as u64;
+        Ok(written)
+    }
+
+    // Writes all of buf at offset. The cursor is left alone, so writes to
+    // disjoint regions can be in flight at once.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "trace", skip(self, buf), fields(len = buf.len()), ret, err)
+    )]
+    pub async fn write_at(&self, buf: &[u8], offset: u64) -> Result<usize> {
+        let mut written = 0;
+        while written < buf.len() {
+            let rest = &buf[written..];
+            let len = rest.len().min(u32::MAX as usize) as u32;
+            let entry = opcode::Write::new(types::Fd(self.file.as_raw_fd()), rest.as_ptr(), len)
+                .offset(offset + written as u64)
+                .build();
+            self.stats.write_issued();
+            let result = Op::new(entry).await.map_err(write_error);
+            self.stats.record_write(&result);
+            match result? {
+                0 => return Err(AsyncFileError::Io(io::ErrorKind::WriteZero.into())),
+                n => written += n,
+            }
+        }
+        Ok(written)
+    }
+
+    // Moves the cursor that read() and write() use, returning where it ends
+    // up. Seeking from the end asks for the file's size first. A position
+    // before the start of the file fails with InvalidInput.
+    pub async fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        let offset = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => self.offset.checked_add_signed(delta),
+            SeekFrom::End(delta) => self.len().await?.checked_add_signed(delta),
+        };
+        self.offset = offset.ok_or_else(invalid_seek)?;
+        Ok(self.offset)
+    }
+
+    async fn len(&self) -> Result<u64> {
+        let file = self.file.try_clone().map_err(AsyncFileError::Io)?;
+        let len = blocking(move || file.metadata())
+            .await?
+            .map_err(AsyncFileError::Io)?
+            .len();
+        Ok(len)
+    }
+
+    // Waits until everything written so far has reached the disk.
+    pub async fn flush(&self) -> Result<()> {
+        let entry = opcode::Fsync::new(types::Fd(self.file.as_raw_fd())).build();
+        Op::new(entry).await.map_err(write_error)?;
+        Ok(())
+    }
+
+    // Grows or shrinks the file to size bytes. Growing fills with zeros and
+    // shrinking discards the tail.
+    pub async fn set_len(&self, size: u64) -> Result<()> {
+        let file = self.file.try_clone().map_err(AsyncFileError::Io)?;
+        blocking(move || file.set_len(size))
+            .await?
+            .map_err(AsyncFileError::write)
+    }
+
+    pub fn close(self) -> Result<()> {
+        drop(self);
+        Ok(())
+    }
+}
+
+// What an operation AsyncCancel got to first fails with.
+const ECANCELED: i32 = 125;
+
+fn read_error(error: io::Error) -> AsyncFileError {
+    if error.raw_os_error() == Some(ECANCELED) {
+        AsyncFileError::Cancelled
+    } else {
+        AsyncFileError::Io(error)
+    }
+}
+
+fn write_error(error: io::Error) -> AsyncFileError {
+    if error.raw_os_error() == Some(ECANCELED) {
+        AsyncFileError::Cancelled
+    } else {
+        AsyncFileError::write(error)
+    }
+}
+
+// Where the reactor leaves the outcome of one submission. The reactor holds a
+// reference to it, passed through the ring as the entry's user_data, until
+// the completion has been stored.
+#[derive(Default)]
+struct Completion {
+    result: AtomicI32,
+    done: AtomicBool,
+    waker: AtomicWaker,
+}
+
+// One operation on the ring, submitted on first poll. Whatever buffer the
+// entry points at belongs to the kernel until completion, so the caller has
+// to keep it borrowed for as long as the Op lives.
+struct Op {
+    entry: Option<squeue::Entry>,
+    completion: Option<Arc<Completion>>,
+}
+
+impl Op {
+    fn new(entry: squeue::Entry) -> Self {
+        Self {
+            entry: Some(entry),
+            completion: None,
+        }
+    }
+}
+
+impl Future for Op {
+    type Output = io::Result<usize>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        if let Some(entry) = this.entry.take() {
+            let completion = Arc::new(Completion::default());
+            // Registered first, as the completion may land before submit returns.
+            completion.waker.register(cx.waker());
+            let user_data = Arc::into_raw(completion.clone()) as u64;
+            let submitted = reactor().and_then(|r| r.submit(&entry.user_data(user_data)));
+            if let Err(e) = submitted {
+                // Never reached the kernel, so the reactor's reference is ours to drop.
+                drop(unsafe { Arc::from_raw(user_data as *const Completion) });
+                return Poll::Ready(Err(e));
+            }
+            #[cfg(feature = "tracing")]
+            tracing::trace!(op = user_data, "submitted");
+            this.completion = Some(completion);
+        }
+
+        let Some(completion) = &this.completion else {
+            return Poll::Ready(Err(io::ErrorKind::NotConnected.into()));
+        };
+        if !completion.done.load(Ordering::Acquire) {
+            completion.waker.register(cx.waker());
+            if !completion.done.load(Ordering::Acquire) {
+                return Poll::Pending;
+            }
+        }
+
+        let result = completion.result.load(Ordering::Relaxed);
+        Poll::Ready(if result >= 0 {
+            Ok(result as usize)
+        } else {
+            Err(io::Error::from_raw_os_error(-result))
+        })
+    }
+}
+
+impl Drop for Op {
+    // The kernel may still be using the buffer, so an operation in flight is
+    // cancelled and this blocks until the reactor has seen it complete.
+    fn drop(&mut self) {
+        let Some(completion) = &self.completion else {
+            return;
+        };
+        if completion.done.load(Ordering::Acquire) {
+            return;
+        }
+
+        let user_data = Arc::as_ptr(completion) as u64;
+        if let Ok(reactor) = reactor() {
+            let cancel = opcode::AsyncCancel::new(user_data)
+                .build()
+                .user_data(NO_COMPLETION);
+            let _ = reactor.submit(&cancel);
+        }
+        while !completion.done.load(Ordering::Acquire) {
+            std::thread::yield_now();
+        }
+    }
+}
+
+// A read_at() from read_scoped(), submitted at the first poll, that holds on
+// to buf for as long as it lives. Dropping it before the read completes
+// cancels it and blocks until the reactor has seen its completion, as any Op
+// does, so once the drop returns nothing can still be writing into buf.
+// A ReadOp leaked with mem::forget never runs that drop, and is what the
+// owned reads are for.
+pub struct ReadOp<'a> {
+    file: &'a AsyncFile,
+    offset: u64,
+    // None for an empty buf, which needs no read.
+    op: Option<Op>,
+    issued: bool,
+    _buf: PhantomData<&'a mut [u8]>,
+}
+
+impl Future for ReadOp<'_> {
+    type Output = Result<usize>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        let Some(op) = &mut this.op else {
+            return Poll::Ready(Ok(0));
+        };
+        if !std::mem::replace(&mut this.issued, true) {
+            this.file.stats.read_issued();
+        }
+
+        let result = ready!(Pin::new(op).poll(cx)).map_err(read_error);
+        this.file.stats.record_read(&result);
+        let n = result?;
+        this.file.eof_at.read_returned(this.offset, n);
+        Poll::Ready(Ok(n))
+    }
+}
+
+// user_data of entries whose completion nobody is waiting for.
+const NO_COMPLETION: u64 = 0;
+
+// The one ring shared by every file, with a thread of its own waiting on the
+// completion queue and waking whichever task each completion belongs to.
+struct Reactor {
+    ring: IoUring,
+    // Serialises pushes onto the submission queue. Only the reactor thread
+    // reads the completion queue, so that needs no lock.
+    submit_lock: Mutex<()>,
+}
+
+fn reactor() -> io::Result<&'static Reactor> {
+    static REACTOR: OnceLock<std::result::Result<Reactor, i32>> = OnceLock::new();
+
+    let reactor = REACTOR.get_or_init(|| {
+        let ring = IoUring::new(RING_ENTRIES).map_err(|e| e.raw_os_error().unwrap_or(0))?;
+        std::thread::Builder::new()
+            .name("async-file-uring".into())
+            .spawn(run_completions)
+            .map_err(|e| e.raw_os_error().unwrap_or(0))?;
+        Ok(Reactor {
+            ring,
+            submit_lock: Mutex::new(()),
+        })
+    });
+
+    reactor
+        .as_ref()
+        .map_err(|&code| io::Error::from_raw_os_error(code))
+}
+
+impl Reactor {
+    fn submit(&self, entry: &squeue::Entry) -> io::Result<()> {
+        let _guard = self.submit_lock.lock().unwrap_or_else(|e| e.into_inner());
+        loop {
+            // The entry only points at memory the Op keeps borrowed until it
+            // has completed. The queue's tail is published when it's dropped,
+            // at the end of this statement.
+            let pushed = unsafe { self.ring.submission_shared().push(entry) }.is_ok();
+            if pushed {
+                break;
+            }
+            // Full, so hand what's queued to the kernel to make room.
+            self.ring.submitter().submit()?;
+        }
+        self.ring.submitter().submit()?;
+        Ok(())
+    }
+}
+
+fn run_completions() {
+    let Ok(reactor) = reactor() else {
+        return;
+    };
+
+    loop {
+        // Errors here (EINTR, EBUSY while the queue overflows) are passing;
+        // draining the queue and waiting again is all there is to do.
+        let _ = reactor.ring.submitter().submit_and_wait(1);
+
+        for cqe in unsafe { reactor.ring.completion_shared() } {
+            if cqe.user_data() == NO_COMPLETION {
+                continue;
+            }
+            #[cfg(feature = "tracing")]
+            tracing::trace!(op = cqe.user_data(), result = cqe.result(), "completed");
+            let completion = unsafe { Arc::from_raw(cqe.user_data() as *const Completion) };
+            completion.result.store(cqe.result(), Ordering::Relaxed);
+            completion.done.store(true, Ordering::Release);
+            completion.waker.wake();
+        }
+    }
+}
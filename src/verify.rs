@@ -0,0 +1,86 @@
+use crate::{AsyncFile, AsyncFileError, BufferPool, Result};
+
+impl AsyncFile {
+    // write_at() followed by a flush and a read back of the same region, which
+    // must match buf byte for byte or this fails with VerificationFailed at the
+    // first offset that differs. The read back reuses a single buffer from
+    // pool. On a buffered file it may well be served from the cache, so this
+    // catches corruption on the way to the cache more than on the disk. The
+    // file has to be open for reading as well as writing.
+    pub async fn write_verified(
+        &self,
+        buf: &[u8],
+        offset: u64,
+        pool: &BufferPool,
+    ) -> Result<usize> {
+        let written = self.write_at(buf, offset).await?;
+        self.flush().await?;
+
+        let mut back = pool.acquire().await;
+        let mut checked = 0;
+        while checked < written {
+            let at = offset + checked as u64;
+            let len = back.len().min(written - checked);
+            let n = self.read_at(&mut back[..len], at).await?;
+            if n == 0 {
+                // The file ends short of what was written.
+                return Err(AsyncFileError::VerificationFailed(at));
+            }
+            if let Some(i) = back[..n]
+                .iter()
+                .zip(&buf[checked..checked + n])
+                .position(|(read, wrote)| read != wrote)
+            {
+                return Err(AsyncFileError::VerificationFailed(at + i as u64));
+            }
+            checked += n;
+        }
+
+        Ok(written)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_util::{pattern, TempFile};
+    use crate::{AsyncFile, BufferPool};
+
+    // A pool buffer smaller than the write, so the read back takes several
+    // goes.
+    #[tokio::test]
+    async fn matching_write_passes() {
+        let temp = TempFile::new(&[]);
+        let file = AsyncFile::open_for_read_write(temp.path()).await.unwrap();
+        let pool = BufferPool::new(1, 64, 1);
+        let data = pattern(1000);
+
+        assert_eq!(file.write_verified(&data, 10, &pool).await.unwrap(), 1000);
+        assert_eq!(std::fs::read(temp.path()).unwrap()[10..], data);
+        assert_eq!(pool.in_use(), 0);
+    }
+
+    // The write goes to the file, and the read back comes from the mock
+    // with one byte changed, as flaky storage might hand it back.
+    #[cfg(windows)]
+    #[tokio::test]
+    async fn mismatch_is_reported_where_it_is() {
+        use crate::win::mock::{MockCompletion, MockIo};
+        use crate::AsyncFileError;
+
+        let temp = TempFile::new(&[]);
+        let data = pattern(100);
+        let mut corrupted = data.clone();
+        corrupted[37] ^= 0x01;
+        let file = AsyncFile::open_for_read_write(temp.path())
+            .await
+            .unwrap()
+            .with_mock(MockIo::new([MockCompletion::Inline(corrupted)]));
+        let pool = BufferPool::new(1, 4096, 1);
+
+        let result = file.write_verified(&data, 500, &pool).await;
+        assert!(matches!(
+            result,
+            Err(AsyncFileError::VerificationFailed(537))
+        ));
+    }
+}
@@ -0,0 +1,104 @@
+use event_listener::Event;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+// Cancels a group of reads from elsewhere, e.g. when the request they were
+// for is aborted. Clones share the one flag, so a single cancel() reaches
+// every read given any of them, including ones that only start afterwards.
+#[derive(Clone, Debug, Default)]
+pub struct CancelToken {
+    inner: Arc<TokenInner>,
+}
+
+#[derive(Debug, Default)]
+struct TokenInner {
+    cancelled: AtomicBool,
+    event: Event,
+}
+
+impl CancelToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Has every read waiting on the token cancel its ReadFile. Calling it
+    // again does nothing.
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::Release);
+        self.inner.event.notify(usize::MAX);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::Acquire)
+    }
+
+    // Completes once cancel() has been called, straight away if it already has.
+    pub async fn cancelled(&self) {
+        loop {
+            // Registered before the flag is checked, so a cancel() in between
+            // isn't missed.
+            let listener = self.inner.event.listen();
+            if self.is_cancelled() {
+                return;
+            }
+            listener.await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempFile;
+    use crate::win::mock::{MockCompletion, MockIo};
+    use crate::{AsyncFile, AsyncFileError};
+    use futures_util::future::{join, join_all};
+    use std::time::Duration;
+
+    // Reads that would never complete by themselves, so only the token can
+    // end them, and it has to without leaving any of them pending.
+    #[tokio::test]
+    async fn cancel_ends_every_pending_read() {
+        let temp = TempFile::new(&[0; 4096]);
+        let mock = MockIo::new(vec![MockCompletion::Stall; 10]);
+        let file = AsyncFile::open_for_read(temp.path())
+            .await
+            .unwrap()
+            .with_mock(mock.clone());
+        let token = CancelToken::new();
+
+        let mut bufs = [[0; 16]; 10];
+        let reads = bufs
+            .iter_mut()
+            .enumerate()
+            .map(|(i, buf)| file.read_at_cancellable(buf, i as u64 * 16, &token));
+        let (results, ()) = join(join_all(reads), async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            assert_eq!(file.pending_ops(), 10);
+            token.cancel();
+        })
+        .await;
+
+        assert!(results
+            .iter()
+            .all(|r| matches!(r, Err(AsyncFileError::Cancelled))));
+        assert_eq!(file.pending_ops(), 0);
+        assert_eq!(mock.remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn read_after_cancel_fails_at_once() {
+        let temp = TempFile::new(&[0; 16]);
+        let file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+        let token = CancelToken::new();
+        token.cancel();
+        token.cancelled().await;
+
+        let mut buf = [0; 16];
+        assert!(matches!(
+            file.read_at_cancellable(&mut buf, 0, &token).await,
+            Err(AsyncFileError::Cancelled)
+        ));
+        assert_eq!(file.stats().reads(), 0);
+    }
+}
@@ -0,0 +1,327 @@
+use super::{handle_of, AsyncFile, AsyncFileError, AsyncOpenOptions, Result};
+use crate::blocking::blocking;
+use crate::{BufferPool, PooledBuffer};
+use futures_util::future::join;
+use futures_util::stream::{FuturesOrdered, StreamExt};
+use std::ffi::{c_void, OsStr};
+use std::fs::File;
+use std::os::windows::ffi::OsStrExt;
+use std::sync::mpsc::Sender;
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{HANDLE, MAX_PATH};
+use windows::Win32::Storage::FileSystem::{
+    CopyFileExW, GetDiskFreeSpaceW, GetVolumeInformationW, GetVolumePathNameW,
+    LPPROGRESS_ROUTINE_CALLBACK_REASON,
+};
+use windows::Win32::System::Ioctl::{DUPLICATE_EXTENTS_DATA, FSCTL_DUPLICATE_EXTENTS_TO_FILE};
+use windows::Win32::System::SystemServices::FILE_SUPPORTS_BLOCK_REFCOUNTING;
+use windows::Win32::System::WindowsProgramming::PROGRESS_CONTINUE;
+use windows::Win32::System::IO::DeviceIoControl;
+
+// Size of each block and how many of them are in flight at once.
+const COPY_BLOCK: usize = 1024 * 256;
+const COPY_DEPTH: usize = 3;
+
+// Most one FSCTL_DUPLICATE_EXTENTS_TO_FILE is asked to clone, well under its
+// 4 GiB limit and a whole number of clusters of any size ReFS uses.
+const CLONE_CHUNK: u64 = 1024 * 1024 * 1024;
+
+// How transfer() went about a copy.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CopyMethod {
+    // ReFS block cloning: dst shares src's clusters until either is written,
+    // so nothing is read or written at all.
+    Clone,
+    // CopyFileEx, which may offload the copy to the storage or, on shares,
+    // the server.
+    System,
+    // copy()'s own overlapped reads and writes.
+    Pipelined,
+}
+
+// Copies src to dst, replacing whatever dst held, and returns the number of
+// bytes copied. COPY_DEPTH pooled buffers circulate between reads and the
+// write: one is being written while the others are being filled from further
+// ahead in src, and a buffer goes back to the reads as soon as its write
+// finishes.
+pub async fn copy(src: &str, dst: &str) -> Result<u64> {
+    copy_pipelined(src, dst, None).await
+}
+
+// copy() by the quickest means to hand, with the running total of bytes
+// copied sent down progress as it goes. Within one volume that's cloning where
+// the file system supports it and CopyFileEx otherwise, and between volumes
+// it's copy()'s pipeline. A clone the file system refuses, e.g. because src
+// is sparse and dst isn't, falls back to CopyFileEx. CopyFileEx copies
+// attributes and alternate streams too, which the others don't. Both run on
+// the blocking pool, and carry on to the end if the future is dropped. As
+// with read_with_progress(), the copy carries on if the receiver has gone.
+pub async fn transfer(src: &str, dst: &str, progress: Sender<u64>) -> Result<(u64, CopyMethod)> {
+    let (from, to) = (src.to_owned(), dst.to_owned());
+    let volume = blocking(move || shared_volume(&from, &to)).await??;
+    if volume == SharedVolume::No {
+        let total = copy_pipelined(src, dst, Some(&progress)).await?;
+        return Ok((total, CopyMethod::Pipelined));
+    }
+
+    if let SharedVolume::Cloning(cluster_size) = volume {
+        let (from, to, sender) = (src.to_owned(), dst.to_owned(), progress.clone());
+        if let Some(total) =
+            blocking(move || clone_file(&from, &to, cluster_size, &sender)).await??
+        {
+            return Ok((total, CopyMethod::Clone));
+        }
+    }
+
+    let (from, to) = (src.to_owned(), dst.to_owned());
+    let total = blocking(move || system_copy(&from, &to, progress)).await??;
+    Ok((total, CopyMethod::System))
+}
+
+// The pipeline behind copy(), reporting the total so far after each write.
+async fn copy_pipelined(src: &str, dst: &str, progress: Option<&Sender<u64>>) -> Result<u64> {
+    let src = AsyncFile::open_for_read(src).await?;
+    let mut dst = AsyncOpenOptions::new()
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(dst)
+        .await?;
+
+    let pool = BufferPool::new(COPY_DEPTH, COPY_BLOCK, 1);
+    let mut reads = FuturesOrdered::new();
+    let mut offset = 0;
+    for _ in 0..COPY_DEPTH {
+        reads.push_back(read_block(&src, &pool, offset));
+        offset += COPY_BLOCK as u64;
+    }
+
+    let mut total = 0;
+    let mut next = reads.next().await;
+    while let Some((buf, result)) = next {
+        // Blocks complete in order, so the first empty one is the end of src.
+        let n = result?;
+        if n == 0 {
+            break;
+        }
+
+        let (written, following) = join(dst.write(&buf[..n]), reads.next()).await;
+        total += written? as u64;
+        if let Some(progress) = progress {
+            let _ = progress.send(total);
+        }
+
+        drop(buf);
+        reads.push_back(read_block(&src, &pool, offset));
+        offset += COPY_BLOCK as u64;
+        next = following;
+    }
+
+    // Dropping the queue cancels the reads that ran past the end.
+    drop(reads);
+    dst.close()?;
+    src.close()?;
+    Ok(total)
+}
+
+// A read into a buffer of its own, handed back with the result and returned
+// to the pool once whatever it held has been written.
+async fn read_block(
+    src: &AsyncFile,
+    pool: &BufferPool,
+    offset: u64,
+) -> (PooledBuffer, Result<usize>) {
+    let mut buf = pool.acquire().await;
+    let result = src.read_at(&mut buf, offset).await;
+    (buf, result)
+}
+
+// Whether two paths are on the same volume, and what it can do if they are.
+#[derive(PartialEq, Eq)]
+enum SharedVolume {
+    No,
+    Yes,
+    // It can clone blocks, in ranges a multiple of this cluster size.
+    Cloning(u64),
+}
+
+fn shared_volume(src: &str, dst: &str) -> Result<SharedVolume> {
+    let root = volume_root(src)?;
+    if root != volume_root(dst)? {
+        return Ok(SharedVolume::No);
+    }
+
+    let mut flags = 0;
+    unsafe {
+        GetVolumeInformationW(
+            PCWSTR(root.as_ptr()),
+            None,
+            None,
+            None,
+            Some(&mut flags),
+            None,
+        )
+    }?;
+    if flags & FILE_SUPPORTS_BLOCK_REFCOUNTING == 0 {
+        return Ok(SharedVolume::Yes);
+    }
+
+    let (mut sectors_per_cluster, mut bytes_per_sector) = (0, 0);
+    unsafe {
+        GetDiskFreeSpaceW(
+            PCWSTR(root.as_ptr()),
+            Some(&mut sectors_per_cluster),
+            Some(&mut bytes_per_sector),
+            None,
+            None,
+        )
+    }?;
+    Ok(SharedVolume::Cloning(
+        sectors_per_cluster as u64 * bytes_per_sector as u64,
+    ))
+}
+
+// The root of the volume path is on, mount points included, nul-terminated.
+// path needn't exist yet.
+fn volume_root(path: &str) -> Result<Vec<u16>> {
+    let path = wide(path);
+    let mut root = vec![0u16; MAX_PATH as usize + 1];
+    unsafe { GetVolumePathNameW(PCWSTR(path.as_ptr()), &mut root) }?;
+    let len = root.iter().position(|&c| c == 0).unwrap_or(root.len());
+    root.truncate(len);
+    // Drive letters may come back in either case.
+    Ok(wide(&String::from_utf16_lossy(&root).to_uppercase()))
+}
+
+// Clones src into dst a chunk at a time, or returns None having done nothing
+// to dst but size it if the file system refuses the very first chunk.
+fn clone_file(
+    src: &str,
+    dst: &str,
+    cluster_size: u64,
+    progress: &Sender<u64>,
+) -> Result<Option<u64>> {
+    let src = File::open(src).map_err(AsyncFileError::Open)?;
+    let len = src.metadata().map_err(AsyncFileError::Io)?.len();
+    let dst = std::fs::OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(dst)
+        .map_err(AsyncFileError::Open)?;
+    // Cloning only maps clusters into dst, so they have to be there already.
+    dst.set_len(len).map_err(AsyncFileError::Io)?;
+
+    let mut offset = 0;
+    while offset < len {
+        let count = (len - offset).min(CLONE_CHUNK);
+        // Only the last chunk can end off a cluster boundary, and as it ends
+        // at EOF it may be rounded up past it.
+        let data = DUPLICATE_EXTENTS_DATA {
+            FileHandle: handle_of(&src),
+            SourceFileOffset: offset as i64,
+            TargetFileOffset: offset as i64,
+            ByteCount: count.next_multiple_of(cluster_size) as i64,
+        };
+        let mut returned = 0;
+        let cloned = unsafe {
+            DeviceIoControl(
+                handle_of(&dst),
+                FSCTL_DUPLICATE_EXTENTS_TO_FILE,
+                Some(&data as *const _ as *const c_void),
+                std::mem::size_of::<DUPLICATE_EXTENTS_DATA>() as u32,
+                None,
+                0,
+                Some(&mut returned),
+                None,
+            )
+        };
+        match cloned {
+            Ok(()) => {}
+            Err(_) if offset == 0 => return Ok(None),
+            Err(e) => return Err(e.into()),
+        }
+
+        offset += count;
+        let _ = progress.send(offset);
+    }
+    Ok(Some(len))
+}
+
+// CopyFileEx, replacing dst, with its progress callbacks forwarded to progress.
+fn system_copy(src: &str, dst: &str, progress: Sender<u64>) -> Result<u64> {
+    let (from, to) = (wide(src), wide(dst));
+    unsafe {
+        CopyFileExW(
+            PCWSTR(from.as_ptr()),
+            PCWSTR(to.as_ptr()),
+            Some(copy_progress),
+            Some(&progress as *const Sender<u64> as *const c_void),
+            None,
+            0,
+        )
+    }?;
+    // The callback's totals take in alternate streams, so the length reported
+    // is the file's own, as with the other methods.
+    Ok(std::fs::metadata(dst).map_err(AsyncFileError::Io)?.len())
+}
+
+// Called back on the thread inside CopyFileEx, which holds on to progress
+// until it returns.
+unsafe extern "system" fn copy_progress(
+    _total_size: i64,
+    transferred: i64,
+    _stream_size: i64,
+    _stream_transferred: i64,
+    _stream: u32,
+    _reason: LPPROGRESS_ROUTINE_CALLBACK_REASON,
+    _src: HANDLE,
+    _dst: HANDLE,
+    data: *const c_void,
+) -> u32 {
+    let progress = &*(data as *const Sender<u64>);
+    let _ = progress.send(transferred as u64);
+    PROGRESS_CONTINUE
+}
+
+fn wide(s: &str) -> Vec<u16> {
+    OsStr::new(s).encode_wide().chain(Some(0)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{pattern, TempFile};
+    use std::sync::mpsc::channel;
+
+    // Not a whole number of blocks or clusters, over a longer file that has
+    // to be cut down. Both ends are in the temp directory, so on one volume
+    // and whichever of cloning or CopyFileEx it allows.
+    #[tokio::test]
+    async fn transfer_copies_every_byte() {
+        let data = pattern(3 * COPY_BLOCK + 1234);
+        let src = TempFile::new(&data);
+        let dst = TempFile::new(&pattern(4 * COPY_BLOCK));
+
+        let (progress, updates) = channel();
+        let (total, method) = transfer(src.path(), dst.path(), progress).await.unwrap();
+        assert_eq!(total, data.len() as u64);
+        assert_ne!(method, CopyMethod::Pipelined);
+        assert_eq!(updates.try_iter().last(), Some(total));
+        assert_eq!(std::fs::read(dst.path()).unwrap(), data);
+    }
+
+    #[tokio::test]
+    async fn copy_copies_every_byte() {
+        let data = pattern(3 * COPY_BLOCK + 1234);
+        let src = TempFile::new(&data);
+        let dst = TempFile::new(&pattern(4 * COPY_BLOCK));
+
+        assert_eq!(
+            copy(src.path(), dst.path()).await.unwrap(),
+            data.len() as u64
+        );
+        assert_eq!(std::fs::read(dst.path()).unwrap(), data);
+    }
+}
@@ -0,0 +1,96 @@
+use super::{handle_of, AsyncFile, AsyncFileError, Result};
+use crate::blocking::blocking;
+use std::io;
+use std::os::windows::fs::OpenOptionsExt;
+use windows::Win32::Storage::FileSystem::{
+    FILE_FLAG_NO_BUFFERING, FILE_FLAG_OVERLAPPED, FILE_SHARE_READ, FILE_SHARE_WRITE,
+};
+use windows::Win32::System::Ioctl::{DISK_GEOMETRY, IOCTL_DISK_GET_DRIVE_GEOMETRY};
+use windows::Win32::System::IO::DeviceIoControl;
+
+impl AsyncFile {
+    // Opens a disk or a volume itself for reading, e.g. \\.\PhysicalDrive0 or
+    // \\.\C:, bypassing any file system on it. That takes an elevated process,
+    // and without it this fails with ElevationRequired. Devices are always
+    // read unbuffered, so every read must be aligned to the sector size, as
+    // from aligned_buffer(). Others may go on using the device meanwhile, so a
+    // mounted volume can change underneath. metadata() doesn't know a
+    // device's length.
+    pub async fn open_device(path: &str) -> Result<Self> {
+        // Both opens go to the blocking pool, as AsyncOpenOptions::open() does.
+        let path = path.to_owned();
+        let (device, sector_size) = blocking(move || -> Result<_> {
+            let device = std::fs::OpenOptions::new()
+                .read(true)
+                .share_mode(FILE_SHARE_READ.0 | FILE_SHARE_WRITE.0)
+                .custom_flags(FILE_FLAG_OVERLAPPED.0 | FILE_FLAG_NO_BUFFERING.0)
+                .open(&path)
+                .map_err(open_error)?;
+            Ok((device, device_sector_size(&path)?))
+        })
+        .await??;
+
+        let mut file = AsyncFile::bind(device)?;
+        file.sector_size = Some(sector_size);
+        Ok(file)
+    }
+}
+
+fn open_error(error: io::Error) -> AsyncFileError {
+    if error.kind() == io::ErrorKind::PermissionDenied {
+        AsyncFileError::ElevationRequired
+    } else {
+        AsyncFileError::Open(error)
+    }
+}
+
+// A device has no FileStorageInfo to give, so the sector size comes from its
+// geometry instead. The IOCTL is asked of a handle of its own, opened neither
+// overlapped nor with any access, which is all a geometry query needs and lets
+// it finish before returning.
+fn device_sector_size(path: &str) -> Result<u32> {
+    let device = std::fs::OpenOptions::new()
+        .access_mode(0)
+        .share_mode(FILE_SHARE_READ.0 | FILE_SHARE_WRITE.0)
+        .open(path)
+        .map_err(open_error)?;
+
+    let mut geometry = DISK_GEOMETRY::default();
+    unsafe {
+        DeviceIoControl(
+            handle_of(&device),
+            IOCTL_DISK_GET_DRIVE_GEOMETRY,
+            None,
+            0,
+            Some(&mut geometry as *mut _ as *mut _),
+            std::mem::size_of::<DISK_GEOMETRY>() as u32,
+            None,
+            None,
+        )
+    }?;
+
+    Ok(geometry.BytesPerSector)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Run with --ignored from an elevated prompt. The first sector of a disk
+    // holds its MBR, or the protective one in front of a GPT, and both end in
+    // the boot signature.
+    #[tokio::test]
+    #[ignore = "needs an elevated process"]
+    async fn reads_the_first_sector_of_a_drive() {
+        let drive = AsyncFile::open_device(r"\\.\PhysicalDrive0").await.unwrap();
+        let mut sector = drive.aligned_buffer(512);
+        let n = drive.read_at(&mut sector, 0).await.unwrap();
+        assert_eq!(n, sector.len());
+        assert_eq!(sector[510..512], [0x55, 0xaa]);
+
+        assert!(matches!(
+            drive.read_at(&mut sector[..100], 0).await,
+            Err(AsyncFileError::Unaligned(_))
+        ));
+    }
+}
@@ -0,0 +1,186 @@
+use super::options::{extended_path, too_long};
+use super::{win32_code, AsyncFileError, Result};
+use crate::blocking::blocking;
+use futures_core::Stream;
+use std::collections::VecDeque;
+use std::ffi::{OsStr, OsString};
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::{ERROR_FILE_NOT_FOUND, ERROR_NO_MORE_FILES, HANDLE};
+use windows::Win32::Storage::FileSystem::{
+    FindClose, FindExInfoBasic, FindExSearchNameMatch, FindFirstFileExW, FindNextFileW,
+    FILE_ATTRIBUTE_DIRECTORY, FILE_ATTRIBUTE_HIDDEN, FILE_ATTRIBUTE_SYSTEM,
+    FIND_FIRST_EX_LARGE_FETCH, WIN32_FIND_DATAW,
+};
+
+// Entries fetched per trip to the blocking pool.
+const DIR_BATCH: usize = 128;
+
+// One entry of a directory listed by read_dir().
+#[derive(Clone, Debug)]
+pub struct DirEntry {
+    name: OsString,
+    len: u64,
+    attributes: u32,
+}
+
+impl DirEntry {
+    pub fn file_name(&self) -> &OsStr {
+        &self.name
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn is_dir(&self) -> bool {
+        self.attributes & FILE_ATTRIBUTE_DIRECTORY.0 != 0
+    }
+
+    pub fn is_hidden(&self) -> bool {
+        self.attributes & FILE_ATTRIBUTE_HIDDEN.0 != 0
+    }
+
+    pub fn is_system(&self) -> bool {
+        self.attributes & FILE_ATTRIBUTE_SYSTEM.0 != 0
+    }
+}
+
+// The entries of the directory at path, hidden and system ones included but
+// not "." and "..". The Find APIs only block, so they run on the blocking
+// pool, DIR_BATCH entries at a time. The stream ends after the first error.
+pub fn read_dir(path: &str) -> impl Stream<Item = Result<DirEntry>> {
+    let state = DirState {
+        search: Search::NotStarted(search_pattern(path)),
+        entries: VecDeque::new(),
+        error: None,
+    };
+
+    futures_util::stream::unfold(state, |mut state| async move {
+        loop {
+            if let Some(entry) = state.entries.pop_front() {
+                return Some((Ok(entry), state));
+            }
+            if let Some(error) = state.error.take() {
+                return Some((Err(error), state));
+            }
+            if let Search::Done = state.search {
+                return None;
+            }
+
+            let search = std::mem::replace(&mut state.search, Search::Done);
+            match blocking(move || next_batch(search)).await {
+                Ok((search, entries, error)) => {
+                    state.search = search;
+                    state.entries = entries.into();
+                    state.error = error;
+                }
+                Err(error) => state.error = Some(error),
+            }
+        }
+    })
+}
+
+struct DirState {
+    search: Search,
+    entries: VecDeque<DirEntry>,
+    error: Option<AsyncFileError>,
+}
+
+enum Search {
+    // Nul-terminated path\* pattern for FindFirstFileExW.
+    NotStarted(Vec<u16>),
+    Open(FindHandle),
+    Done,
+}
+
+// Closed on drop, which is how an abandoned stream lets go of the search.
+pub(super) struct FindHandle(pub(super) HANDLE);
+
+// The handle is only used by whichever blocking pool thread has the search.
+unsafe impl Send for FindHandle {}
+
+impl Drop for FindHandle {
+    fn drop(&mut self) {
+        let _ = unsafe { FindClose(self.0) };
+    }
+}
+
+fn search_pattern(path: &str) -> Vec<u16> {
+    let dir = path.trim_end_matches(['\\', '/']);
+    let pattern = format!(r"{dir}\*");
+    let pattern = if too_long(&pattern) {
+        extended_path(&pattern).map_or(pattern.into(), |p| p.into_os_string())
+    } else {
+        pattern.into()
+    };
+    pattern.encode_wide().chain(Some(0)).collect()
+}
+
+// Moves the search on by up to DIR_BATCH entries, starting it first if need
+// be. Any error ends the search, after the entries found before it.
+fn next_batch(mut search: Search) -> (Search, Vec<DirEntry>, Option<AsyncFileError>) {
+    let mut entries = Vec::new();
+    let mut data = WIN32_FIND_DATAW::default();
+
+    if let Search::NotStarted(pattern) = &search {
+        let result = unsafe {
+            FindFirstFileExW(
+                PCWSTR(pattern.as_ptr()),
+                FindExInfoBasic,
+                &mut data as *mut WIN32_FIND_DATAW as *mut _,
+                FindExSearchNameMatch,
+                None,
+                FIND_FIRST_EX_LARGE_FETCH,
+            )
+        };
+        match result {
+            Ok(handle) => {
+                search = Search::Open(FindHandle(handle));
+                push_entry(&mut entries, &data);
+            }
+            // Nothing matched, which only happens for an empty root directory.
+            Err(error) if win32_code(&error) == ERROR_FILE_NOT_FOUND => {
+                return (Search::Done, entries, None)
+            }
+            Err(error) => return (Search::Done, entries, Some(error.into())),
+        }
+    }
+
+    let Search::Open(handle) = &search else {
+        return (search, entries, None);
+    };
+    while entries.len() < DIR_BATCH {
+        match unsafe { FindNextFileW(handle.0, &mut data) } {
+            Ok(()) => push_entry(&mut entries, &data),
+            Err(error) if win32_code(&error) == ERROR_NO_MORE_FILES => {
+                return (Search::Done, entries, None)
+            }
+            Err(error) => return (Search::Done, entries, Some(error.into())),
+        }
+    }
+
+    (search, entries, None)
+}
+
+fn push_entry(entries: &mut Vec<DirEntry>, data: &WIN32_FIND_DATAW) {
+    let len = data
+        .cFileName
+        .iter()
+        .position(|&c| c == 0)
+        .unwrap_or(data.cFileName.len());
+    let name = &data.cFileName[..len];
+    if name == [b'.' as u16] || name == [b'.' as u16, b'.' as u16] {
+        return;
+    }
+
+    entries.push(DirEntry {
+        name: OsString::from_wide(name),
+        len: (data.nFileSizeHigh as u64) << 32 | data.nFileSizeLow as u64,
+        attributes: data.dwFileAttributes,
+    });
+}
@@ -0,0 +1,97 @@
+use super::{AsyncFile, Result};
+use digest::{Digest, Output};
+#[cfg(feature = "tokio")]
+use std::io;
+#[cfg(feature = "tokio")]
+use std::pin::Pin;
+#[cfg(feature = "tokio")]
+use std::task::{ready, Context, Poll};
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncRead, ReadBuf};
+
+// Wraps an AsyncFile and feeds every byte read through it to a hasher, so a
+// digest of the file comes out of the same pass that reads it.
+pub struct HashingRead<H> {
+    file: AsyncFile,
+    hasher: H,
+}
+
+impl<H: Digest> HashingRead<H> {
+    pub fn new(file: AsyncFile) -> Self {
+        Self {
+            file,
+            hasher: H::new(),
+        }
+    }
+
+    // AsyncFile::read(), hashing just the bytes it returns.
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        let n = self.file.read(buf).await?;
+        self.hasher.update(&buf[..n]);
+        Ok(n)
+    }
+
+    // Digest of everything read so far, which is the whole file once a read
+    // has returned 0.
+    pub fn finalize(self) -> Output<H> {
+        self.hasher.finalize()
+    }
+
+    pub fn into_inner(self) -> AsyncFile {
+        self.file
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<H: Digest + Unpin> AsyncRead for HashingRead<H> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let start = buf.filled().len();
+        ready!(Pin::new(&mut this.file).poll_read(cx, buf))?;
+        this.hasher.update(&buf.filled()[start..]);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempFile;
+    use sha2::Sha256;
+
+    const ABC_SHA256: &str = "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad";
+
+    fn hex(digest: &[u8]) -> String {
+        digest.iter().map(|b| format!("{b:02x}")).collect()
+    }
+
+    // Two bytes at a time, so the last read comes back short and only the
+    // byte it returned may be hashed, not the stale one after it.
+    #[tokio::test]
+    async fn hashes_just_the_bytes_read() {
+        let temp = TempFile::new(b"abc");
+        let file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+        let mut reader = HashingRead::<Sha256>::new(file);
+        let mut buf = [0; 2];
+        while reader.read(&mut buf).await.unwrap() != 0 {}
+        assert_eq!(hex(&reader.finalize()), ABC_SHA256);
+    }
+
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn hashes_through_async_read() {
+        use tokio::io::AsyncReadExt;
+
+        let temp = TempFile::new(b"abc");
+        let file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+        let mut reader = HashingRead::<Sha256>::new(file);
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).await.unwrap();
+        assert_eq!(out, b"abc");
+        assert_eq!(hex(&reader.finalize()), ABC_SHA256);
+    }
+}
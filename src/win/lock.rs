@@ -0,0 +1,213 @@
+use super::{
+    completion_code, handle_of, win32_code, AsyncFile, AsyncFileError, OverlappedWrap, Result,
+};
+use std::fs::File;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::atomic::Ordering;
+use std::task::{ready, Context, Poll};
+use windows::core::Error;
+use windows::Win32::Foundation::{ERROR_IO_PENDING, ERROR_LOCK_VIOLATION, WIN32_ERROR};
+use windows::Win32::Storage::FileSystem::{
+    LockFileEx, UnlockFileEx, LOCKFILE_EXCLUSIVE_LOCK, LOCKFILE_FAIL_IMMEDIATELY, LOCK_FILE_FLAGS,
+};
+use windows::Win32::System::IO::OVERLAPPED;
+
+impl AsyncFile {
+    // Takes an advisory lock on len bytes from offset, waiting for as long as
+    // another handle holds a conflicting one. Shared locks only conflict with
+    // exclusive ones. The lock is released when the guard is dropped.
+    pub async fn lock_range(
+        &self,
+        offset: u64,
+        len: u64,
+        exclusive: bool,
+    ) -> Result<FileLockGuard<'_>> {
+        self.lock(offset, len, exclusive, false).await
+    }
+
+    // lock_range() that fails with WouldBlock rather than waiting.
+    pub async fn try_lock_range(
+        &self,
+        offset: u64,
+        len: u64,
+        exclusive: bool,
+    ) -> Result<FileLockGuard<'_>> {
+        self.lock(offset, len, exclusive, true).await
+    }
+
+    async fn lock(
+        &self,
+        offset: u64,
+        len: u64,
+        exclusive: bool,
+        immediate: bool,
+    ) -> Result<FileLockGuard<'_>> {
+        let mut flags = LOCK_FILE_FLAGS(0);
+        if exclusive {
+            flags |= LOCKFILE_EXCLUSIVE_LOCK;
+        }
+        if immediate {
+            flags |= LOCKFILE_FAIL_IMMEDIATELY;
+        }
+
+        LockFuture {
+            file: &self.handle,
+            // Not counted in pending_ops(), but polled for like any other
+            // operation if the file is.
+            overlapped: Box::new(OverlappedWrap {
+                polled: self.polled,
+                ..OverlappedWrap::default()
+            }),
+            offset,
+            len,
+            flags,
+            state: LockState::Start,
+        }
+        .await
+        .map_err(|code| match code {
+            ERROR_LOCK_VIOLATION if immediate => AsyncFileError::WouldBlock,
+            code => AsyncFileError::Win32(code),
+        })?;
+
+        Ok(FileLockGuard {
+            file: &self.handle,
+            offset,
+            len,
+        })
+    }
+}
+
+// A byte range locked by lock_range(), unlocked on drop.
+pub struct FileLockGuard<'a> {
+    file: &'a File,
+    offset: u64,
+    len: u64,
+}
+
+impl<'a> Drop for FileLockGuard<'a> {
+    fn drop(&mut self) {
+        unlock(self.file, self.offset, self.len);
+    }
+}
+
+// Unlocking never waits, and unlike locking it queues no completion packet,
+// so an OVERLAPPED on the stack that only carries the offset will do.
+fn unlock(file: &File, offset: u64, len: u64) {
+    let mut o = OVERLAPPED::default();
+    o.Anonymous.Anonymous.Offset = offset as u32;
+    o.Anonymous.Anonymous.OffsetHigh = (offset >> 32) as u32;
+    let _ = unsafe { UnlockFileEx(handle_of(file), 0, len as u32, (len >> 32) as u32, &mut o) };
+}
+
+#[derive(PartialEq)]
+enum LockState {
+    Start,
+    Pending,
+    Finished,
+}
+
+struct LockFuture<'a> {
+    file: &'a File,
+    overlapped: Box<OverlappedWrap>,
+    offset: u64,
+    len: u64,
+    flags: LOCK_FILE_FLAGS,
+    state: LockState,
+}
+
+impl<'a> Future for LockFuture<'a> {
+    type Output = std::result::Result<(), WIN32_ERROR>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        if this.state == LockState::Start {
+            this.overlapped.o.Anonymous.Anonymous.Offset = this.offset as u32;
+            this.overlapped.o.Anonymous.Anonymous.OffsetHigh = (this.offset >> 32) as u32;
+            this.overlapped.arm(cx.waker());
+
+            let result = unsafe {
+                LockFileEx(
+                    handle_of(this.file),
+                    this.flags,
+                    0,
+                    this.len as u32,
+                    (this.len >> 32) as u32,
+                    &mut this.overlapped.o,
+                )
+            };
+
+            match result {
+                // Granted on the spot, so no completion is queued for it.
+                Ok(()) => this.overlapped.complete_inline(),
+                // Granted or not, a queued lock reports back through the callback.
+                Err(error) if error == Error::from(ERROR_IO_PENDING) => {}
+                Err(error) => {
+                    this.overlapped.disarm();
+                    this.state = LockState::Finished;
+                    return Poll::Ready(Err(win32_code(&error)));
+                }
+            }
+            this.state = LockState::Pending;
+        }
+
+        let (err, _) = ready!(this.overlapped.poll_complete(cx));
+        this.state = LockState::Finished;
+        if err != 0 {
+            return Poll::Ready(Err(completion_code(err)));
+        }
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<'a> Drop for LockFuture<'a> {
+    fn drop(&mut self) {
+        if self.state != LockState::Pending {
+            return;
+        }
+
+        // A lock granted after all is released again, as no guard is coming.
+        self.overlapped.cancel(self.file);
+        if self.overlapped.done.load(Ordering::Acquire)
+            && self.overlapped.err.load(Ordering::Relaxed) == 0
+        {
+            unlock(self.file, self.offset, self.len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempFile;
+
+    // Two handles on the one file, as two processes would have, the lock held
+    // from a task of its own.
+    #[tokio::test]
+    async fn exclusive_lock_turns_away_an_immediate_one() {
+        let temp = TempFile::new(&[0; 4096]);
+        let holder = AsyncFile::open_for_read(temp.path()).await.unwrap();
+        let other = AsyncFile::open_for_read(temp.path()).await.unwrap();
+
+        let (held, is_held) = tokio::sync::oneshot::channel();
+        let (done, finished) = tokio::sync::oneshot::channel::<()>();
+        let holding = tokio::spawn(async move {
+            let _guard = holder.lock_range(0, 100, true).await.unwrap();
+            held.send(()).unwrap();
+            let _ = finished.await;
+        });
+        is_held.await.unwrap();
+
+        assert!(matches!(
+            other.try_lock_range(50, 10, false).await,
+            Err(AsyncFileError::WouldBlock)
+        ));
+        // Outside the locked range there's nothing to wait for.
+        drop(other.try_lock_range(100, 10, true).await.unwrap());
+
+        done.send(()).unwrap();
+        holding.await.unwrap();
+        drop(other.try_lock_range(0, 100, true).await.unwrap());
+    }
+}
@@ -0,0 +1,46 @@
+use super::{AsyncFile, AsyncFileError, Result};
+use crate::blocking::blocking;
+use std::time::SystemTime;
+
+// What metadata() reports about an open file.
+#[derive(Clone, Debug)]
+pub struct FileMetadata {
+    len: u64,
+    created: Option<SystemTime>,
+    modified: Option<SystemTime>,
+}
+
+impl FileMetadata {
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn created(&self) -> Option<SystemTime> {
+        self.created
+    }
+
+    pub fn modified(&self) -> Option<SystemTime> {
+        self.modified
+    }
+}
+
+impl AsyncFile {
+    // Size and times of the open file. The query is a blocking syscall, so it
+    // runs on the blocking pool against a duplicate of the handle.
+    pub async fn metadata(&self) -> Result<FileMetadata> {
+        let file = self.handle.try_clone().map_err(AsyncFileError::Io)?;
+        let metadata = blocking(move || file.metadata())
+            .await?
+            .map_err(AsyncFileError::Io)?;
+
+        Ok(FileMetadata {
+            len: metadata.len(),
+            created: metadata.created().ok(),
+            modified: metadata.modified().ok(),
+        })
+    }
+}
@@ -0,0 +1,173 @@
+use super::{handle_of, AsyncFile, AsyncFileError, Result};
+use crate::blocking::blocking;
+use std::sync::Arc;
+use windows::core::{Error, PCWSTR};
+use windows::Win32::Foundation::{CloseHandle, ERROR_FILE_TOO_LARGE};
+use windows::Win32::System::Memory::{
+    CreateFileMappingW, MapViewOfFile, UnmapViewOfFile, FILE_MAP_READ, MEMORY_MAPPED_VIEW_ADDRESS,
+    PAGE_READONLY,
+};
+
+// Granularity read_at() touches the mapping at to fault it in. Pages are never
+// smaller, and larger ones just mean a few touches that cost nothing.
+const PAGE_SIZE: usize = 4096;
+
+// A read-only view of a whole file, mapped into memory. Reads become copies
+// out of the mapping, so there's no ReadFile or completion per read, which
+// pays off for lots of small reads scattered over a big file. The mapping is
+// of the file as it was when mapped: it doesn't grow with the file, and a file
+// cut short underneath it makes reads past the new end fault.
+pub struct AsyncMmap {
+    view: Option<Arc<View>>,
+}
+
+impl AsyncMmap {
+    // Maps the whole of file, which must be open for reading. The view keeps
+    // the file's data alive on its own, so file can be dropped afterwards.
+    pub async fn new(file: &AsyncFile) -> Result<Self> {
+        let len = file.metadata().await?.len();
+        // The Win32 mapping calls refuse an empty file.
+        if len == 0 {
+            return Ok(Self { view: None });
+        }
+        let len = usize::try_from(len).map_err(|_| AsyncFileError::Win32(ERROR_FILE_TOO_LARGE))?;
+
+        let handle = handle_of(&file.handle);
+        let mapping =
+            unsafe { CreateFileMappingW(handle, None, PAGE_READONLY, 0, 0, PCWSTR::null())? };
+        let address = unsafe { MapViewOfFile(mapping, FILE_MAP_READ, 0, 0, 0) };
+        // The view holds on to the mapping, so its handle isn't needed past here.
+        let mapped: Result<View> = if address.Value.is_null() {
+            Err(Error::from_win32().into())
+        } else {
+            Ok(View { address, len })
+        };
+        let _ = unsafe { CloseHandle(mapping) };
+
+        Ok(Self {
+            view: Some(Arc::new(mapped?)),
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.view.as_ref().map_or(0, |view| view.len)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // The whole file, without a copy. Touching a page that isn't resident
+    // faults it in from disk there and then, blocking whichever thread it is,
+    // so this suits callers away from the runtime or sure of a warm cache. A
+    // read error while faulting raises an in-page exception rather than
+    // returning one.
+    pub fn as_slice(&self) -> &[u8] {
+        match &self.view {
+            Some(view) => unsafe { view.as_slice() },
+            None => &[],
+        }
+    }
+
+    // Copies up to buf.len() bytes from offset, returning 0 at or past the end
+    // of the mapping. The range is faulted in on the blocking pool first, so a
+    // cold read waits there rather than on the runtime thread. A page the
+    // system evicts again before the copy faults on the caller's thread after
+    // all, which only costs time.
+    pub async fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        let Some(view) = &self.view else {
+            return Ok(0);
+        };
+        let Some(start) = usize::try_from(offset).ok().filter(|&o| o < view.len) else {
+            return Ok(0);
+        };
+        let n = buf.len().min(view.len - start);
+        if n == 0 {
+            return Ok(0);
+        }
+
+        let touched = view.clone();
+        blocking(move || touched.fault_in(start, n)).await?;
+
+        let data = unsafe { view.as_slice() };
+        buf[..n].copy_from_slice(&data[start..start + n]);
+        Ok(n)
+    }
+}
+
+struct View {
+    address: MEMORY_MAPPED_VIEW_ADDRESS,
+    len: usize,
+}
+
+// The view is read-only memory that lives until the last Arc is dropped, so
+// any thread may read it.
+unsafe impl Send for View {}
+unsafe impl Sync for View {}
+
+impl View {
+    // Safety: reading through the slice can fault, see AsyncMmap::as_slice().
+    unsafe fn as_slice(&self) -> &[u8] {
+        std::slice::from_raw_parts(self.address.Value as *const u8, self.len)
+    }
+
+    // Reads a byte from every page of start..start + len, which brings each
+    // one into memory if it isn't already.
+    fn fault_in(&self, start: usize, len: usize) {
+        let data = unsafe { self.as_slice() };
+        let first = start - start % PAGE_SIZE;
+        for at in (first..start + len).step_by(PAGE_SIZE) {
+            unsafe { std::ptr::read_volatile(&data[at]) };
+        }
+    }
+}
+
+impl Drop for View {
+    fn drop(&mut self) {
+        let _ = unsafe { UnmapViewOfFile(self.address) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{pattern, TempFile};
+
+    // Whatever the offset and length, a read from the mapping has to see just
+    // what ReadFile does over the same file, ends and page boundaries included.
+    #[tokio::test]
+    async fn reads_match_read_at() {
+        let data = pattern(3 * PAGE_SIZE + 123);
+        let temp = TempFile::new(&data);
+        let file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+        let mmap = AsyncMmap::new(&file).await.unwrap();
+        assert_eq!(mmap.len(), data.len());
+        assert_eq!(mmap.as_slice(), data);
+
+        let len = data.len() as u64;
+        let ranges = [
+            (0, 10),
+            (4090, 12),
+            (0, 20_000),
+            (len - 5, 100),
+            (len, 10),
+            (len + 1, 1),
+        ];
+        for (offset, n) in ranges {
+            let (mut mapped, mut read) = (vec![0; n], vec![0; n]);
+            let a = mmap.read_at(&mut mapped, offset).await.unwrap();
+            let b = file.read_at(&mut read, offset).await.unwrap();
+            assert_eq!(a, b, "at {offset}");
+            assert_eq!(mapped[..a], read[..b]);
+        }
+    }
+
+    #[tokio::test]
+    async fn empty_file_maps_to_nothing() {
+        let temp = TempFile::new(&[]);
+        let file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+        let mmap = AsyncMmap::new(&file).await.unwrap();
+        assert!(mmap.is_empty());
+        assert_eq!(mmap.read_at(&mut [0; 8], 0).await.unwrap(), 0);
+    }
+}
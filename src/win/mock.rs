@@ -0,0 +1,224 @@
+use super::{waker_callback, OverlappedWrap};
+use std::collections::{HashMap, VecDeque};
+use std::sync::mpsc::{self, RecvTimeoutError, Sender};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use windows::core::Error;
+use windows::Win32::Foundation::{ERROR_IO_PENDING, ERROR_OPERATION_ABORTED, WIN32_ERROR};
+use windows::Win32::System::IO::OVERLAPPED;
+
+// How a scripted read turns out, standing in for what ReadFile and the
+// completion callback would have done.
+#[derive(Clone, Debug)]
+pub enum MockCompletion {
+    // ReadFile succeeds on the spot with these bytes, and no callback follows.
+    Inline(Vec<u8>),
+    // ReadFile goes pending, and the callback delivers these bytes from a
+    // thread of its own once delay has passed.
+    Callback(Vec<u8>, Duration),
+    // ReadFile fails on the spot with this code.
+    Refused(WIN32_ERROR),
+    // ReadFile goes pending, and the callback reports this code after delay.
+    // Codes are passed on as given, so an NTSTATUS such as
+    // STATUS_END_OF_FILE works as it would from the thread pool.
+    Failed(u32, Duration),
+    // ReadFile goes pending, and the callback only comes once the read is
+    // cancelled, i.e. its future dropped.
+    Stall,
+}
+
+// A script of read completions an AsyncHandle plays out in place of calling
+// ReadFile, one per read, through the same OverlappedWrap states and
+// waker_callback as real ones. Everything above that, poll_read, retries,
+// timeouts, write_verified(), runs as it would against a disk, which lets it
+// be exercised, races and cancellation included, without a disk's timing.
+// Once the script runs out reads go to the handle as normal, and writes
+// always do, so a real file underneath supplies whatever isn't scripted.
+// Attach one with AsyncHandle::with_mock().
+#[derive(Debug, Default)]
+pub struct MockIo {
+    script: Mutex<VecDeque<MockCompletion>>,
+    // Callback threads of scripted reads still pending, by the address of
+    // their OVERLAPPED, for cancel() to reach as CancelIoEx would.
+    pending: Mutex<HashMap<usize, Sender<()>>>,
+}
+
+impl MockIo {
+    pub fn new(script: impl IntoIterator<Item = MockCompletion>) -> Arc<Self> {
+        Arc::new(Self {
+            script: Mutex::new(script.into_iter().collect()),
+            pending: Mutex::default(),
+        })
+    }
+
+    // Adds a completion to the end of the script.
+    pub fn push(&self, completion: MockCompletion) {
+        self.script.lock().unwrap().push_back(completion);
+    }
+
+    // Completions not yet played.
+    pub fn remaining(&self) -> usize {
+        self.script.lock().unwrap().len()
+    }
+
+    // What ReadFile would have returned for the next completion in the
+    // script, with any bytes already in buf and, for one that goes pending, a
+    // thread to play the callback. None once the script has run out.
+    pub(super) fn read(
+        self: &Arc<Self>,
+        wrap: &mut OverlappedWrap,
+        buf: &mut [u8],
+    ) -> Option<windows::core::Result<()>> {
+        let completion = self.script.lock().unwrap().pop_front()?;
+
+        let (data, err, delay) = match completion {
+            MockCompletion::Inline(data) => {
+                let n = data.len().min(buf.len());
+                buf[..n].copy_from_slice(&data[..n]);
+                wrap.o.InternalHigh = n;
+                return Some(Ok(()));
+            }
+            MockCompletion::Refused(code) => return Some(Err(code.into())),
+            MockCompletion::Callback(data, delay) => (data, 0, Some(delay)),
+            MockCompletion::Failed(err, delay) => (Vec::new(), err, Some(delay)),
+            MockCompletion::Stall => (Vec::new(), 0, None),
+        };
+
+        let (cancel, cancelled) = mpsc::channel();
+        // Both outlive the thread, as nothing lets go of an OVERLAPPED, or
+        // the buffer it was given, until its callback has run.
+        let overlapped = &mut wrap.o as *mut OVERLAPPED as usize;
+        let (ptr, len) = (buf.as_mut_ptr() as usize, buf.len());
+        self.pending.lock().unwrap().insert(overlapped, cancel);
+
+        let mock = self.clone();
+        std::thread::spawn(move || {
+            let woken = match delay {
+                Some(delay) => cancelled.recv_timeout(delay),
+                None => cancelled.recv().map_err(|_| RecvTimeoutError::Disconnected),
+            };
+            let (err, n) = match woken {
+                Err(RecvTimeoutError::Timeout) => {
+                    let n = data.len().min(len);
+                    unsafe { std::ptr::copy_nonoverlapping(data.as_ptr(), ptr as *mut u8, n) };
+                    (err, n as u32)
+                }
+                _ => (ERROR_OPERATION_ABORTED.0, 0),
+            };
+            // Before the callback, as the OVERLAPPED is free to be reused
+            // for another read once it's run.
+            mock.pending.lock().unwrap().remove(&overlapped);
+            unsafe { waker_callback(err, n, overlapped as *mut OVERLAPPED) };
+        });
+        Some(Err(Error::from(ERROR_IO_PENDING)))
+    }
+
+    // CancelIoEx for a scripted read still pending on overlapped, returning
+    // false if there's none, in which case it was a real ReadFile (or has
+    // completed already).
+    pub(super) fn cancel(&self, overlapped: &OVERLAPPED) -> bool {
+        let key = overlapped as *const OVERLAPPED as usize;
+        match self.pending.lock().unwrap().remove(&key) {
+            Some(cancel) => {
+                let _ = cancel.send(());
+                true
+            }
+            None => false,
+        }
+    }
+
+    // CancelIoEx with no OVERLAPPED, for every scripted read pending.
+    pub(super) fn cancel_all(&self) {
+        for (_, cancel) in self.pending.lock().unwrap().drain() {
+            let _ = cancel.send(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempFile;
+    use crate::{AsyncFile, AsyncFileError};
+    use windows::Win32::Foundation::{ERROR_ACCESS_DENIED, STATUS_END_OF_FILE};
+
+    async fn mocked(temp: &TempFile, script: Vec<MockCompletion>) -> (AsyncFile, Arc<MockIo>) {
+        let mock = MockIo::new(script);
+        let file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+        (file.with_mock(mock.clone()), mock)
+    }
+
+    #[tokio::test]
+    async fn callback_delivers_after_delay() {
+        let temp = TempFile::new(&[0; 16]);
+        let delay = Duration::from_millis(20);
+        let (file, _mock) = mocked(
+            &temp,
+            vec![MockCompletion::Callback(b"hello".to_vec(), delay)],
+        )
+        .await;
+
+        let mut buf = [0; 16];
+        assert_eq!(file.read_at(&mut buf, 0).await.unwrap(), 5);
+        assert_eq!(&buf[..5], b"hello");
+        assert_eq!(file.pending_ops(), 0);
+    }
+
+    #[tokio::test]
+    async fn inline_and_refused_complete_on_the_spot() {
+        let temp = TempFile::new(&[0; 16]);
+        let script = vec![
+            MockCompletion::Inline(b"abc".to_vec()),
+            MockCompletion::Refused(ERROR_ACCESS_DENIED),
+        ];
+        let (file, _mock) = mocked(&temp, script).await;
+
+        let mut buf = [0; 16];
+        assert_eq!(file.read_at(&mut buf, 0).await.unwrap(), 3);
+        assert!(matches!(
+            file.read_at(&mut buf, 0).await,
+            Err(AsyncFileError::Read(ERROR_ACCESS_DENIED))
+        ));
+    }
+
+    #[tokio::test]
+    async fn failed_eof_reads_as_zero() {
+        let temp = TempFile::new(&[1; 16]);
+        let script = vec![MockCompletion::Failed(
+            STATUS_END_OF_FILE.0 as u32,
+            Duration::ZERO,
+        )];
+        let (file, _mock) = mocked(&temp, script).await;
+
+        let mut buf = [0; 16];
+        assert_eq!(file.read_at(&mut buf, 0).await.unwrap(), 0);
+    }
+
+    // Dropping the future has to cancel the read and wait for its callback,
+    // so nothing is left pending and buf is free again.
+    #[tokio::test]
+    async fn stalled_read_is_cancelled_on_drop() {
+        let temp = TempFile::new(&[0; 16]);
+        let (file, mock) = mocked(&temp, vec![MockCompletion::Stall]).await;
+
+        let mut buf = [0; 16];
+        let read = file.read_at(&mut buf, 0);
+        assert!(tokio::time::timeout(Duration::from_millis(20), read)
+            .await
+            .is_err());
+        assert_eq!(file.pending_ops(), 0);
+        assert_eq!(mock.remaining(), 0);
+    }
+
+    #[tokio::test]
+    async fn script_runs_out_to_the_file() {
+        let temp = TempFile::new(b"from the disk");
+        let (file, _mock) = mocked(&temp, vec![MockCompletion::Inline(b"mock".to_vec())]).await;
+
+        let mut buf = [0; 32];
+        assert_eq!(file.read_at(&mut buf, 0).await.unwrap(), 4);
+        assert_eq!(&buf[..4], b"mock");
+        let n = file.read_at(&mut buf, 0).await.unwrap();
+        assert_eq!(&buf[..n], b"from the disk");
+    }
+}
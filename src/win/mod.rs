@@ -0,0 +1,2275 @@
+use futures_core::Stream;
+use futures_util::task::AtomicWaker;
+use std::fs::File;
+use std::future::Future;
+use std::io::{self, IoSlice, IoSliceMut, Seek, SeekFrom};
+use std::mem::MaybeUninit;
+use std::os::windows::io::{AsRawHandle, FromRawHandle};
+use std::pin::{pin, Pin};
+use std::ptr;
+use std::sync::atomic::{fence, AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::mpsc::Sender;
+use std::sync::Arc;
+use std::task::{ready, Context, Poll, Waker};
+#[cfg(feature = "tokio")]
+use std::time::{Duration, Instant};
+#[cfg(feature = "tokio")]
+use tokio::io::{AsyncRead, ReadBuf};
+use windows::core::Error;
+use windows::Wdk::Storage::FileSystem::{
+    FileAccessInformation, FileModeInformation, NtQueryInformationFile, FILE_ACCESS_INFORMATION,
+    FILE_INFORMATION_CLASS, FILE_MODE_INFORMATION, FILE_SYNCHRONOUS_IO_ALERT,
+    FILE_SYNCHRONOUS_IO_NONALERT,
+};
+use windows::Win32::Foundation::{
+    ERROR_HANDLE_EOF, ERROR_INVALID_PARAMETER, ERROR_IO_PENDING, ERROR_NOT_FOUND, HANDLE,
+    STATUS_END_OF_FILE, STATUS_PENDING, WIN32_ERROR,
+};
+use windows::Win32::Storage::FileSystem::{
+    FileStorageInfo, FlushFileBuffers, GetFileInformationByHandleEx, ReOpenFile, ReadFile,
+    SetEndOfFile, SetFileCompletionNotificationModes, SetFilePointerEx, WriteFile,
+    FILE_APPEND_DATA, FILE_BEGIN, FILE_FLAG_OVERLAPPED, FILE_SHARE_DELETE, FILE_SHARE_MODE,
+    FILE_SHARE_READ, FILE_SHARE_WRITE, FILE_STORAGE_INFO, FILE_WRITE_DATA,
+};
+use windows::Win32::System::WindowsProgramming::FILE_SKIP_COMPLETION_PORT_ON_SUCCESS;
+use windows::Win32::System::IO::{
+    BindIoCompletionCallback, CancelIoEx, IO_STATUS_BLOCK, OVERLAPPED,
+};
+
+use crate::blocking::blocking;
+use crate::callback::ReadCallback;
+use crate::eof::EofMark;
+use crate::error::{completion_code, invalid_seek, win32_code};
+use crate::limiter::{IoLimiter, IoPermit};
+use crate::stats::IoStats;
+use crate::{AlignedBuffer, AsyncFileError, FileStats, Result};
+
+mod cancel;
+mod copy;
+mod device;
+mod dir;
+#[cfg(feature = "digest")]
+mod hashing;
+mod lock;
+mod metadata;
+mod mmap;
+#[cfg(any(test, feature = "mock"))]
+pub(crate) mod mock;
+mod options;
+mod pipe;
+mod port;
+mod retry;
+mod stdin;
+mod streams;
+mod transmit;
+mod watch;
+
+pub use cancel::CancelToken;
+pub use copy::{copy, transfer, CopyMethod};
+pub use dir::{read_dir, DirEntry};
+#[cfg(feature = "digest")]
+pub use hashing::HashingRead;
+pub use lock::FileLockGuard;
+pub use metadata::FileMetadata;
+pub use mmap::AsyncMmap;
+#[cfg(all(test, not(feature = "mock")))]
+use mock::MockIo;
+#[cfg(feature = "mock")]
+pub use mock::{MockCompletion, MockIo};
+pub use options::{AccessPattern, AsyncOpenOptions};
+pub use pipe::NamedPipeClient;
+pub use port::{CompletionPort, CompletionPriority};
+pub use retry::RetryPolicy;
+pub use stdin::AsyncStdin;
+pub use streams::{list_streams, StreamInfo};
+pub use transmit::SocketHandle;
+pub use watch::{ChangeEvent, ChangeKind, DirectoryWatcher};
+
+// Every handle is bound with FILE_SKIP_COMPLETION_PORT_ON_SUCCESS, so an
+// operation that succeeds straight away queues no completion and its callback
+// never runs. Whoever issued it must call complete_inline() and take the result
+// there and then. Only ERROR_IO_PENDING means a callback is coming, and until
+// it has run the OVERLAPPED and buffer stay with the kernel.
+
+// Outcome of the raw ReadFile/WriteFile steps, before it's known which
+// AsyncFileError variant a failure belongs to.
+type Win32Result<T> = std::result::Result<T, WIN32_ERROR>;
+
+// Overlapped I/O on any handle that supports it: files, pipes, sockets. The
+// submit/await machinery all lives here, with the file-only parts (opening,
+// metadata, locking, resizing) on AsyncFile.
+pub struct AsyncHandle<H: AsRawHandle = File> {
+    handle: H,
+    // Cursor shared by write() and poll_read().
+    offset: u64,
+    // Where the cursor was when a read there last found EOF.
+    eof_at: EofMark,
+    // Read issued by poll_read, created on first use.
+    read_state: Option<Box<ReadState>>,
+    // Set when opened unbuffered, as reads must then cover whole sectors.
+    sector_size: Option<u32>,
+    // Shared cap on reads in flight, if the file was opened with one.
+    limiter: Option<IoLimiter>,
+    // Whether write() appends rather than writing at the cursor.
+    append: bool,
+    // Whether and how read() and read_at() retry transient failures.
+    retry: Option<RetryPolicy>,
+    stats: IoStats,
+    // Run by read_all_default() for each completed read.
+    read_callback: ReadCallback,
+    // Reads and writes issued and not yet completed. Shared with their
+    // OVERLAPPEDs, as the callback takes them off and the file may have been
+    // moved in the meantime.
+    ops: Arc<AtomicUsize>,
+    // Whether the handle was left unbound, its operations polled for rather
+    // than woken from a callback.
+    polled: bool,
+    // Kept alive while completions for this file may be queued to it.
+    _port: Option<Arc<CompletionPort>>,
+    // Script that reads play out before going to the handle.
+    #[cfg(any(test, feature = "mock"))]
+    mock: Option<Arc<MockIo>>,
+}
+
+// Asynchronous file I/O wrapper for Windows
+pub type AsyncFile = AsyncHandle<File>;
+
+// Largest single ReadFile issued on behalf of poll_read.
+const POLL_READ_CHUNK: usize = 1024 * 64;
+
+// Offset and OffsetHigh both 0xFFFFFFFF, which has WriteFile write at the end
+// of the file. Each WriteFile lands whole, even with other appenders about.
+const APPEND_OFFSET: u64 = u64::MAX;
+
+// poll_read can't hand the kernel the caller's ReadBuf, as the next poll may
+// bring a different one, so it reads into this buffer and copies out. Boxed so
+// the OVERLAPPED keeps its address while a read is in flight, since AsyncHandle
+// is Unpin and free to move between polls.
+#[derive(Default)]
+struct ReadState {
+    overlapped: OverlappedWrap,
+    buf: Vec<u8>,
+    // Bytes in buf[pos..filled] have been read but not yet handed out.
+    pos: usize,
+    filled: usize,
+    in_flight: bool,
+    // Why a read failed, returned to every poll from then on.
+    error: Option<WIN32_ERROR>,
+}
+
+#[repr(C)]
+#[derive(Default)]
+pub struct OverlappedWrap {
+    o: OVERLAPPED,
+    // Filled in by the callback, which runs on a thread pool thread.
+    len: AtomicU32,
+    err: AtomicU32,
+    waker: AtomicWaker,
+    // Set by the callback once len and err hold the outcome.
+    done: AtomicBool,
+    // Set while the kernel owns the OVERLAPPED, cleared by the callback as the
+    // last thing it does with it.
+    pending: AtomicBool,
+    // The file's count of operations in flight, which this one joins for as
+    // long as pending is set, if it's counted at all.
+    ops: Option<Arc<AtomicUsize>>,
+    // Set for a file with no callback bound, whose operations are instead
+    // found to have finished by looking at the OVERLAPPED on each poll.
+    polled: bool,
+    // Milliseconds until a polled operation still in flight is looked at
+    // again, doubling each time up to POLL_NUDGE_MAX.
+    nudge: AtomicU32,
+    // The file's script, if it has one, for reads to play out in place of
+    // ReadFile.
+    #[cfg(any(test, feature = "mock"))]
+    mock: Option<Arc<MockIo>>,
+}
+
+// Longest wait, in milliseconds, between looks at a polled operation.
+const POLL_NUDGE_MAX: u32 = 16;
+
+// The raw pointers in OVERLAPPED are only ever used by the kernel, and
+// everything the callback shares with the owner goes through atomics. That
+// lets reads on one file be spread over tasks on different threads.
+unsafe impl Send for OverlappedWrap {}
+unsafe impl Sync for OverlappedWrap {}
+
+unsafe extern "system" fn waker_callback(
+    dwerrorcode: u32,
+    dwnumberofbytestransfered: u32,
+    lpoverlapped: *mut OVERLAPPED,
+) {
+    #[cfg(feature = "tracing")]
+    tracing::trace!(
+        op = lpoverlapped as usize,
+        error = dwerrorcode,
+        bytes = dwnumberofbytestransfered,
+        "completed"
+    );
+    let wrap_ptr: *mut OverlappedWrap = lpoverlapped as *mut OverlappedWrap;
+    let wrap: &OverlappedWrap = &*wrap_ptr;
+    wrap.err.store(dwerrorcode, Ordering::Relaxed);
+    wrap.len.store(dwnumberofbytestransfered, Ordering::Relaxed);
+    wrap.done.store(true, Ordering::Release);
+    // A poll registering after this take() will see done and not wait
+    let waker = wrap.waker.take();
+    wrap.settle();
+    // wrap may be freed as soon as this is seen, so don't touch it again
+    wrap.pending.store(false, Ordering::Release);
+    if let Some(waker) = waker {
+        waker.wake();
+    }
+}
+
+impl OverlappedWrap {
+    // One whose operations count towards ops while they're in flight, and
+    // with polled, that has no callback coming and is polled instead.
+    fn counted(ops: &Arc<AtomicUsize>, polled: bool) -> Self {
+        Self {
+            ops: Some(ops.clone()),
+            polled,
+            ..Self::default()
+        }
+    }
+
+    // Parks the waker for the callback. Must happen before the operation is
+    // issued, as the callback can run before ReadFile/WriteFile even returns.
+    fn arm(&mut self, waker: &Waker) {
+        // The previous callback may have published its result but not quite
+        // let go of the OVERLAPPED yet.
+        while self.pending.load(Ordering::Acquire) {
+            std::hint::spin_loop();
+        }
+
+        self.waker.register(waker);
+        self.err.store(0, Ordering::Relaxed);
+        self.len.store(0, Ordering::Relaxed);
+        self.done.store(false, Ordering::Relaxed);
+        self.nudge.store(1, Ordering::Relaxed);
+        self.pending.store(true, Ordering::Relaxed);
+        if let Some(ops) = &self.ops {
+            ops.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    // Undoes arm() when the operation failed to queue, so no callback is coming.
+    fn disarm(&mut self) {
+        self.waker.take();
+        self.settle();
+        self.pending.store(false, Ordering::Relaxed);
+    }
+
+    // Takes a finished operation off the count. Must come before pending is
+    // cleared, as the file and its count may be gone once it is.
+    fn settle(&self) {
+        if let Some(ops) = &self.ops {
+            ops.fetch_sub(1, Ordering::Release);
+        }
+    }
+
+    // Records an operation that succeeded synchronously the way the callback
+    // would have, as none is coming. The kernel leaves the byte count in the
+    // OVERLAPPED itself.
+    fn complete_inline(&mut self) {
+        self.waker.take();
+        self.err.store(0, Ordering::Relaxed);
+        self.len
+            .store(self.o.InternalHigh as u32, Ordering::Relaxed);
+        self.done.store(true, Ordering::Relaxed);
+        self.settle();
+        self.pending.store(false, Ordering::Release);
+    }
+
+    // Ready with the error code and byte count once the callback has run (or
+    // straight away if nothing was issued). Until then the waker from the
+    // latest poll replaces the parked one, as the task may have moved.
+    fn poll_complete(&self, cx: &mut Context<'_>) -> Poll<(u32, u32)> {
+        if self.polled {
+            if !self.reap() {
+                self.nudge(cx.waker());
+                return Poll::Pending;
+            }
+        } else if self.pending.load(Ordering::Acquire) && !self.done.load(Ordering::Acquire) {
+            self.waker.register(cx.waker());
+            if !self.done.load(Ordering::Acquire) {
+                return Poll::Pending;
+            }
+        }
+
+        Poll::Ready((
+            self.err.load(Ordering::Relaxed),
+            self.len.load(Ordering::Relaxed),
+        ))
+    }
+
+    // Whether a polled operation is over, recording its outcome the way the
+    // callback would have if it has only now been found to be, as
+    // GetOverlappedResult() without waiting would. The kernel leaves the
+    // status in Internal, STATUS_PENDING until it's done, and the byte count
+    // in InternalHigh. Only the owner polls, so there's no racing another
+    // thread to it.
+    fn reap(&self) -> bool {
+        if !self.pending.load(Ordering::Acquire) {
+            return true;
+        }
+        let status = unsafe { ptr::read_volatile(&self.o.Internal) };
+        if status == STATUS_PENDING.0 as usize {
+            return false;
+        }
+        fence(Ordering::Acquire);
+
+        let len = unsafe { ptr::read_volatile(&self.o.InternalHigh) } as u32;
+        #[cfg(feature = "tracing")]
+        tracing::trace!(
+            op = &self.o as *const OVERLAPPED as usize,
+            error = status as u32,
+            bytes = len,
+            "completed, found by polling"
+        );
+        self.err.store(status as u32, Ordering::Relaxed);
+        self.len.store(len, Ordering::Relaxed);
+        self.done.store(true, Ordering::Release);
+        self.settle();
+        self.pending.store(false, Ordering::Release);
+        true
+    }
+
+    // With nothing to wake a polled operation's task, has it woken after a
+    // while to look again, waiting longer each time it's still in flight.
+    // Outside a tokio runtime, or without the tokio feature, there's no timer
+    // to hand, so the task is simply woken again at once.
+    fn nudge(&self, waker: &Waker) {
+        let delay = self.nudge.load(Ordering::Relaxed).max(1);
+        self.nudge
+            .store((delay * 2).min(POLL_NUDGE_MAX), Ordering::Relaxed);
+        #[cfg(feature = "tokio")]
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            let waker = waker.clone();
+            runtime.spawn(async move {
+                tokio::time::sleep(Duration::from_millis(delay as u64)).await;
+                waker.wake();
+            });
+            return;
+        }
+        waker.wake_by_ref();
+    }
+
+    // Issues a ReadFile of buf at offset, parking the waker for the callback.
+    // Returns false if the read hit EOF straight away, in which case nothing
+    // was queued and the callback won't run. A read that finished on the spot
+    // is already complete, so poll_read_result() is ready at once.
+    fn start_read(
+        &mut self,
+        handle: &impl AsRawHandle,
+        buf: &mut [MaybeUninit<u8>],
+        offset: u64,
+        waker: &Waker,
+    ) -> Win32Result<bool> {
+        self.o.Anonymous.Anonymous.Offset = offset as u32;
+        self.o.Anonymous.Anonymous.OffsetHigh = (offset >> 32) as u32;
+
+        self.arm(waker);
+
+        // ReadFile only ever writes to the buffer, so it can be handed memory
+        // that hasn't been initialised.
+        let buf =
+            unsafe { std::slice::from_raw_parts_mut(buf.as_mut_ptr().cast::<u8>(), buf.len()) };
+        let result = self.read_file(handle_of(handle), buf);
+
+        match result {
+            Ok(()) => {
+                self.complete_inline();
+                Ok(true)
+            }
+            Err(error) if error == Error::from(ERROR_IO_PENDING) => Ok(true),
+            Err(error) => {
+                self.disarm();
+                if is_eof(win32_code(&error).0) {
+                    Ok(false)
+                } else {
+                    Err(win32_code(&error))
+                }
+            }
+        }
+    }
+
+    // ReadFile of buf at the offset already set in the OVERLAPPED, which must
+    // have been armed. A file with a mock plays the next scripted completion
+    // instead, while there is one.
+    fn read_file(&mut self, handle: HANDLE, buf: &mut [u8]) -> windows::core::Result<()> {
+        #[cfg(feature = "tracing")]
+        let len = buf.len();
+        let result = self.issue_read(handle, buf);
+        #[cfg(feature = "tracing")]
+        self.trace_issued("ReadFile", len, &result);
+        result
+    }
+
+    fn issue_read(&mut self, handle: HANDLE, buf: &mut [u8]) -> windows::core::Result<()> {
+        #[cfg(any(test, feature = "mock"))]
+        if let Some(result) = self.mock.clone().and_then(|mock| mock.read(self, buf)) {
+            return result;
+        }
+        unsafe { ReadFile(handle, Some(buf), None, Some(&mut self.o)) }
+    }
+
+    // WriteFile of buf at the offset already set in the OVERLAPPED, which must
+    // have been armed.
+    fn write_file(&mut self, handle: HANDLE, buf: &[u8]) -> windows::core::Result<()> {
+        let result = unsafe { WriteFile(handle, Some(buf), None, Some(&mut self.o)) };
+        #[cfg(feature = "tracing")]
+        self.trace_issued("WriteFile", buf.len(), &result);
+        result
+    }
+
+    // An event for each ReadFile or WriteFile as it returns. op is the
+    // OVERLAPPED's address, which the completion's own event carries as well,
+    // so the two can be matched up in a trace.
+    #[cfg(feature = "tracing")]
+    fn trace_issued(&self, call: &'static str, len: usize, result: &windows::core::Result<()>) {
+        let op = &self.o as *const OVERLAPPED as usize;
+        let offset = unsafe {
+            (self.o.Anonymous.Anonymous.OffsetHigh as u64) << 32
+                | self.o.Anonymous.Anonymous.Offset as u64
+        };
+        match result {
+            Ok(()) => tracing::trace!(
+                call,
+                op,
+                offset,
+                len,
+                bytes = self.o.InternalHigh,
+                "completed on the spot"
+            ),
+            Err(error) if *error == Error::from(ERROR_IO_PENDING) => {
+                tracing::trace!(call, op, offset, len, "pending")
+            }
+            Err(error) => tracing::debug!(
+                call,
+                op,
+                offset,
+                len,
+                error = win32_code(error).0,
+                "refused"
+            ),
+        }
+    }
+
+    // Makes sure the kernel is done with the OVERLAPPED (and the buffer it was
+    // given) before they are freed. An operation still in flight is cancelled
+    // and this blocks until its callback has run.
+    fn cancel(&mut self, handle: &impl AsRawHandle) {
+        if !self.pending.load(Ordering::Acquire) {
+            return;
+        }
+
+        self.request_cancel(handle);
+        while if self.polled {
+            !self.reap()
+        } else {
+            self.pending.load(Ordering::Acquire)
+        } {
+            std::thread::yield_now();
+        }
+    }
+
+    // Asks for an operation in flight to be cancelled without waiting for it.
+    // Its callback still runs, with ERROR_OPERATION_ABORTED unless the
+    // operation managed to finish first.
+    fn request_cancel(&self, handle: &impl AsRawHandle) {
+        if self.pending.load(Ordering::Acquire) {
+            #[cfg(any(test, feature = "mock"))]
+            if self.mock.as_ref().is_some_and(|mock| mock.cancel(&self.o)) {
+                return;
+            }
+            // Fails if the operation has already completed, but then the
+            // callback is on its way regardless.
+            let _ = unsafe { CancelIoEx(handle_of(handle), Some(&self.o)) };
+        }
+    }
+
+    // Outcome of a finished read, mapping EOF to zero bytes read.
+    fn poll_read_result(&self, cx: &mut Context<'_>) -> Poll<Win32Result<usize>> {
+        let (err, len) = ready!(self.poll_complete(cx));
+        Poll::Ready(read_outcome(err, len))
+    }
+}
+
+impl AsyncFile {
+    pub async fn open_for_read(path: &str) -> Result<Self> {
+        AsyncOpenOptions::new().read(true).open(path).await
+    }
+
+    pub async fn open_for_write(path: &str) -> Result<Self> {
+        AsyncOpenOptions::new()
+            .write(true)
+            .create(true)
+            .open(path)
+            .await
+    }
+
+    // Opens for reading and writing, creating the file if need be. Every
+    // read_at() and write_at() has its own OVERLAPPED, so reads and writes at
+    // different offsets can be in flight together on the one handle.
+    pub async fn open_for_read_write(path: &str) -> Result<Self> {
+        AsyncOpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .open(path)
+            .await
+    }
+
+    // Opens for reading with FILE_FLAG_NO_BUFFERING, bypassing the page cache.
+    // Every read must then use a buffer, length and offset aligned to the
+    // volume's sector size; aligned_buffer() hands out suitable buffers.
+    pub async fn open_unbuffered(path: &str) -> Result<Self> {
+        AsyncOpenOptions::new()
+            .read(true)
+            .no_buffering(true)
+            .open(path)
+            .await
+    }
+
+    // Takes over a handle opened elsewhere, e.g. a pipe or one inherited from
+    // a parent process, and binds it the same way open_for_read() does. It's
+    // unsafe as handle must be open, opened with FILE_FLAG_OVERLAPPED (which
+    // is checked) and the caller's own, never to be used by them again; it's
+    // closed here if this fails.
+    #[allow(clippy::missing_safety_doc)]
+    pub unsafe fn from_raw_handle(handle: HANDLE) -> Result<Self> {
+        Self::new(File::from_raw_handle(handle.0))
+    }
+
+    // Upgrades a std File, however it was opened. One opened with
+    // FILE_FLAG_OVERLAPPED is taken over as it is. A synchronous handle can't
+    // be switched to overlapped in place, so the file is opened a second time,
+    // overlapped and with the same access, through ReOpenFile, and the original
+    // closed. That needs no path, only sharing on the original that lets the
+    // second handle in. The cursor, and append mode, carry over.
+    pub fn from_std(file: File) -> Result<Self> {
+        if is_overlapped(&file)? {
+            return Self::new(file);
+        }
+
+        let offset = (&file).stream_position().map_err(AsyncFileError::Io)?;
+        let access = granted_access(&file)?;
+        let share = FILE_SHARE_MODE(FILE_SHARE_READ.0 | FILE_SHARE_WRITE.0 | FILE_SHARE_DELETE.0);
+        let handle = unsafe { ReOpenFile(handle_of(&file), access, share, FILE_FLAG_OVERLAPPED) }
+            .map_err(|e| AsyncFileError::Open(e.into()))?;
+        drop(file);
+
+        let mut reopened = Self::new(unsafe { File::from_raw_handle(handle.0) })?;
+        reopened.offset = offset;
+        reopened.append = access & FILE_APPEND_DATA.0 != 0 && access & FILE_WRITE_DATA.0 == 0;
+        Ok(reopened)
+    }
+
+    // Bytes between the cursor and the end of the file, if its size can be
+    // had, so read_to_end() can make room for them up front.
+    pub(crate) async fn remaining_len(&self) -> Option<u64> {
+        let len = self.metadata().await.ok()?.len();
+        Some(len.saturating_sub(self.offset))
+    }
+
+    // Moves the cursor that read() and write() use, returning where it ends
+    // up. Seeking from the end asks for the file's size first. A position
+    // before the start of the file fails with InvalidInput and leaves the
+    // cursor alone. A failed read poll_read was holding on to is forgotten.
+    pub async fn seek(&mut self, pos: SeekFrom) -> Result<u64> {
+        self.rewind_read_state().await;
+        if let Some(state) = self.read_state.as_deref_mut() {
+            state.error = None;
+        }
+
+        let offset = match pos {
+            SeekFrom::Start(offset) => Some(offset),
+            SeekFrom::Current(delta) => self.offset.checked_add_signed(delta),
+            SeekFrom::End(delta) => self.metadata().await?.len().checked_add_signed(delta),
+        };
+        self.offset = offset.ok_or_else(invalid_seek)?;
+        Ok(self.offset)
+    }
+
+    // Waits until everything written so far has reached the disk.
+    // FlushFileBuffers blocks, so it runs on the blocking pool against a
+    // duplicate of the handle.
+    pub async fn flush(&self) -> Result<()> {
+        let file = self.handle.try_clone().map_err(AsyncFileError::Io)?;
+        blocking(move || unsafe { FlushFileBuffers(handle_of(&file)) }).await??;
+        Ok(())
+    }
+
+    // Grows or shrinks the file to size bytes. Growing fills with zeros and
+    // shrinking discards the tail. Run on the blocking pool like flush(); the
+    // file pointer it moves isn't used by overlapped I/O, so nothing else cares.
+    pub async fn set_len(&self, size: u64) -> Result<()> {
+        let file = self.handle.try_clone().map_err(AsyncFileError::Io)?;
+        blocking(move || unsafe {
+            let handle = handle_of(&file);
+            SetFilePointerEx(handle, size as i64, None, FILE_BEGIN)?;
+            SetEndOfFile(handle)
+        })
+        .await??;
+        Ok(())
+    }
+}
+
+impl<H: AsRawHandle> AsyncHandle<H> {
+    // Takes over a handle opened with FILE_FLAG_OVERLAPPED by whatever made
+    // it, e.g. a pipe or socket, and binds it to the thread pool. A handle
+    // without the flag is refused with NotOverlapped, and one that's been
+    // bound before with DoubleBind.
+    pub fn new(handle: H) -> Result<Self> {
+        if !is_overlapped(&handle)? {
+            return Err(AsyncFileError::NotOverlapped);
+        }
+
+        Self::bind(handle)
+    }
+
+    // On failure handle is dropped, and so closed, along with everything else.
+    fn bind(handle: H) -> Result<Self> {
+        Self::bind_with_flags(handle, 0)
+    }
+
+    // bind() passing flags on to BindIoCompletionCallback.
+    fn bind_with_flags(handle: H, flags: u32) -> Result<Self> {
+        // BindIoCompletionCallback is used to have a callback trigger the waker.
+        unsafe { BindIoCompletionCallback(handle_of(&handle), Some(waker_callback), flags) }
+            .map_err(bind_error)?;
+        skip_completion_on_success(&handle)?;
+        Ok(Self::with_handle(handle, None))
+    }
+
+    // Like bind() but with completions going to a port of the caller's.
+    fn bind_to_port(
+        handle: H,
+        port: Arc<CompletionPort>,
+        priority: CompletionPriority,
+    ) -> Result<Self> {
+        port.associate(&handle, priority)?;
+        skip_completion_on_success(&handle)?;
+        Ok(Self::with_handle(handle, Some(port)))
+    }
+
+    // Leaves handle unbound, for where neither the thread pool nor a port
+    // will do. Its operations are polled for instead, see
+    // AsyncOpenOptions::polling().
+    fn bind_polled(handle: H) -> Self {
+        let mut file = Self::with_handle(handle, None);
+        file.polled = true;
+        file
+    }
+
+    fn with_handle(handle: H, port: Option<Arc<CompletionPort>>) -> Self {
+        Self {
+            handle,
+            offset: 0,
+            eof_at: EofMark::default(),
+            read_state: None,
+            sector_size: None,
+            limiter: None,
+            append: false,
+            retry: None,
+            stats: IoStats::default(),
+            read_callback: ReadCallback::default(),
+            ops: Arc::default(),
+            polled: false,
+            _port: port,
+            #[cfg(any(test, feature = "mock"))]
+            mock: None,
+        }
+    }
+
+    // Has reads play out mock's script before going to the handle, see
+    // MockIo. Operations already in flight aren't affected.
+    #[cfg(any(test, feature = "mock"))]
+    pub fn with_mock(mut self, mock: Arc<MockIo>) -> Self {
+        self.mock = Some(mock);
+        self
+    }
+
+    // A fresh OVERLAPPED for one of the handle's operations.
+    fn overlapped(&self) -> OverlappedWrap {
+        OverlappedWrap {
+            #[cfg(any(test, feature = "mock"))]
+            mock: self.mock.clone(),
+            ..OverlappedWrap::counted(&self.ops, self.polled)
+        }
+    }
+
+    // A zeroed buffer of at least len bytes that reads and writes on this file
+    // accept, rounded up to whole sectors if it was opened unbuffered.
+    pub fn aligned_buffer(&self, len: usize) -> AlignedBuffer {
+        AlignedBuffer::new(len, self.sector_size.unwrap_or(1) as usize)
+    }
+
+    // Opened with FILE_FLAG_NO_BUFFERING, so bypassing the page cache.
+    pub(crate) fn is_unbuffered(&self) -> bool {
+        self.sector_size.is_some()
+    }
+
+    // Whether a read at the cursor has found the end of the file, with the
+    // cursor not moved since. Seeking elsewhere or writing clears it. If the
+    // file grows underneath it stays set until the next read finds more.
+    // read_at() has no cursor, but one that gets data from before where EOF
+    // was found clears it too.
+    pub fn is_eof(&self) -> bool {
+        self.eof_at.is_at(self.offset)
+    }
+
+    // Moves the cursor past the n bytes a read of len at it returned, noting
+    // EOF if there were none and forgetting it if there were.
+    fn advance_read(&mut self, len: usize, n: usize) {
+        self.eof_at.cursor_read(self.offset, len, n);
+        self.offset += n as u64;
+    }
+
+    // ReadFile and WriteFile calls made for this file that haven't completed
+    // yet, cancelled ones included until the cancellation lands. Zero once a
+    // file is idle, which makes it something to check before shutting down.
+    pub fn pending_ops(&self) -> usize {
+        self.ops.load(Ordering::Acquire)
+    }
+
+    // Cancels every operation in flight on the handle at once, rather than
+    // one future at a time, e.g. when tearing down a connection. Each one's
+    // future then fails with Cancelled, unless it managed to complete first,
+    // once its callback has run, so no buffer is written to after its future
+    // has seen the outcome. Operations issued after this aren't affected. A
+    // read poll_read had in flight leaves it reporting Cancelled until the
+    // next seek().
+    pub fn cancel_all(&self) -> Result<()> {
+        #[cfg(any(test, feature = "mock"))]
+        if let Some(mock) = &self.mock {
+            mock.cancel_all();
+        }
+        match unsafe { CancelIoEx(handle_of(&self.handle), None) } {
+            Ok(()) => Ok(()),
+            // Nothing was in flight.
+            Err(e) if win32_code(&e) == ERROR_NOT_FOUND => Ok(()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    // Totals of the ReadFile and WriteFile calls made for this file so far,
+    // each counted as it's issued and again as it completes.
+    pub fn stats(&self) -> FileStats {
+        self.stats.snapshot()
+    }
+
+    // Refuses up front what an unbuffered ReadFile or WriteFile would only fail
+    // with ERROR_INVALID_PARAMETER. buf is bytes, initialised or not.
+    fn check_alignment<T>(&self, buf: &[T], offset: u64) -> Result<()> {
+        let Some(sector_size) = self.sector_size else {
+            return Ok(());
+        };
+
+        let align = sector_size as usize;
+        if !(buf.as_ptr() as usize).is_multiple_of(align)
+            || !buf.len().is_multiple_of(align)
+            || !offset.is_multiple_of(sector_size as u64)
+        {
+            return Err(AsyncFileError::Unaligned(sector_size));
+        }
+
+        Ok(())
+    }
+
+    // Reads the whole file from the start, from offset 0 whatever the cursor,
+    // reusing buf for every chunk and returning the total. callback runs once
+    // per completed read, given just the bytes that read brought in, and not
+    // again at the end. It runs on the task awaiting this, never on the thread
+    // the completion arrived on. An empty buf fails with InvalidInput.
+    pub async fn read_all<F>(&self, buf: &mut [u8], callback: F) -> Result<usize>
+    where
+        F: FnMut(&[u8]),
+    {
+        // Reads of nothing would never reach EOF.
+        if buf.is_empty() {
+            return Err(AsyncFileError::Io(io::ErrorKind::InvalidInput.into()));
+        }
+        AsyncFileReadFuture {
+            handle: &self.handle,
+            stats: &self.stats,
+            buf,
+            overlapped: Box::new(self.overlapped()),
+            offset: 0,
+            callback,
+            issued: false,
+            finished: false,
+        }
+        .await
+    }
+
+    // A callback for read_all_default() to run once per completed read, given
+    // just the bytes that read brought in, as read_all() runs the one it's
+    // handed. It replaces any set before, and a read_all() call's own
+    // callback still stands in for it there.
+    pub fn set_read_callback(&mut self, callback: impl Fn(&[u8]) + Send + Sync + 'static) {
+        self.read_callback.set(callback);
+    }
+
+    pub fn clear_read_callback(&mut self) {
+        self.read_callback.clear();
+    }
+
+    // read_all() with the callback from set_read_callback(), or with none if
+    // it hasn't been set.
+    pub async fn read_all_default(&self, buf: &mut [u8]) -> Result<usize> {
+        self.read_all(buf, |bytes| self.read_callback.call(bytes))
+            .await
+    }
+
+    // read_all() for when the bytes themselves are wanted elsewhere: after each
+    // chunk lands the running total is sent down progress, e.g. to a UI thread.
+    // The read carries on regardless if the receiver has gone away.
+    pub async fn read_with_progress(
+        &self,
+        buf: &mut [u8],
+        progress: Sender<usize>,
+    ) -> Result<usize> {
+        let mut total = 0;
+        self.read_all(buf, |bytes| {
+            total += bytes.len();
+            let _ = progress.send(total);
+        })
+        .await
+    }
+
+    // Single read of up to buf.len() bytes at the cursor, which then advances
+    // past them. Returns 0 at EOF.
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        self.read_uninit(as_uninit(buf)).await
+    }
+
+    // read() into memory that needn't be initialised first, saving the cost of
+    // zeroing it. Only the first n bytes of buf, where n is the count returned,
+    // are initialised by the read; the rest are left as they were.
+    pub async fn read_uninit(&mut self, buf: &mut [MaybeUninit<u8>]) -> Result<usize> {
+        // Anything poll_read has in hand or in flight has to be handed out first.
+        if self.read_state_pending() {
+            return std::future::poll_fn(|cx| self.poll_read_into(cx, buf)).await;
+        }
+
+        let n = self.read_at_uninit(buf, self.offset).await?;
+        self.advance_read(buf.len(), n);
+        Ok(n)
+    }
+
+    // read() that gives up with TimedOut if nothing arrives within timeout.
+    // The read is cancelled, but if it completes anyway before the
+    // cancellation lands then its bytes are returned instead; either way the
+    // kernel is done with buf by the time this returns. The timer is tokio's,
+    // so this and the two below need the tokio feature and a runtime.
+    #[cfg(feature = "tokio")]
+    pub async fn read_timeout(&mut self, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        self.read_deadline(buf, Instant::now() + timeout).await
+    }
+
+    // read_timeout() against a point in time rather than a span, so a run of
+    // reads can share one deadline, such as a request's, without working out
+    // what's left of it each time. A deadline already passed still lets a
+    // read that completes on the spot return its bytes.
+    #[cfg(feature = "tokio")]
+    pub async fn read_deadline(&mut self, buf: &mut [u8], deadline: Instant) -> Result<usize> {
+        let deadline = tokio::time::Instant::from_std(deadline);
+
+        // A read poll_read started belongs to read_state, so it can be left
+        // running for the next read to pick up.
+        if self.read_state_pending() {
+            let buf = as_uninit(buf);
+            let read = std::future::poll_fn(|cx| self.poll_read_into(cx, buf));
+            return tokio::time::timeout_at(deadline, read)
+                .await
+                .unwrap_or(Err(AsyncFileError::TimedOut));
+        }
+
+        let buf = as_uninit(buf);
+        let sleep = tokio::time::sleep_until(deadline);
+        let (result, timed_out) = self.read_at_until(buf, self.offset, sleep).await;
+        let n = match result {
+            Err(AsyncFileError::Cancelled) if timed_out => return Err(AsyncFileError::TimedOut),
+            result => result?,
+        };
+        self.advance_read(buf.len(), n);
+        Ok(n)
+    }
+
+    // Fills as much of buf as it can within timeout, from the cursor, and
+    // returns how much that was. Running out of time isn't an error here, nor
+    // is EOF; either just ends it short. Each read goes through
+    // read_deadline(), so the one cut off by the timer has either completed,
+    // and its bytes are counted, or been cancelled before this returns. Other
+    // errors are returned as they are, with the cursor past whatever was read.
+    #[cfg(feature = "tokio")]
+    pub async fn read_exact_or_timeout(
+        &mut self,
+        buf: &mut [u8],
+        timeout: Duration,
+    ) -> Result<usize> {
+        let deadline = Instant::now() + timeout;
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.read_deadline(&mut buf[filled..], deadline).await {
+                Ok(0) | Err(AsyncFileError::TimedOut) => break,
+                Ok(n) => filled += n,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(filled)
+    }
+
+    // read() that gives up with Cancelled once token is cancelled, which can
+    // happen from anywhere. As with read_timeout(), a read that completes
+    // before the cancellation lands returns its bytes, and buf is free again
+    // by the time this returns.
+    pub async fn read_cancellable(&mut self, buf: &mut [u8], token: &CancelToken) -> Result<usize> {
+        if token.is_cancelled() {
+            return Err(AsyncFileError::Cancelled);
+        }
+
+        if self.read_state_pending() {
+            let buf = as_uninit(buf);
+            let mut cancelled = pin!(token.cancelled());
+            return std::future::poll_fn(|cx| {
+                if let Poll::Ready(result) = self.poll_read_into(cx, buf) {
+                    return Poll::Ready(result);
+                }
+                cancelled
+                    .as_mut()
+                    .poll(cx)
+                    .map(|()| Err(AsyncFileError::Cancelled))
+            })
+            .await;
+        }
+
+        let buf = as_uninit(buf);
+        let n = self
+            .read_at_until(buf, self.offset, token.cancelled())
+            .await
+            .0?;
+        self.advance_read(buf.len(), n);
+        Ok(n)
+    }
+
+    // read_at() that gives up with Cancelled once token is cancelled.
+    pub async fn read_at_cancellable(
+        &self,
+        buf: &mut [u8],
+        offset: u64,
+        token: &CancelToken,
+    ) -> Result<usize> {
+        if token.is_cancelled() {
+            return Err(AsyncFileError::Cancelled);
+        }
+
+        self.read_at_until(as_uninit(buf), offset, token.cancelled())
+            .await
+            .0
+    }
+
+    // Whether poll_read has bytes in hand, a read in flight or an error to
+    // report, all of which come before any new read at the cursor.
+    fn read_state_pending(&self) -> bool {
+        self.read_state
+            .as_ref()
+            .is_some_and(|s| s.in_flight || s.pos < s.filled || s.error.is_some())
+    }
+
+    // A read at offset that is cancelled if stop completes first, along with
+    // whether it did. Nothing is returned until the read has completed, so the
+    // kernel is done with buf however it ended.
+    async fn read_at_until(
+        &self,
+        buf: &mut [MaybeUninit<u8>],
+        offset: u64,
+        stop: impl Future<Output = ()>,
+    ) -> (Result<usize>, bool) {
+        if buf.is_empty() {
+            return (Ok(0), false);
+        }
+        if let Err(e) = self.check_alignment(buf, offset) {
+            return (Err(e), false);
+        }
+        let _permit = self.acquire_permit().await;
+        let mut read = AsyncFileReadAtFuture {
+            handle: &self.handle,
+            stats: &self.stats,
+            buf,
+            overlapped: Box::new(self.overlapped()),
+            offset,
+            submitted: false,
+        };
+        let mut stop = pin!(stop);
+        let mut stopped = false;
+
+        let result = std::future::poll_fn(|cx| {
+            if let Poll::Ready(result) = Pin::new(&mut read).poll(cx) {
+                return Poll::Ready(result);
+            }
+            if !stopped && stop.as_mut().poll(cx).is_ready() {
+                stopped = true;
+                read.overlapped.request_cancel(read.handle);
+            }
+            Poll::Pending
+        })
+        .await;
+        if let Ok(n) = result {
+            self.eof_at.read_returned(offset, n);
+        }
+
+        (result, stopped)
+    }
+
+    // Keeps reading at the cursor until buf is full, failing with
+    // UnexpectedEof if the file ends first.
+    pub async fn read_exact(&mut self, buf: &mut [u8]) -> Result<()> {
+        let mut filled = 0;
+        while filled < buf.len() {
+            match self.read(&mut buf[filled..]).await? {
+                0 => return Err(AsyncFileError::Eof),
+                n => filled += n,
+            }
+        }
+        Ok(())
+    }
+
+    // Fills bufs in order from the cursor, returning the total read. It stops
+    // at the first short read, normally EOF, leaving the remaining slices be.
+    //
+    // ReadFileScatter would do this in one call, but only for page-sized,
+    // page-aligned segments on a file opened with FILE_FLAG_NO_BUFFERING, so
+    // each slice gets its own ReadFile instead.
+    pub async fn read_vectored(&mut self, bufs: &mut [IoSliceMut<'_>]) -> Result<usize> {
+        let mut total = 0;
+        for buf in bufs.iter_mut() {
+            let n = self.read(buf).await?;
+            total += n;
+            if n < buf.len() {
+                break;
+            }
+        }
+        Ok(total)
+    }
+
+    // Single read of up to buf.len() bytes at offset, returning 0 at EOF. It
+    // leaves the cursor alone, so any number can be in flight at once.
+    //
+    // ReadFile is handed buf itself, so dropping the future before the read
+    // completes cancels it with CancelIoEx and blocks until its completion
+    // has arrived. Once the drop returns, and the borrow of buf with it,
+    // nothing can write into buf any more. A future leaked with mem::forget
+    // never runs that drop, and is what the owned reads are for.
+    pub async fn read_at(&self, buf: &mut [u8], offset: u64) -> Result<usize> {
+        self.read_at_uninit(as_uninit(buf), offset).await
+    }
+
+    // A single ReadFile of buf at offset as a ReadOp, a future with a name
+    // that can be kept in a struct or passed around before it's awaited.
+    // Unlike read_at() it doesn't wait its turn with the file's limiter or
+    // retry by its policy.
+    pub fn read_scoped<'a>(&'a self, buf: &'a mut [u8], offset: u64) -> ReadOp<'a, H> {
+        let buf = as_uninit(buf);
+        let early = if buf.is_empty() {
+            Some(Ok(0))
+        } else {
+            self.check_alignment(buf, offset).err().map(Err)
+        };
+        ReadOp {
+            read: AsyncFileReadAtFuture {
+                handle: &self.handle,
+                stats: &self.stats,
+                buf,
+                overlapped: Box::new(self.overlapped()),
+                offset,
+                submitted: false,
+            },
+            eof_at: &self.eof_at,
+            early,
+        }
+    }
+
+    // What read_at() and read_uninit() both come down to.
+    async fn read_at_uninit(&self, buf: &mut [MaybeUninit<u8>], offset: u64) -> Result<usize> {
+        // What a zero-length ReadFile completes with, if it completes at all,
+        // varies by device, so it isn't issued.
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        self.check_alignment(buf, offset)?;
+        let mut attempt = 1;
+        loop {
+            let permit = self.acquire_permit().await;
+            let result = AsyncFileReadAtFuture {
+                handle: &self.handle,
+                stats: &self.stats,
+                buf: &mut *buf,
+                overlapped: Box::new(self.overlapped()),
+                offset,
+                submitted: false,
+            }
+            .await;
+            drop(permit);
+
+            match (&self.retry, result) {
+                (Some(retry), Err(e)) if retry.should_retry(&e, attempt) => {
+                    retry.wait(attempt).await;
+                    attempt += 1;
+                }
+                (_, result) => {
+                    if let Ok(n) = result {
+                        self.eof_at.read_returned(offset, n);
+                    }
+                    return result;
+                }
+            }
+        }
+    }
+
+    // read_at() without the interpretation: the status and byte count exactly
+    // as the completion reported them, for working out what odd storage is
+    // really doing. A read refused up front comes back as its error code with
+    // no bytes, and one that hits EOF straight away as ERROR_HANDLE_EOF.
+    pub async fn read_raw(&self, buf: &mut [u8], offset: u64) -> Result<CompletionResult> {
+        let buf = as_uninit(buf);
+        self.check_alignment(buf, offset)?;
+        let _permit = self.acquire_permit().await;
+        Ok(AsyncFileReadRawFuture {
+            handle: &self.handle,
+            stats: &self.stats,
+            buf,
+            overlapped: Box::new(self.overlapped()),
+            offset,
+            submitted: false,
+        }
+        .await)
+    }
+
+    async fn acquire_permit(&self) -> Option<IoPermit<'_>> {
+        match &self.limiter {
+            Some(limiter) => Some(limiter.acquire().await),
+            None => None,
+        }
+    }
+
+    // Writes all of buf at the current write position, which then advances past
+    // it. A file opened for appending writes at its end instead, wherever
+    // that is by then, and leaves the cursor alone.
+    pub async fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        if self.append {
+            return AsyncFileWriteFuture {
+                handle: &self.handle,
+                stats: &self.stats,
+                buf,
+                overlapped: Box::new(self.overlapped()),
+                offset: APPEND_OFFSET,
+                written: 0,
+                issued: false,
+            }
+            .await;
+        }
+
+        // The write lands where the reader left off.
+        self.rewind_read_state().await;
+        self.check_alignment(buf, self.offset)?;
+
+        let written = AsyncFileWriteFuture {
+            handle: &self.handle,
+            stats: &self.stats,
+            buf,
+            overlapped: Box::new(self.overlapped()),
+            offset: self.offset,
+            written: 0,
+            issued: false,
+        }
+        .await?;
+
+        self.offset += written as u64;
+        Ok(written)
+    }
+
+    // Writes bufs in order at the cursor (or the end, when appending) and
+    // returns the total written. If one fails after others have gone out, the
+    // bytes already written are reported and the error is left for the next
+    // call to run into, as std's write_vectored does.
+    //
+    // WriteFileGather would do it in one call, but like ReadFileScatter it
+    // wants page-sized, page-aligned segments on an unbuffered file, so each
+    // slice gets its own WriteFile instead.
+    pub async fn write_vectored(&mut self, bufs: &[IoSlice<'_>]) -> Result<usize> {
+        let mut total = 0;
+        for buf in bufs {
+            match self.write(buf).await {
+                Ok(n) => total += n,
+                Err(_) if total != 0 => break,
+                Err(e) => return Err(e),
+            }
+        }
+        Ok(total)
+    }
+
+    // Gives back anything poll_read fetched but didn't hand out, waiting for a
+    // read in flight first, so the cursor is where the reader left off.
+    async fn rewind_read_state(&mut self) {
+        std::future::poll_fn(|cx| self.poll_read_state(cx)).await;
+        if let Some(state) = self.read_state.as_deref_mut() {
+            self.offset -= (state.filled - state.pos) as u64;
+            state.pos = 0;
+            state.filled = 0;
+        }
+    }
+
+    // Writes all of buf at offset. The cursor is left alone, so writes to
+    // disjoint regions can be in flight at once.
+    pub async fn write_at(&self, buf: &[u8], offset: u64) -> Result<usize> {
+        self.check_alignment(buf, offset)?;
+        AsyncFileWriteFuture {
+            handle: &self.handle,
+            stats: &self.stats,
+            buf,
+            overlapped: Box::new(self.overlapped()),
+            offset,
+            written: 0,
+            issued: false,
+        }
+        .await
+    }
+
+    // Turns the file into a stream of owned buffers of up to chunk_size bytes,
+    // read from the cursor until EOF ends the stream.
+    pub fn chunks(self, chunk_size: usize) -> ChunkStream<H> {
+        assert!(chunk_size != 0, "chunk size must be non-zero");
+        let overlapped = Box::new(self.overlapped());
+        ChunkStream {
+            file: self,
+            chunk_size,
+            overlapped,
+            buf: Vec::new(),
+            in_flight: false,
+            done: false,
+        }
+    }
+
+    // The handle belongs to H and is closed exactly once when it drops, after
+    // any read poll_read left in flight has been cancelled. Dropping the
+    // AsyncHandle does the same; this just makes it explicit.
+    pub fn close(self) -> Result<()> {
+        drop(self);
+        Ok(())
+    }
+
+    // Issues a ReadFile of up to len bytes at the cursor into read_state.
+    fn start_read(&mut self, cx: &mut Context<'_>, len: usize) -> Result<()> {
+        if self.read_state.is_none() {
+            self.read_state = Some(Box::new(ReadState {
+                overlapped: self.overlapped(),
+                ..ReadState::default()
+            }));
+        }
+        let state = self.read_state.as_deref_mut().unwrap();
+        state.buf.resize(len, 0);
+        state.pos = 0;
+        state.filled = 0;
+        self.stats.read_issued();
+        let started = state.overlapped.start_read(
+            &self.handle,
+            as_uninit(&mut state.buf),
+            self.offset,
+            cx.waker(),
+        );
+        match started {
+            Ok(true) => {}
+            Ok(false) => {
+                self.stats.record_read(&Ok::<_, ()>(0));
+                self.eof_at.set(self.offset);
+            }
+            Err(code) => self.stats.record_read(&Err(code)),
+        }
+        state.in_flight = started.map_err(AsyncFileError::read)?;
+        Ok(())
+    }
+
+    // Drives a read started by start_read to completion, leaving its bytes in
+    // read_state and the cursor past them. Hitting EOF leaves nothing filled,
+    // and a failure is kept in read_state.error.
+    fn poll_read_state(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let state = match self.read_state.as_deref_mut() {
+            Some(state) if state.in_flight => state,
+            _ => return Poll::Ready(()),
+        };
+
+        let result = ready!(state.overlapped.poll_read_result(cx));
+        state.in_flight = false;
+        self.stats.record_read(&result);
+        match result {
+            Ok(len) => {
+                state.filled = len;
+                self.eof_at.cursor_read(self.offset, state.buf.len(), len);
+                self.offset += len as u64;
+            }
+            Err(code) => state.error = Some(code),
+        }
+        Poll::Ready(())
+    }
+
+    // Shared by the AsyncRead impls: copies the next bytes at the cursor into
+    // dst, returning 0 at EOF. Once a read fails every later call reports the
+    // same error rather than issuing another ReadFile.
+    fn poll_read_into(
+        &mut self,
+        cx: &mut Context<'_>,
+        dst: &mut [MaybeUninit<u8>],
+    ) -> Poll<Result<usize>> {
+        loop {
+            if let Some(state) = self.read_state.as_deref_mut() {
+                if let Some(code) = state.error {
+                    return Poll::Ready(Err(AsyncFileError::read(code)));
+                }
+
+                if state.pos < state.filled {
+                    let n = dst.len().min(state.filled - state.pos);
+                    let src = &state.buf[state.pos..state.pos + n];
+                    unsafe { ptr::copy_nonoverlapping(src.as_ptr(), dst.as_mut_ptr().cast(), n) };
+                    state.pos += n;
+                    return Poll::Ready(Ok(n));
+                }
+
+                if state.in_flight {
+                    ready!(self.poll_read_state(cx));
+                    if self
+                        .read_state
+                        .as_ref()
+                        .is_some_and(|s| s.filled == 0 && s.error.is_none())
+                    {
+                        // EOF
+                        return Poll::Ready(Ok(0));
+                    }
+                    continue;
+                }
+            }
+
+            if dst.is_empty() {
+                return Poll::Ready(Ok(0));
+            }
+
+            if let Err(e) = self.start_read(cx, dst.len().min(POLL_READ_CHUNK)) {
+                if let (Some(state), AsyncFileError::Read(code)) =
+                    (self.read_state.as_deref_mut(), &e)
+                {
+                    state.error = Some(*code);
+                }
+                return Poll::Ready(Err(e));
+            }
+            if self.read_state.as_ref().is_some_and(|s| !s.in_flight) {
+                // EOF reported synchronously
+                return Poll::Ready(Ok(0));
+            }
+        }
+    }
+}
+
+#[cfg(feature = "tokio")]
+impl<H: AsRawHandle + Unpin> AsyncRead for AsyncHandle<H> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        // Read straight into the unfilled part, initialised or not.
+        let n = ready!(self
+            .get_mut()
+            .poll_read_into(cx, unsafe { buf.unfilled_mut() }))?;
+        unsafe { buf.assume_init(n) };
+        buf.advance(n);
+        Poll::Ready(Ok(()))
+    }
+}
+
+#[cfg(feature = "futures-io")]
+impl<H: AsRawHandle + Unpin> futures_io::AsyncRead for AsyncHandle<H> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut [u8],
+    ) -> Poll<io::Result<usize>> {
+        self.get_mut()
+            .poll_read_into(cx, as_uninit(buf))
+            .map_err(io::Error::from)
+    }
+}
+
+impl<H: AsRawHandle> Drop for AsyncHandle<H> {
+    fn drop(&mut self) {
+        // poll_read may have left a read in flight
+        if let Some(state) = self.read_state.as_deref_mut() {
+            state.overlapped.cancel(&self.handle);
+        }
+    }
+}
+
+// Whole contents of the file at path, like std::fs::read. If the read fails
+// partway the file is still closed, as dropping it does the same as close().
+pub async fn read(path: &str) -> Result<Vec<u8>> {
+    let mut file = AsyncFile::open_for_read(path).await?;
+    let mut bytes = Vec::new();
+    file.read_to_end(&mut bytes).await?;
+    file.close()?;
+    Ok(bytes)
+}
+
+// Unbuffered I/O has to line up with the logical sectors of the volume the
+// file lives on.
+fn sector_size(file: &File) -> Result<u32> {
+    let mut info = FILE_STORAGE_INFO::default();
+    unsafe {
+        GetFileInformationByHandleEx(
+            handle_of(file),
+            FileStorageInfo,
+            &mut info as *mut _ as *mut _,
+            std::mem::size_of::<FILE_STORAGE_INFO>() as u32,
+        )
+    }?;
+
+    Ok(info.LogicalBytesPerSector)
+}
+
+// Windows refuses to bind a handle a second time, to the thread pool or a
+// port, with nothing more telling than ERROR_INVALID_PARAMETER. That's the one
+// way the binding calls fail with it, so it's reported as what it means.
+fn bind_error(error: Error) -> AsyncFileError {
+    if win32_code(&error) == ERROR_INVALID_PARAMETER {
+        AsyncFileError::DoubleBind
+    } else {
+        error.into()
+    }
+}
+
+// Stops operations that complete synchronously from queueing a completion as
+// well, which saves a trip through the thread pool for reads served from the
+// cache. See complete_inline().
+fn skip_completion_on_success(handle: &impl AsRawHandle) -> Result<()> {
+    unsafe {
+        SetFileCompletionNotificationModes(
+            handle_of(handle),
+            FILE_SKIP_COMPLETION_PORT_ON_SUCCESS as u8,
+        )
+    }?;
+    Ok(())
+}
+
+// The thread pool, a completion port and polling all report EOF as the
+// NTSTATUS left in the OVERLAPPED, and a ReadFile that fails on the spot, as
+// one at or past the end of the file does whatever its offset, as
+// ERROR_HANDLE_EOF. Every EOF check goes through here so that none of them
+// misses one of the forms and reports EOF as a failed read.
+fn is_eof(err: u32) -> bool {
+    err == STATUS_END_OF_FILE.0 as u32 || err == ERROR_HANDLE_EOF.0
+}
+
+// What a read's completion amounts to, with EOF as zero bytes read.
+fn read_outcome(err: u32, len: u32) -> Win32Result<usize> {
+    if is_eof(err) {
+        Ok(0)
+    } else if err != 0 {
+        Err(completion_code(err))
+    } else {
+        Ok(len as usize)
+    }
+}
+
+// The windows crate's HANDLE for anything std can give a raw handle for. Both
+// are a *mut c_void at the moment, but HANDLE has changed shape between
+// releases, so every conversion goes through here to be fixed in one place.
+fn handle_of(handle: &(impl AsRawHandle + ?Sized)) -> HANDLE {
+    HANDLE(handle.as_raw_handle().cast())
+}
+
+// Lets an initialised buffer go where reads accept uninitialised ones. Sound
+// because reads only ever store initialised bytes through it.
+fn as_uninit(buf: &mut [u8]) -> &mut [MaybeUninit<u8>] {
+    unsafe { &mut *(buf as *mut [u8] as *mut [MaybeUninit<u8>]) }
+}
+
+// A handle opened without FILE_FLAG_OVERLAPPED has every operation on it
+// serialised and completed synchronously, so it can't be driven from here.
+fn is_overlapped(handle: &impl AsRawHandle) -> Result<bool> {
+    let info: FILE_MODE_INFORMATION = query_information(handle, FileModeInformation)?;
+    let synchronous = FILE_SYNCHRONOUS_IO_ALERT.0 | FILE_SYNCHRONOUS_IO_NONALERT.0;
+    Ok(info.Mode & synchronous == 0)
+}
+
+// The access rights handle was opened with.
+fn granted_access(handle: &impl AsRawHandle) -> Result<u32> {
+    let info: FILE_ACCESS_INFORMATION = query_information(handle, FileAccessInformation)?;
+    Ok(info.AccessFlags)
+}
+
+// The NtQueryInformationFile class whose struct is T.
+fn query_information<T: Default>(
+    handle: &impl AsRawHandle,
+    class: FILE_INFORMATION_CLASS,
+) -> Result<T> {
+    let mut io_status = IO_STATUS_BLOCK::default();
+    let mut info = T::default();
+    let status = unsafe {
+        NtQueryInformationFile(
+            handle_of(handle),
+            &mut io_status,
+            &mut info as *mut T as *mut _,
+            std::mem::size_of::<T>() as u32,
+            class,
+        )
+    };
+    if status.is_err() {
+        return Err(AsyncFileError::Win32(completion_code(status.0 as u32)));
+    }
+    Ok(info)
+}
+
+struct AsyncFileReadFuture<'a, H: AsRawHandle, F> {
+    handle: &'a H,
+    stats: &'a IoStats,
+    buf: &'a mut [u8],
+    // The kernel holds on to this address while a read is in flight. Boxing it
+    // keeps it valid however the future is moved between polls.
+    overlapped: Box<OverlappedWrap>,
+    offset: u64,
+    callback: F,
+    // Whether a ReadFile has been issued whose outcome is yet to be counted.
+    issued: bool,
+    // Set once the future has returned Ready. Polling it again from there
+    // would go back to issuing reads past the point the result came from.
+    finished: bool,
+}
+
+impl<'a, H: AsRawHandle, F> Future for AsyncFileReadFuture<'a, H, F>
+where
+    F: FnMut(&[u8]) + 'a,
+{
+    type Output = Result<usize>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = unsafe { self.get_unchecked_mut() };
+
+        // An error leaves the OVERLAPPED idle, so a combinator polling again
+        // would quietly start reading afresh rather than see the error.
+        assert!(!this.finished, "read_all() polled after it completed");
+        let result = ready!(this.poll_chunks(cx));
+        this.finished = true;
+        Poll::Ready(result)
+    }
+}
+
+impl<'a, H: AsRawHandle, F> AsyncFileReadFuture<'a, H, F>
+where
+    F: FnMut(&[u8]) + 'a,
+{
+    fn poll_chunks(&mut self, cx: &mut Context<'_>) -> Poll<Result<usize>> {
+        let this = self;
+
+        // Reads served from the cache complete inline, so keep going until one
+        // is left pending.
+        loop {
+            let (err, len) = ready!(this.overlapped.poll_complete(cx));
+            if std::mem::take(&mut this.issued) {
+                this.stats.record_read(&read_outcome(err, len));
+            }
+
+            if is_eof(err) {
+                // End of file
+                return Poll::Ready(Ok(this.offset as usize));
+            }
+
+            if err != 0 {
+                return Poll::Ready(Err(AsyncFileError::read(completion_code(err))));
+            }
+
+            if len != 0 {
+                // Some data has been read
+                let bytes_transferred = len;
+
+                (this.callback)(&this.buf[..bytes_transferred as usize]);
+                this.offset += bytes_transferred as u64;
+                this.overlapped.o.Anonymous.Anonymous.Offset = this.offset as u32;
+                this.overlapped.o.Anonymous.Anonymous.OffsetHigh = (this.offset >> 32) as u32;
+            }
+
+            // needs to be set before the call to ReadFile to avoid a race
+            this.overlapped.arm(cx.waker());
+            this.stats.read_issued();
+            this.issued = true;
+
+            let result = this.overlapped.read_file(handle_of(this.handle), this.buf);
+
+            match result {
+                // Data was read synchronously
+                Ok(()) => this.overlapped.complete_inline(),
+                // Left to poll_complete() at the top of the loop, which parks
+                // the waker, or on a polled file looks for the result itself.
+                Err(error) if error == Error::from(ERROR_IO_PENDING) => {}
+                Err(error) => {
+                    // Read operation failed, or found EOF straight away
+                    this.overlapped.disarm();
+                    this.issued = false;
+                    this.stats
+                        .record_read(&read_outcome(win32_code(&error).0, 0));
+                    if is_eof(win32_code(&error).0) {
+                        return Poll::Ready(Ok(this.offset as usize));
+                    }
+                    return Poll::Ready(Err(AsyncFileError::read(win32_code(&error))));
+                }
+            }
+        }
+    }
+}
+
+impl<'a, H: AsRawHandle, F> Drop for AsyncFileReadFuture<'a, H, F> {
+    fn drop(&mut self) {
+        // Dropped mid-flight, e.g. by a select or timeout
+        self.overlapped.cancel(self.handle);
+    }
+}
+
+pub struct ChunkStream<H: AsRawHandle = File> {
+    file: AsyncHandle<H>,
+    chunk_size: usize,
+    // Boxed, like ReadState, as the stream may move between polls.
+    overlapped: Box<OverlappedWrap>,
+    buf: Vec<u8>,
+    in_flight: bool,
+    done: bool,
+}
+
+impl<H: AsRawHandle + Unpin> Stream for ChunkStream<H> {
+    type Item = Result<Vec<u8>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            if this.done {
+                return Poll::Ready(None);
+            }
+
+            if this.in_flight {
+                let result = ready!(this.overlapped.poll_read_result(cx));
+                this.in_flight = false;
+                this.file.stats.record_read(&result);
+                return Poll::Ready(match result {
+                    Ok(0) => {
+                        this.done = true;
+                        None
+                    }
+                    Ok(n) => {
+                        this.buf.truncate(n);
+                        this.file.offset += n as u64;
+                        this.file.eof_at.clear();
+                        Some(Ok(std::mem::take(&mut this.buf)))
+                    }
+                    Err(code) => {
+                        this.done = true;
+                        Some(Err(AsyncFileError::read(code)))
+                    }
+                });
+            }
+
+            // Whatever poll_read had already fetched comes out first.
+            ready!(this.file.poll_read_state(cx));
+            if let Some(state) = this.file.read_state.as_deref_mut() {
+                if let Some(code) = state.error.take() {
+                    this.done = true;
+                    return Poll::Ready(Some(Err(AsyncFileError::read(code))));
+                }
+                if state.pos < state.filled {
+                    let chunk = state.buf[state.pos..state.filled].to_vec();
+                    state.pos = state.filled;
+                    return Poll::Ready(Some(Ok(chunk)));
+                }
+            }
+
+            this.buf = vec![0u8; this.chunk_size];
+            this.file.stats.read_issued();
+            match this.overlapped.start_read(
+                &this.file.handle,
+                as_uninit(&mut this.buf),
+                this.file.offset,
+                cx.waker(),
+            ) {
+                // loop round in case the callback has already run
+                Ok(true) => this.in_flight = true,
+                Ok(false) => {
+                    this.file.stats.record_read(&Ok::<_, ()>(0));
+                    this.done = true;
+                }
+                Err(code) => {
+                    this.file.stats.record_read(&Err(code));
+                    this.done = true;
+                    return Poll::Ready(Some(Err(AsyncFileError::read(code))));
+                }
+            }
+        }
+    }
+}
+
+impl<H: AsRawHandle> Drop for ChunkStream<H> {
+    fn drop(&mut self) {
+        self.overlapped.cancel(&self.file.handle);
+    }
+}
+
+// Outcome of an operation as the completion delivered it. Through the thread
+// pool status is often an NTSTATUS (STATUS_END_OF_FILE, say) rather than a
+// Win32 code; it's passed on untranslated either way.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct CompletionResult {
+    pub bytes: u32,
+    pub status: WIN32_ERROR,
+}
+
+struct AsyncFileReadRawFuture<'a, H: AsRawHandle> {
+    handle: &'a H,
+    stats: &'a IoStats,
+    buf: &'a mut [MaybeUninit<u8>],
+    overlapped: Box<OverlappedWrap>,
+    offset: u64,
+    submitted: bool,
+}
+
+impl<'a, H: AsRawHandle> Future for AsyncFileReadRawFuture<'a, H> {
+    type Output = CompletionResult;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        if !this.submitted {
+            this.submitted = true;
+            this.stats.read_issued();
+            let started =
+                this.overlapped
+                    .start_read(this.handle, this.buf, this.offset, cx.waker());
+            let status = match started {
+                Ok(true) => None,
+                Ok(false) => Some(ERROR_HANDLE_EOF),
+                Err(code) => Some(code),
+            };
+            if let Some(status) = status {
+                this.stats.record_read(&read_outcome(status.0, 0));
+                return Poll::Ready(CompletionResult { bytes: 0, status });
+            }
+        }
+
+        let (err, len) = ready!(this.overlapped.poll_complete(cx));
+        this.stats.record_read(&read_outcome(err, len));
+        Poll::Ready(CompletionResult {
+            bytes: len,
+            status: WIN32_ERROR(err),
+        })
+    }
+}
+
+impl<'a, H: AsRawHandle> Drop for AsyncFileReadRawFuture<'a, H> {
+    fn drop(&mut self) {
+        self.overlapped.cancel(self.handle);
+    }
+}
+
+struct AsyncFileReadAtFuture<'a, H: AsRawHandle> {
+    handle: &'a H,
+    stats: &'a IoStats,
+    buf: &'a mut [MaybeUninit<u8>],
+    overlapped: Box<OverlappedWrap>,
+    offset: u64,
+    submitted: bool,
+}
+
+impl<'a, H: AsRawHandle> Future for AsyncFileReadAtFuture<'a, H> {
+    type Output = Result<usize>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        if !this.submitted {
+            this.submitted = true;
+            this.stats.read_issued();
+            match this
+                .overlapped
+                .start_read(this.handle, this.buf, this.offset, cx.waker())
+            {
+                // Pending, or already complete if it finished on the spot
+                Ok(true) => {}
+                Ok(false) => {
+                    this.stats.record_read(&Ok::<_, ()>(0));
+                    return Poll::Ready(Ok(0));
+                }
+                Err(code) => {
+                    this.stats.record_read(&Err(code));
+                    return Poll::Ready(Err(AsyncFileError::read(code)));
+                }
+            }
+        }
+
+        let result = ready!(this.overlapped.poll_read_result(cx));
+        this.stats.record_read(&result);
+        Poll::Ready(result.map_err(AsyncFileError::read))
+    }
+}
+
+impl<'a, H: AsRawHandle> Drop for AsyncFileReadAtFuture<'a, H> {
+    fn drop(&mut self) {
+        self.overlapped.cancel(self.handle);
+    }
+}
+
+// A read from read_scoped(), issued at the first poll, that holds on to buf
+// for as long as it lives. Dropping it before the read completes cancels it
+// with CancelIoEx and blocks until the completion has arrived, so once the
+// drop returns nothing can still be writing into buf. A ReadOp leaked with
+// mem::forget never runs that drop, and is what the owned reads are for.
+pub struct ReadOp<'a, H: AsRawHandle = File> {
+    read: AsyncFileReadAtFuture<'a, H>,
+    eof_at: &'a EofMark,
+    // The outcome when there's nothing to issue: 0 for an empty buf, or why
+    // buf was turned away, e.g. for being unaligned.
+    early: Option<Result<usize>>,
+}
+
+impl<H: AsRawHandle> Future for ReadOp<'_, H> {
+    type Output = Result<usize>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        if let Some(result) = this.early.take() {
+            return Poll::Ready(result);
+        }
+
+        let n = ready!(Pin::new(&mut this.read).poll(cx))?;
+        this.eof_at.read_returned(this.read.offset, n);
+        Poll::Ready(Ok(n))
+    }
+}
+
+struct AsyncFileWriteFuture<'a, H: AsRawHandle> {
+    handle: &'a H,
+    stats: &'a IoStats,
+    buf: &'a [u8],
+    overlapped: Box<OverlappedWrap>,
+    offset: u64,
+    written: usize,
+    // Whether a WriteFile has been issued whose outcome is yet to be counted.
+    issued: bool,
+}
+
+impl<'a, H: AsRawHandle> Future for AsyncFileWriteFuture<'a, H> {
+    type Output = Result<usize>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        // Writes that complete inline go straight on to the next part.
+        loop {
+            let (err, len) = ready!(this.overlapped.poll_complete(cx));
+            let issued = std::mem::take(&mut this.issued);
+            if issued {
+                let outcome = if err == 0 { Ok(len as usize) } else { Err(err) };
+                this.stats.record_write(&outcome);
+            }
+
+            if err != 0 {
+                return Poll::Ready(Err(AsyncFileError::write(completion_code(err))));
+            }
+
+            // A pipe with a full buffer may take only part of a write, and
+            // nothing at all would leave this looping.
+            if issued && len == 0 {
+                let error = io::Error::from(io::ErrorKind::WriteZero);
+                return Poll::Ready(Err(AsyncFileError::Io(error)));
+            }
+
+            // Some data has been written, carry on from where it stopped
+            this.written += len as usize;
+
+            if this.written == this.buf.len() {
+                return Poll::Ready(Ok(this.written));
+            }
+
+            let offset = match this.offset {
+                APPEND_OFFSET => APPEND_OFFSET,
+                offset => offset + this.written as u64,
+            };
+            this.overlapped.o.Anonymous.Anonymous.Offset = offset as u32;
+            this.overlapped.o.Anonymous.Anonymous.OffsetHigh = (offset >> 32) as u32;
+
+            // needs to be set before the call to WriteFile to avoid a race
+            this.overlapped.arm(cx.waker());
+            this.stats.write_issued();
+            this.issued = true;
+
+            let result = this
+                .overlapped
+                .write_file(handle_of(this.handle), &this.buf[this.written..]);
+
+            match result {
+                // Completed synchronously, so no completion is queued for it.
+                Ok(()) => this.overlapped.complete_inline(),
+                // Round again to wait for it, as a polled file has to reap it.
+                Err(error) if error == Error::from(ERROR_IO_PENDING) => {}
+                Err(error) => {
+                    // Write operation failed
+                    this.overlapped.disarm();
+                    this.issued = false;
+                    this.stats.record_write(&Err(win32_code(&error)));
+                    return Poll::Ready(Err(AsyncFileError::write(win32_code(&error))));
+                }
+            }
+        }
+    }
+}
+
+impl<'a, H: AsRawHandle> Drop for AsyncFileWriteFuture<'a, H> {
+    fn drop(&mut self) {
+        self.overlapped.cancel(self.handle);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::mock::{MockCompletion, MockIo};
+    use super::*;
+    use crate::test_util::{block_on, pattern, TempFile};
+    use futures_util::FutureExt;
+    use std::time::Duration;
+    use windows::Win32::Foundation::ERROR_CRC;
+
+    // Each read is polled once and dropped, with its callback due anywhere
+    // from at once to a little later, so the drop races the completion. If a
+    // drop could return before the callback had run, the bytes it delivers
+    // would land on top of the fill after it.
+    #[test]
+    fn dropping_reads_mid_flight_leaves_buf_alone() {
+        let temp = TempFile::new(&[0; 4096]);
+        let mock = MockIo::new([]);
+        let file = block_on(AsyncFile::open_for_read(temp.path()))
+            .unwrap()
+            .with_mock(mock.clone());
+        let mut buf = vec![0; 4096];
+        for i in 0..500 {
+            let delay = Duration::from_micros(i % 50);
+            mock.push(MockCompletion::Callback(vec![i as u8; 4096], delay));
+            let _ = file.read_at(&mut buf, 0).now_or_never();
+            buf.fill(0xaa);
+        }
+        std::thread::sleep(Duration::from_millis(10));
+        assert!(buf.iter().all(|&b| b == 0xaa));
+        assert_eq!(file.pending_ops(), 0);
+    }
+
+    // The read goes pending on the first poll, and the struct holding it is
+    // moved twice before the callback delivers. The OVERLAPPED the callback
+    // writes through is on the heap, so the bytes still land in buf.
+    #[tokio::test]
+    async fn moving_a_pending_read_keeps_its_overlapped() {
+        struct Holder<F> {
+            read: Pin<Box<F>>,
+        }
+
+        let temp = TempFile::new(&[0; 64]);
+        let delay = Duration::from_millis(30);
+        let mock = MockIo::new([MockCompletion::Callback(b"moved".to_vec(), delay)]);
+        let file = AsyncFile::open_for_read(temp.path())
+            .await
+            .unwrap()
+            .with_mock(mock);
+        let mut buf = [0; 64];
+
+        let n = {
+            let mut holder = Holder {
+                read: Box::pin(file.read_at(&mut buf, 0)),
+            };
+            let first = std::future::poll_fn(|cx| Poll::Ready(holder.read.as_mut().poll(cx))).await;
+            assert!(first.is_pending());
+            let holders = vec![holder];
+            let holder = holders.into_iter().next().unwrap();
+            let moved = Box::new(holder);
+            moved.read.await.unwrap()
+        };
+        assert_eq!(n, 5);
+        assert_eq!(&buf[..5], b"moved");
+        assert_eq!(file.pending_ops(), 0);
+    }
+
+    fn handle_count() -> u32 {
+        use windows::Win32::System::Threading::{GetCurrentProcess, GetProcessHandleCount};
+        let mut count = 0;
+        unsafe { GetProcessHandleCount(GetCurrentProcess(), &mut count) }.unwrap();
+        count
+    }
+
+    // Half dropped and half closed, each after a read so it's been bound to
+    // the thread pool. A leak would be one handle a file; the thread pool may
+    // make a few of its own along the way, hence the slack.
+    #[tokio::test]
+    async fn files_close_their_handle_once() {
+        let temp = TempFile::new(b"handle");
+        let mut buf = [0; 8];
+        AsyncFile::open_for_read(temp.path())
+            .await
+            .unwrap()
+            .read_at(&mut buf, 0)
+            .await
+            .unwrap();
+
+        let before = handle_count();
+        for i in 0..500 {
+            let file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+            assert_eq!(file.read_at(&mut buf, 0).await.unwrap(), 6);
+            if i % 2 == 0 {
+                file.close().unwrap();
+            }
+        }
+        assert!(handle_count() < before + 50);
+    }
+
+    // Three reads whose callbacks come a while after they're issued, checked
+    // on while they're pending and again once they've all completed.
+    #[tokio::test]
+    async fn pending_ops_counts_reads_in_flight() {
+        let temp = TempFile::new(&[0; 64]);
+        let delay = Duration::from_millis(30);
+        let mock = MockIo::new(vec![MockCompletion::Callback(vec![1; 8], delay); 3]);
+        let file = AsyncFile::open_for_read(temp.path())
+            .await
+            .unwrap()
+            .with_mock(mock);
+        assert_eq!(file.pending_ops(), 0);
+
+        let mut bufs = [[0; 8]; 3];
+        let [a, b, c] = &mut bufs;
+        let reads = futures_util::future::join3(
+            file.read_at(a, 0),
+            file.read_at(b, 8),
+            file.read_at(c, 16),
+        );
+        let (results, ()) = futures_util::future::join(reads, async {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            assert_eq!(file.pending_ops(), 3);
+        })
+        .await;
+
+        assert!([results.0, results.1, results.2]
+            .iter()
+            .all(|r| matches!(r, Ok(8))));
+        assert_eq!(file.pending_ops(), 0);
+    }
+
+    // Reads that never complete by themselves, all ended by the one call,
+    // with a read issued afterwards left to go through as normal.
+    #[tokio::test]
+    async fn cancel_all_ends_every_pending_read() {
+        let temp = TempFile::new(b"still readable");
+        let mock = MockIo::new(vec![MockCompletion::Stall; 4]);
+        let file = AsyncFile::open_for_read(temp.path())
+            .await
+            .unwrap()
+            .with_mock(mock.clone());
+
+        let mut bufs = [[0; 16]; 4];
+        let reads = bufs.iter_mut().map(|buf| file.read_at(buf, 0));
+        let (results, ()) =
+            futures_util::future::join(futures_util::future::join_all(reads), async {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+                assert_eq!(file.pending_ops(), 4);
+                file.cancel_all().unwrap();
+            })
+            .await;
+
+        assert!(results
+            .iter()
+            .all(|r| matches!(r, Err(AsyncFileError::Cancelled))));
+        assert_eq!(file.pending_ops(), 0);
+        let mut buf = [0; 16];
+        let n = file.read_at(&mut buf, 0).await.unwrap();
+        assert_eq!(&buf[..n], b"still readable");
+    }
+
+    // A read failing mid-stream ends read_all() with the error, and polling
+    // it again from there panics rather than issuing ReadFile afresh, which
+    // the script's last read being left unplayed shows.
+    #[test]
+    fn polling_past_an_error_does_not_restart() {
+        let temp = TempFile::new(&[0; 64]);
+        let mock = MockIo::new([
+            MockCompletion::Inline(vec![7; 16]),
+            MockCompletion::Failed(ERROR_CRC.0, Duration::from_millis(5)),
+            MockCompletion::Inline(vec![9; 16]),
+        ]);
+        let file = block_on(AsyncFile::open_for_read(temp.path()))
+            .unwrap()
+            .with_mock(mock.clone());
+        let mut buf = [0; 16];
+        let mut chunks = 0;
+        {
+            let mut read = std::pin::pin!(AsyncFileReadFuture {
+                handle: &file.handle,
+                stats: &file.stats,
+                buf: &mut buf,
+                overlapped: Box::new(file.overlapped()),
+                offset: 0,
+                callback: |_: &[u8]| chunks += 1,
+                issued: false,
+                finished: false,
+            });
+
+            assert!(matches!(
+                block_on(read.as_mut()),
+                Err(AsyncFileError::Read(ERROR_CRC))
+            ));
+            let again =
+                std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| block_on(read.as_mut())));
+            assert!(again.is_err());
+            assert_eq!(mock.remaining(), 1);
+        }
+        assert_eq!(chunks, 1);
+        assert_eq!(file.pending_ops(), 0);
+    }
+
+    struct CountWakes(std::sync::atomic::AtomicUsize, std::thread::Thread);
+
+    impl std::task::Wake for CountWakes {
+        fn wake(self: Arc<Self>) {
+            self.0.fetch_add(1, Ordering::Relaxed);
+            self.1.unpark();
+        }
+    }
+
+    // Reads of a file in the cache complete inside ReadFile. As nothing is
+    // queued for those, the only wakes are for the reads that did go
+    // pending, one each, and none turn up late for a read long since over.
+    #[test]
+    fn hot_cache_reads_complete_without_wakes() {
+        let data = pattern(256 * 1024);
+        let temp = TempFile::new(&data);
+        let file = block_on(AsyncFile::open_for_read(temp.path())).unwrap();
+        let counter = Arc::new(CountWakes(Default::default(), std::thread::current()));
+        let waker = counter.clone().into();
+        let mut cx = Context::from_waker(&waker);
+
+        let mut buf = vec![0; 4096];
+        let (mut inline, mut pending) = (0, 0);
+        for i in 0..2000 {
+            let offset = (i % 64) * 4096;
+            let n = {
+                let mut read = std::pin::pin!(file.read_at(&mut buf, offset as u64));
+                match read.as_mut().poll(&mut cx) {
+                    Poll::Ready(n) => {
+                        inline += 1;
+                        n
+                    }
+                    Poll::Pending => {
+                        pending += 1;
+                        loop {
+                            std::thread::park();
+                            if let Poll::Ready(n) = read.as_mut().poll(&mut cx) {
+                                break n;
+                            }
+                        }
+                    }
+                }
+            };
+            assert_eq!(n.unwrap(), 4096);
+            assert_eq!(buf, data[offset..offset + 4096]);
+        }
+        std::thread::sleep(Duration::from_millis(20));
+
+        assert!(inline > 0);
+        assert_eq!(counter.0.load(Ordering::Relaxed), pending);
+        assert_eq!(file.pending_ops(), 0);
+    }
+
+    // A duplicate shares the original's file object, which is what carries
+    // the binding, so binding it is binding the same handle again.
+    #[tokio::test]
+    async fn binding_twice_is_double_bind() {
+        let temp = TempFile::new(b"bound");
+        let file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+        let duplicate = file.handle.try_clone().unwrap();
+        let Err(e) = AsyncHandle::new(duplicate) else {
+            panic!("bound a second time");
+        };
+        assert!(matches!(e, AsyncFileError::DoubleBind));
+
+        // And the first binding is none the worse for it.
+        let mut buf = [0; 8];
+        let n = file.read_at(&mut buf, 0).await.unwrap();
+        assert_eq!(&buf[..n], b"bound");
+    }
+
+    // std opens synchronously, which AsyncHandle::new() won't take, so
+    // from_std() opens the file again overlapped, carrying the cursor over.
+    // Only when the original's sharing keeps a second handle out is it
+    // refused.
+    #[tokio::test]
+    async fn from_std_reopens_a_synchronous_file() {
+        use std::os::windows::fs::OpenOptionsExt;
+
+        let data = pattern(10_000);
+        let temp = TempFile::new(&data);
+        let Err(e) = AsyncHandle::new(File::open(temp.path()).unwrap()) else {
+            panic!("took a synchronous handle");
+        };
+        assert!(matches!(e, AsyncFileError::NotOverlapped));
+
+        let mut std_file = File::open(temp.path()).unwrap();
+        std_file.seek(SeekFrom::Start(100)).unwrap();
+        let mut file = AsyncFile::from_std(std_file).unwrap();
+        let mut buf = [0; 50];
+        file.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, data[100..150]);
+        let n = file.read_at(&mut buf, 9_990).await.unwrap();
+        assert_eq!(buf[..n], data[9_990..]);
+
+        let unshared = std::fs::OpenOptions::new()
+            .read(true)
+            .share_mode(0)
+            .open(temp.path())
+            .unwrap();
+        let Err(e) = AsyncFile::from_std(unshared) else {
+            panic!("reopened a file shared with nobody");
+        };
+        assert!(matches!(e, AsyncFileError::Open(_)));
+    }
+
+    // Bytes trickling in a little at a time and then not at all: what came
+    // before the deadline is the answer, and the read still waiting when it
+    // passed is cancelled rather than left behind.
+    #[cfg(feature = "tokio")]
+    #[tokio::test]
+    async fn slow_reads_fill_what_they_can_by_the_deadline() {
+        let data = pattern(1000);
+        let temp = TempFile::new(&data);
+        let slow = |byte| MockCompletion::Callback(vec![byte; 100], Duration::from_millis(20));
+        let mock = MockIo::new([slow(1), slow(2), slow(3), MockCompletion::Stall]);
+        let mut file = AsyncFile::open_for_read(temp.path())
+            .await
+            .unwrap()
+            .with_mock(mock.clone());
+
+        let mut buf = [0; 1000];
+        let n = file
+            .read_exact_or_timeout(&mut buf, Duration::from_millis(500))
+            .await
+            .unwrap();
+        assert_eq!(n, 300);
+        for (i, chunk) in buf[..300].chunks(100).enumerate() {
+            assert!(chunk.iter().all(|&b| b == i as u8 + 1));
+        }
+        assert!(buf[300..].iter().all(|&b| b == 0));
+        assert_eq!(mock.remaining(), 0);
+        assert_eq!(file.pending_ops(), 0);
+
+        // The cursor is past the 300 and no further.
+        assert_eq!(file.read(&mut buf[..10]).await.unwrap(), 10);
+        assert_eq!(buf[..10], data[300..310]);
+    }
+}
@@ -0,0 +1,590 @@
+use super::{
+    sector_size, AsyncFile, AsyncFileError, CompletionPort, CompletionPriority, IoLimiter, Result,
+    RetryPolicy,
+};
+use crate::blocking::blocking;
+use std::ffi::OsStr;
+use std::io;
+use std::os::windows::ffi::OsStrExt;
+use std::os::windows::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::sync::Arc;
+use windows::Win32::Foundation::MAX_PATH;
+use windows::Win32::Storage::FileSystem::{
+    FILE_FLAG_NO_BUFFERING, FILE_FLAG_OVERLAPPED, FILE_FLAG_RANDOM_ACCESS,
+    FILE_FLAG_SEQUENTIAL_SCAN, FILE_FLAG_WRITE_THROUGH, FILE_SHARE_DELETE, FILE_SHARE_READ,
+    FILE_SHARE_WRITE,
+};
+
+// How the file is expected to be read, which the cache manager sizes its
+// read-ahead and how long it keeps pages by.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AccessPattern {
+    // No hint; the cache manager works it out as it goes.
+    #[default]
+    Normal,
+    // Start to finish, e.g. a scan or a copy. Reads ahead further, and lets
+    // pages go sooner once they've been read.
+    Sequential,
+    // Here and there, e.g. an index or a database. Reads ahead little.
+    Random,
+}
+
+// Says how an AsyncFile is opened, much like std::fs::OpenOptions. Files are
+// always opened overlapped and bound to the completion callback. Sharing
+// defaults to what std does: other handles may read, write and delete.
+#[derive(Clone, Debug)]
+pub struct AsyncOpenOptions {
+    read: bool,
+    write: bool,
+    append: bool,
+    create: bool,
+    create_new: bool,
+    truncate: bool,
+    share_read: bool,
+    share_write: bool,
+    share_delete: bool,
+    no_buffering: bool,
+    write_through: bool,
+    extended_path: bool,
+    access_pattern: AccessPattern,
+    custom_flags: u32,
+    port: Option<Arc<CompletionPort>>,
+    completion_priority: CompletionPriority,
+    polling: bool,
+    callback_flags: u32,
+    limiter: Option<IoLimiter>,
+    retry: Option<RetryPolicy>,
+}
+
+impl Default for AsyncOpenOptions {
+    fn default() -> Self {
+        Self {
+            read: false,
+            write: false,
+            append: false,
+            create: false,
+            create_new: false,
+            truncate: false,
+            share_read: true,
+            share_write: true,
+            share_delete: true,
+            no_buffering: false,
+            write_through: false,
+            extended_path: false,
+            access_pattern: AccessPattern::Normal,
+            custom_flags: 0,
+            port: None,
+            completion_priority: CompletionPriority::Normal,
+            polling: false,
+            callback_flags: 0,
+            limiter: None,
+            retry: None,
+        }
+    }
+}
+
+impl AsyncOpenOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn read(&mut self, read: bool) -> &mut Self {
+        self.read = read;
+        self
+    }
+
+    pub fn write(&mut self, write: bool) -> &mut Self {
+        self.write = write;
+        self
+    }
+
+    // Opens with FILE_APPEND_DATA, and has write() always write at the end of
+    // the file. Implies write access.
+    pub fn append(&mut self, append: bool) -> &mut Self {
+        self.append = append;
+        self
+    }
+
+    // Creates the file if it doesn't exist. Needs write access.
+    pub fn create(&mut self, create: bool) -> &mut Self {
+        self.create = create;
+        self
+    }
+
+    // Creates the file, failing if it already exists. Needs write access.
+    pub fn create_new(&mut self, create_new: bool) -> &mut Self {
+        self.create_new = create_new;
+        self
+    }
+
+    // Empties an existing file on open. Needs write access.
+    pub fn truncate(&mut self, truncate: bool) -> &mut Self {
+        self.truncate = truncate;
+        self
+    }
+
+    // Whether other handles may read the file while this one is open.
+    pub fn share_read(&mut self, share: bool) -> &mut Self {
+        self.share_read = share;
+        self
+    }
+
+    // Whether other handles may write the file while this one is open.
+    pub fn share_write(&mut self, share: bool) -> &mut Self {
+        self.share_write = share;
+        self
+    }
+
+    // Whether the file may be deleted or renamed while this handle is open.
+    pub fn share_delete(&mut self, share: bool) -> &mut Self {
+        self.share_delete = share;
+        self
+    }
+
+    // Bypasses the page cache. Reads must then be sector aligned, see
+    // AsyncFile::aligned_buffer().
+    pub fn no_buffering(&mut self, no_buffering: bool) -> &mut Self {
+        self.no_buffering = no_buffering;
+        self
+    }
+
+    // Has each write complete only once it's on the disk rather than in the
+    // cache, as with FILE_FLAG_WRITE_THROUGH, so a write that has finished
+    // survives a crash without a flush() after it. Every write then waits for
+    // the disk, which for small ones is a lot slower than letting the cache
+    // gather them, and the drive's own cache may still hold on to it unless
+    // no_buffering is set too. Reads are unaffected.
+    pub fn write_through(&mut self, write_through: bool) -> &mut Self {
+        self.write_through = write_through;
+        self
+    }
+
+    // Opens through the \\?\ form of the path even when it's short enough not
+    // to need it. Long paths always are.
+    pub fn extended_path(&mut self, extended_path: bool) -> &mut Self {
+        self.extended_path = extended_path;
+        self
+    }
+
+    // Hints with FILE_FLAG_SEQUENTIAL_SCAN or FILE_FLAG_RANDOM_ACCESS. Any
+    // pattern reads the same; only the caching differs.
+    pub fn access_pattern(&mut self, pattern: AccessPattern) -> &mut Self {
+        self.access_pattern = pattern;
+        self
+    }
+
+    // Extra FILE_FLAG_* and FILE_ATTRIBUTE_* bits for CreateFileW.
+    // FILE_FLAG_OVERLAPPED is added regardless.
+    pub fn custom_flags(&mut self, flags: u32) -> &mut Self {
+        self.custom_flags = flags;
+        self
+    }
+
+    // Delivers completions to port instead of the system thread pool.
+    pub fn completion_port(&mut self, port: Arc<CompletionPort>) -> &mut Self {
+        self.port = Some(port);
+        self
+    }
+
+    // How soon the port wakes the file's completions relative to others', see
+    // CompletionPriority. Only matters with completion_port().
+    pub fn completion_priority(&mut self, priority: CompletionPriority) -> &mut Self {
+        self.completion_priority = priority;
+        self
+    }
+
+    // Leaves the file unbound rather than binding it to the thread pool or a
+    // port, for where neither is available or wanted. Each poll of a read or
+    // write then checks the OVERLAPPED itself, as GetOverlappedResult() would
+    // without waiting, and one still in flight has its task woken to look
+    // again after 1 ms, then 2, doubling up to 16 ms. That costs latency and
+    // some wakeups, but needs nothing beyond the handle. Takes precedence over
+    // completion_port().
+    pub fn polling(&mut self, polling: bool) -> &mut Self {
+        self.polling = polling;
+        self
+    }
+
+    // Flags for the BindIoCompletionCallback that binds the file to the
+    // thread pool, 0 by default, e.g. WT_EXECUTEINPERSISTENTTHREAD for
+    // callbacks that must always run on the same thread. The documentation
+    // now calls the parameter reserved, so what a given Windows makes of
+    // other values is up to it, and one it refuses fails the open. Ignored
+    // with completion_port() or polling().
+    pub fn callback_flags(&mut self, flags: u32) -> &mut Self {
+        self.callback_flags = flags;
+        self
+    }
+
+    // Counts the file's reads against limiter, which other files may share.
+    pub fn limiter(&mut self, limiter: IoLimiter) -> &mut Self {
+        self.limiter = Some(limiter);
+        self
+    }
+
+    // Has read() and read_at() retry transient network failures per policy.
+    pub fn retry(&mut self, policy: RetryPolicy) -> &mut Self {
+        self.retry = Some(policy);
+        self
+    }
+
+    // The path goes to CreateFile as it is, apart from the \\?\ prefix, so one
+    // naming an NTFS stream ("file.txt:notes") opens just that stream.
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(level = "debug", skip(self), err)
+    )]
+    pub async fn open(&self, path: &str) -> Result<AsyncFile> {
+        let mut share_mode = 0;
+        if self.share_read {
+            share_mode |= FILE_SHARE_READ.0;
+        }
+        if self.share_write {
+            share_mode |= FILE_SHARE_WRITE.0;
+        }
+        if self.share_delete {
+            share_mode |= FILE_SHARE_DELETE.0;
+        }
+
+        let mut flags = self.custom_flags | FILE_FLAG_OVERLAPPED.0;
+        if self.no_buffering {
+            flags |= FILE_FLAG_NO_BUFFERING.0;
+        }
+        if self.write_through {
+            flags |= FILE_FLAG_WRITE_THROUGH.0;
+        }
+        match self.access_pattern {
+            AccessPattern::Normal => {}
+            AccessPattern::Sequential => flags |= FILE_FLAG_SEQUENTIAL_SCAN.0,
+            AccessPattern::Random => flags |= FILE_FLAG_RANDOM_ACCESS.0,
+        }
+
+        let path = if self.extended_path || too_long(path) {
+            extended_path(path).map_err(AsyncFileError::Open)?
+        } else {
+            PathBuf::from(path)
+        };
+
+        let mut options = std::fs::OpenOptions::new();
+        options
+            .read(self.read)
+            .write(self.write)
+            .append(self.append)
+            .create(self.create)
+            .create_new(self.create_new)
+            .truncate(self.truncate)
+            .share_mode(share_mode)
+            .custom_flags(flags);
+
+        // CreateFile has no overlapped form, and on a slow share or a file
+        // being scanned it can take seconds, so it goes to the blocking pool
+        // along with the sector size query. Binding is quick and done here.
+        let (file, sector_size) = blocking(move || -> Result<_> {
+            let file = options.open(&path).map_err(AsyncFileError::Open)?;
+            let sector_size = if flags & FILE_FLAG_NO_BUFFERING.0 != 0 {
+                Some(sector_size(&file)?)
+            } else {
+                None
+            };
+            Ok((file, sector_size))
+        })
+        .await??;
+
+        let mut file = match &self.port {
+            _ if self.polling => AsyncFile::bind_polled(file),
+            Some(port) => AsyncFile::bind_to_port(file, port.clone(), self.completion_priority)?,
+            None => AsyncFile::bind_with_flags(file, self.callback_flags)?,
+        };
+        file.sector_size = sector_size;
+        file.limiter = self.limiter.clone();
+        file.append = self.append;
+        file.retry = self.retry.clone();
+        Ok(file)
+    }
+}
+
+// Whether Win32 would refuse path for length. It counts UTF-16 units, so a
+// name of non-ASCII characters can be far more bytes than that.
+pub(super) fn too_long(path: &str) -> bool {
+    OsStr::new(path).encode_wide().count() >= MAX_PATH as usize
+}
+
+// Win32 refuses paths of MAX_PATH or more unless they're given as \\?\ paths,
+// which are handed to the file system untouched. That means they have to be
+// made absolute, with any '/', '.' and '..' resolved, before being prefixed.
+pub(super) fn extended_path(path: &str) -> io::Result<PathBuf> {
+    if path.starts_with(r"\\?\") || path.starts_with(r"\\.\") {
+        return Ok(PathBuf::from(path));
+    }
+
+    // GetFullPathNameW underneath, which also turns '/' into '\'.
+    let absolute = std::path::absolute(path)?;
+    let absolute = absolute.to_string_lossy();
+    Ok(match absolute.strip_prefix(r"\\") {
+        Some(unc) => PathBuf::from(format!(r"\\?\UNC\{unc}")),
+        None => PathBuf::from(format!(r"\\?\{absolute}")),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{pattern, TempFile};
+    use crate::{list_streams, AlignedBuffer, AsyncFileError};
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    #[test]
+    fn length_is_counted_in_utf16_units() {
+        // Two bytes each in UTF-8, one unit each in UTF-16.
+        let accented = format!(r"C:\{}", "é".repeat(200));
+        assert!(accented.len() > MAX_PATH as usize);
+        assert!(!too_long(&accented));
+        assert!(too_long(&format!(r"C:\{}", "a".repeat(300))));
+    }
+
+    // Written with / between the components, which a \\?\ path wouldn't
+    // take as it is.
+    #[tokio::test]
+    async fn path_over_max_path_opens() {
+        let root = std::env::temp_dir().join(format!("async_file_long_{}", std::process::id()));
+        let dir = (0..6).fold(root.to_str().unwrap().to_owned(), |dir, i| {
+            format!("{dir}/{}", i.to_string().repeat(50))
+        });
+        let path = format!("{dir}/file.txt");
+        assert!(path.len() > MAX_PATH as usize);
+        std::fs::create_dir_all(extended_path(&dir).unwrap()).unwrap();
+        std::fs::write(extended_path(&path).unwrap(), b"deep down").unwrap();
+
+        let file = AsyncFile::open_for_read(&path).await.unwrap();
+        let mut buf = [0; 16];
+        let n = file.read_at(&mut buf, 0).await.unwrap();
+        assert_eq!(&buf[..n], b"deep down");
+        let streams = list_streams(&path).await.unwrap();
+        assert_eq!(streams.len(), 1);
+        assert_eq!(streams[0].len(), 9);
+
+        drop(file);
+        std::fs::remove_dir_all(extended_path(root.to_str().unwrap()).unwrap()).unwrap();
+    }
+
+    async fn append_records(mut file: AsyncFile, name: char) {
+        for i in 0..100 {
+            let record = format!("{name}{i:03}\n");
+            file.write(record.as_bytes()).await.unwrap();
+        }
+    }
+
+    // Two handles appending at once, neither knowing where the other has got
+    // to. Every record has to come out whole, and none over another.
+    #[tokio::test]
+    async fn appenders_dont_overwrite_each_other() {
+        let temp = TempFile::new(b"log\n");
+        let mut options = AsyncOpenOptions::new();
+        options.append(true);
+        let a = options.open(temp.path()).await.unwrap();
+        let b = options.open(temp.path()).await.unwrap();
+        futures_util::future::join(append_records(a, 'a'), append_records(b, 'b')).await;
+
+        let log = std::fs::read_to_string(temp.path()).unwrap();
+        let mut lines: Vec<_> = log.lines().collect();
+        assert_eq!(lines.remove(0), "log");
+        lines.sort();
+        let expected: Vec<_> = ['a', 'b']
+            .iter()
+            .flat_map(|name| (0..100).map(move |i| format!("{name}{i:03}")))
+            .collect();
+        assert_eq!(lines, expected);
+    }
+
+    // 4 KiB is a whole number of sectors whatever the disk, 512-byte or 4Kn.
+    // The file doesn't end on one, so the last read comes back short, and
+    // the offset after it would no longer be aligned.
+    #[tokio::test]
+    async fn unbuffered_reads_with_an_aligned_buffer() {
+        let data = pattern(1_000_000);
+        let temp = TempFile::new(&data);
+        let file = AsyncFile::open_unbuffered(temp.path()).await.unwrap();
+
+        let mut buf = AlignedBuffer::new(64 * 1024, 4096);
+        let mut out = Vec::new();
+        loop {
+            let n = file.read_at(&mut buf, out.len() as u64).await.unwrap();
+            out.extend_from_slice(&buf[..n]);
+            if n < buf.len() {
+                break;
+            }
+        }
+        assert_eq!(out, data);
+
+        // Refused before ReadFile sees them.
+        assert!(matches!(
+            file.read_at(&mut buf[..100], 0).await,
+            Err(AsyncFileError::Unaligned(_))
+        ));
+        assert!(matches!(
+            file.read_at(&mut buf, 100).await,
+            Err(AsyncFileError::Unaligned(_))
+        ));
+    }
+
+    // The hints only steer the cache manager, so reads have to come back the
+    // same with either of them as without.
+    #[tokio::test]
+    async fn access_pattern_hints_still_read() {
+        let data = pattern(300_000);
+        let temp = TempFile::new(&data);
+        for hint in [AccessPattern::Sequential, AccessPattern::Random] {
+            let mut file = AsyncOpenOptions::new()
+                .read(true)
+                .access_pattern(hint)
+                .open(temp.path())
+                .await
+                .unwrap();
+            let mut out = Vec::new();
+            file.read_to_end(&mut out).await.unwrap();
+            assert_eq!(out, data, "{hint:?}");
+        }
+    }
+
+    // A buffer from with_capacity() fits whatever sector size the volume
+    // has, where a Vec of the same length is turned away.
+    #[tokio::test]
+    async fn aligned_buffer_fits_a_no_buffering_file() {
+        let data = pattern(20_000);
+        let temp = TempFile::new(&data);
+        let mut file = AsyncOpenOptions::new()
+            .read(true)
+            .write(true)
+            .no_buffering(true)
+            .open(temp.path())
+            .await
+            .unwrap();
+
+        let mut buf = AlignedBuffer::with_capacity(&file, 1000);
+        assert!(buf.len() >= 1000);
+        let n = file.read_at(&mut buf, 0).await.unwrap();
+        assert_eq!(buf[..n], data[..n]);
+
+        buf.fill(7);
+        file.write_at(&buf, 0).await.unwrap();
+        assert!(matches!(
+            file.write_at(&vec![7; 1000], 0).await,
+            Err(AsyncFileError::Unaligned(_))
+        ));
+        assert!(matches!(
+            file.read(&mut vec![0; 1000]).await,
+            Err(AsyncFileError::Unaligned(_))
+        ));
+        drop(file);
+        let written = std::fs::read(temp.path()).unwrap();
+        assert!(written[..buf.len()].iter().all(|&b| b == 7));
+        assert_eq!(written[buf.len()..], data[buf.len()..]);
+    }
+
+    // Each write goes through to the disk before it completes, and what's
+    // there after the file is closed and opened again is what was written.
+    #[tokio::test]
+    async fn write_through_writes_survive_reopening() {
+        let temp = TempFile::new(&[]);
+        let data = pattern(300_000);
+        let mut file = AsyncOpenOptions::new()
+            .write(true)
+            .write_through(true)
+            .open(temp.path())
+            .await
+            .unwrap();
+        for chunk in data.chunks(64 * 1024) {
+            assert_eq!(file.write(chunk).await.unwrap(), chunk.len());
+        }
+        drop(file);
+
+        let mut file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+        let mut out = Vec::new();
+        file.read_to_end(&mut out).await.unwrap();
+        assert_eq!(out, data);
+    }
+
+    // A polled file has nothing to wake its tasks but its own nudges, so
+    // every read or write that goes pending has to be come back to. Rewriting
+    // a file through to the disk, rather than growing it, keeps the writes
+    // from completing inline.
+    #[tokio::test]
+    async fn polled_files_finish_reads_and_writes() {
+        const LEN: usize = 1 << 20;
+        let temp = TempFile::new(&vec![0; LEN]);
+        let data = pattern(LEN);
+        let mut file = AsyncOpenOptions::new()
+            .read(true)
+            .write(true)
+            .write_through(true)
+            .polling(true)
+            .open(temp.path())
+            .await
+            .unwrap();
+
+        for chunk in data.chunks(64 * 1024) {
+            assert_eq!(file.write(chunk).await.unwrap(), chunk.len());
+        }
+        assert_eq!(file.write_at(&data, 0).await.unwrap(), LEN);
+
+        let mut read = Vec::new();
+        let mut buf = vec![0; 64 * 1024];
+        let total = file
+            .read_all(&mut buf, |bytes| read.extend_from_slice(bytes))
+            .await
+            .unwrap();
+        assert_eq!(total, LEN);
+        assert_eq!(read, data);
+        assert_eq!(file.pending_ops(), 0);
+    }
+
+    // WT_EXECUTEINPERSISTENTTHREAD, from winnt.h, has every callback run on
+    // the one thread that never goes away. Reads have to complete the same,
+    // and unbuffered ones can't be served from the cache without a callback.
+    #[tokio::test]
+    async fn reads_complete_with_callback_flags() {
+        const WT_EXECUTEINPERSISTENTTHREAD: u32 = 0x80;
+        let data = pattern(1 << 20);
+        let temp = TempFile::new(&data);
+        let file = AsyncOpenOptions::new()
+            .read(true)
+            .no_buffering(true)
+            .callback_flags(WT_EXECUTEINPERSISTENTTHREAD)
+            .open(temp.path())
+            .await
+            .unwrap();
+
+        let reads = (0..16).map(|i| {
+            let file = &file;
+            async move {
+                let mut buf = file.aligned_buffer(64 * 1024);
+                let n = file.read_at(&mut buf, i * 64 * 1024).await.unwrap();
+                buf[..n].to_vec()
+            }
+        });
+        let read = futures_util::future::join_all(reads).await.concat();
+        assert_eq!(read, data);
+        assert_eq!(file.pending_ops(), 0);
+    }
+
+    // On a runtime with one thread, a task spawned before the open only gets
+    // to run if the open gives the thread up while CreateFile is going on, as
+    // it would if the path were slow to answer.
+    #[tokio::test(flavor = "current_thread")]
+    async fn open_leaves_the_runtime_free() {
+        let temp = TempFile::new(b"data");
+        let ran = Arc::new(AtomicBool::new(false));
+        let other = tokio::spawn({
+            let ran = ran.clone();
+            async move { ran.store(true, Ordering::Relaxed) }
+        });
+
+        let file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+        assert!(ran.load(Ordering::Relaxed));
+        other.await.unwrap();
+        let mut buf = [0; 4];
+        assert_eq!(file.read_at(&mut buf, 0).await.unwrap(), 4);
+    }
+}
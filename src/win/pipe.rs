@@ -0,0 +1,157 @@
+use super::{AsyncFile, AsyncFileError, AsyncOpenOptions, Result};
+use crate::blocking::blocking;
+use windows::core::HSTRING;
+use windows::Win32::Foundation::{ERROR_BROKEN_PIPE, ERROR_PIPE_BUSY};
+use windows::Win32::System::Pipes::{WaitNamedPipeW, NMPWAIT_USE_DEFAULT_WAIT};
+
+// The client end of a named pipe, driven by the same overlapped machinery as
+// AsyncFile.
+pub struct NamedPipeClient {
+    file: AsyncFile,
+}
+
+impl NamedPipeClient {
+    // Connects to the pipe called name, e.g. \\.\pipe\name, for reading and
+    // writing. While every instance of it is busy this waits, in the server's
+    // default time slices, for one to come free.
+    pub async fn connect(name: &str) -> Result<Self> {
+        loop {
+            match AsyncOpenOptions::new()
+                .read(true)
+                .write(true)
+                .open(name)
+                .await
+            {
+                Ok(file) => return Ok(Self { file }),
+                Err(AsyncFileError::Open(e))
+                    if e.raw_os_error() == Some(ERROR_PIPE_BUSY.0 as i32) =>
+                {
+                    wait_for_instance(name).await?
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+
+    // Reads whatever the server has sent, up to buf.len() bytes. Returns 0 once
+    // the server has closed its end.
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        match self.file.read(buf).await {
+            Err(AsyncFileError::Read(code)) if code == ERROR_BROKEN_PIPE => Ok(0),
+            result => result,
+        }
+    }
+
+    pub async fn write(&mut self, buf: &[u8]) -> Result<usize> {
+        self.file.write(buf).await
+    }
+
+    pub fn close(self) -> Result<()> {
+        self.file.close()
+    }
+}
+
+// WaitNamedPipe blocks, so it waits on the blocking pool. Another client may
+// still get to the instance first, in which case connect() goes round again.
+async fn wait_for_instance(name: &str) -> Result<()> {
+    let name = HSTRING::from(name);
+    blocking(move || unsafe { WaitNamedPipeW(&name, NMPWAIT_USE_DEFAULT_WAIT).ok() }).await??;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{pattern, unique_name};
+    use std::fs::File;
+    use std::io::{Read, Write};
+    use std::os::windows::io::{AsRawHandle, FromRawHandle};
+    use windows::Win32::Foundation::{ERROR_PIPE_CONNECTED, HANDLE};
+    use windows::Win32::Storage::FileSystem::PIPE_ACCESS_DUPLEX;
+    use windows::Win32::System::Pipes::{
+        ConnectNamedPipe, CreateNamedPipeW, PIPE_READMODE_BYTE, PIPE_TYPE_BYTE, PIPE_WAIT,
+    };
+
+    // The server end of a new pipe, made with plain synchronous I/O so the
+    // tests can run it on a thread of its own. buffer_size is what the pipe
+    // holds in each direction before a writer has to wait for the reader.
+    fn server(buffer_size: u32) -> (String, File) {
+        let name = format!(r"\\.\pipe\{}", unique_name());
+        let handle = unsafe {
+            CreateNamedPipeW(
+                &HSTRING::from(name.as_str()),
+                PIPE_ACCESS_DUPLEX,
+                PIPE_TYPE_BYTE | PIPE_READMODE_BYTE | PIPE_WAIT,
+                1,
+                buffer_size,
+                buffer_size,
+                0,
+                None,
+            )
+        };
+        assert!(!handle.is_invalid());
+        (name, unsafe { File::from_raw_handle(handle.0) })
+    }
+
+    // ConnectNamedPipe for a client that may already have connected.
+    fn accept(pipe: &File) {
+        let handle = HANDLE(pipe.as_raw_handle());
+        match unsafe { ConnectNamedPipe(handle, None) } {
+            Err(e) if e.code() != ERROR_PIPE_CONNECTED.to_hresult() => panic!("{e}"),
+            _ => {}
+        }
+    }
+
+    // The server sends back each message reversed, then hangs up.
+    #[tokio::test]
+    async fn round_trip_through_a_loopback_pipe() {
+        let (name, mut pipe) = server(4096);
+        let echo = std::thread::spawn(move || {
+            accept(&pipe);
+            let mut buf = [0; 64];
+            let n = pipe.read(&mut buf).unwrap();
+            buf[..n].reverse();
+            pipe.write_all(&buf[..n]).unwrap();
+        });
+
+        let mut client = NamedPipeClient::connect(&name).await.unwrap();
+        assert_eq!(client.write(b"hello, pipe").await.unwrap(), 11);
+        let mut buf = [0; 64];
+        let n = client.read(&mut buf).await.unwrap();
+        assert_eq!(&buf[..n], b"epip ,olleh");
+        echo.join().unwrap();
+
+        // Hung up, which reads as the end rather than an error.
+        assert_eq!(client.read(&mut buf).await.unwrap(), 0);
+    }
+
+    // The pipe holds 4 KiB and the server takes it a little at a time, so a
+    // megabyte can't go in at once: the write waits on the reader, and any
+    // part of it that comes back short is issued again for the rest. Once the
+    // server has gone, writing reports the pipe closed.
+    #[tokio::test]
+    async fn large_write_into_a_small_pipe_gets_through() {
+        let (name, mut pipe) = server(4096);
+        let reader = std::thread::spawn(move || {
+            accept(&pipe);
+            let mut received = vec![0; 1 << 20];
+            let mut filled = 0;
+            while filled < received.len() {
+                let end = (filled + 3000).min(received.len());
+                filled += pipe.read(&mut received[filled..end]).unwrap();
+                std::thread::sleep(std::time::Duration::from_micros(100));
+            }
+            received
+        });
+
+        let data = pattern(1 << 20);
+        let mut client = NamedPipeClient::connect(&name).await.unwrap();
+        assert_eq!(client.write(&data).await.unwrap(), data.len());
+        assert_eq!(reader.join().unwrap(), data);
+
+        assert!(matches!(
+            client.write(b"anyone there?").await,
+            Err(AsyncFileError::PipeClosed)
+        ));
+    }
+}
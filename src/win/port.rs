@@ -0,0 +1,356 @@
+use super::{bind_error, handle_of, waker_callback, AsyncFileError, Result};
+use std::io;
+use std::os::windows::io::AsRawHandle;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::JoinHandle;
+use windows::Win32::Foundation::{CloseHandle, HANDLE, INVALID_HANDLE_VALUE};
+use windows::Win32::System::IO::{
+    CreateIoCompletionPort, GetQueuedCompletionStatusEx, PostQueuedCompletionStatus,
+    OVERLAPPED_ENTRY,
+};
+
+// Wait without a timeout, i.e. INFINITE.
+const WAIT_FOREVER: u32 = u32::MAX;
+
+// Completions run_completions() takes off the port at a time, and sorts by
+// priority before dispatching.
+const DISPATCH_BATCH: usize = 64;
+
+// Which of a port's completions are woken first, set per file with
+// AsyncOpenOptions::completion_priority(). Each time a thread takes a batch
+// of completions off the port, those of High files are dispatched before the
+// rest, so e.g. interactive reads needn't wait behind a backlog of background
+// ones. Completions that haven't arrived yet aren't waited for, so it's an
+// ordering within what's ready rather than a guarantee.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum CompletionPriority {
+    #[default]
+    Normal,
+    High,
+}
+
+impl CompletionPriority {
+    // Handed to the port as the file's completion key.
+    fn key(self) -> usize {
+        match self {
+            Self::Normal => 0,
+            Self::High => 1,
+        }
+    }
+}
+
+// An I/O completion port with threads of your own draining it, for when the
+// system thread pool behind BindIoCompletionCallback gives too little control.
+// Files opened with AsyncOpenOptions::completion_port() deliver their
+// completions here, and each thread calling run_completions() wakes the tasks
+// they belong to. spawn_workers() starts threads the port owns to do that,
+// which shutdown() then joins.
+//
+// Something has to be running run_completions() for as long as such a file has
+// I/O in flight: dropping a future waits for its completion to be dispatched.
+#[derive(Debug)]
+pub struct CompletionPort {
+    port: HANDLE,
+    // Set by shutdown(), after which no more files are taken on and no more
+    // threads start running completions. Only changed with running locked.
+    closed: AtomicBool,
+    // Threads inside run_completions(), which shutdown() waits to reach zero.
+    running: Mutex<usize>,
+    exited: Condvar,
+    // Threads started by spawn_workers(), for shutdown() to join.
+    workers: Mutex<Vec<JoinHandle<Result<()>>>>,
+}
+
+// The port handle may be used from any thread at once.
+unsafe impl Send for CompletionPort {}
+unsafe impl Sync for CompletionPort {}
+
+impl CompletionPort {
+    // A port letting up to concurrency threads run completions at once, with
+    // zero meaning one per processor.
+    pub fn new(concurrency: u32) -> Result<Self> {
+        let port = unsafe { CreateIoCompletionPort(INVALID_HANDLE_VALUE, None, 0, concurrency) }?;
+        Ok(Self {
+            port,
+            closed: AtomicBool::new(false),
+            running: Mutex::new(0),
+            exited: Condvar::new(),
+            workers: Mutex::default(),
+        })
+    }
+
+    // Has completions for file's overlapped I/O queued to this port, to be
+    // dispatched with the given priority. Refused once shutdown() has been
+    // called.
+    pub(crate) fn associate(
+        &self,
+        handle: &impl AsRawHandle,
+        priority: CompletionPriority,
+    ) -> Result<()> {
+        if self.closed.load(Ordering::Acquire) {
+            return Err(AsyncFileError::Io(io::Error::other(
+                "completion port is shut down",
+            )));
+        }
+        unsafe { CreateIoCompletionPort(handle_of(handle), self.port, priority.key(), 0) }
+            .map_err(bind_error)?;
+        Ok(())
+    }
+
+    // Starts count threads of the port's own running run_completions(), on
+    // top of any already going. Each keeps the port alive until it returns.
+    pub fn spawn_workers(self: &Arc<Self>, count: usize) -> Result<()> {
+        let mut workers = self.workers.lock().unwrap();
+        for _ in 0..count {
+            let port = self.clone();
+            let worker = std::thread::Builder::new()
+                .name("async-file-completions".into())
+                .spawn(move || port.run_completions())
+                .map_err(AsyncFileError::Io)?;
+            workers.push(worker);
+        }
+        Ok(())
+    }
+
+    // Dispatches completions on the calling thread until stop() or shutdown()
+    // is called. Returns straight away once the port has been shut down.
+    pub fn run_completions(&self) -> Result<()> {
+        {
+            let mut running = self.running.lock().unwrap();
+            if self.closed.load(Ordering::Acquire) {
+                return Ok(());
+            }
+            *running += 1;
+        }
+        let _running = Running(self);
+
+        let mut entries = [OVERLAPPED_ENTRY::default(); DISPATCH_BATCH];
+        loop {
+            let mut removed = 0;
+            unsafe {
+                GetQueuedCompletionStatusEx(
+                    self.port,
+                    &mut entries,
+                    &mut removed,
+                    WAIT_FOREVER,
+                    false,
+                )
+            }?;
+            let entries = &entries[..removed as usize];
+
+            // stop()'s packets have no OVERLAPPED, and there's one for each
+            // thread. Whatever came out with one is still dispatched first.
+            // Should a batch hold more than one, the extras go back for the
+            // threads they were meant for.
+            let stops = entries
+                .iter()
+                .filter(|entry| entry.lpOverlapped.is_null())
+                .count();
+            for priority in [CompletionPriority::High, CompletionPriority::Normal] {
+                for entry in entries.iter().filter(|entry| {
+                    !entry.lpOverlapped.is_null() && entry.lpCompletionKey == priority.key()
+                }) {
+                    unsafe { dispatch(entry) };
+                }
+            }
+
+            if stops != 0 {
+                for _ in 1..stops {
+                    self.post_stop()?;
+                }
+                return Ok(());
+            }
+        }
+    }
+
+    // Makes every thread in run_completions() return once it has dispatched
+    // what's already queued ahead of this, by queueing one packet for each.
+    // Threads that start running completions afterwards carry on.
+    pub fn stop(&self) -> Result<()> {
+        let running = self.running.lock().unwrap();
+        for _ in 0..*running {
+            self.post_stop()?;
+        }
+        Ok(())
+    }
+
+    fn post_stop(&self) -> Result<()> {
+        unsafe { PostQueuedCompletionStatus(self.port, 0, 0, None) }?;
+        Ok(())
+    }
+
+    // Refuses new files and new threads, stops every thread in
+    // run_completions(), joins those spawn_workers() started and waits for the
+    // rest to return, so each completion already queued has been dispatched
+    // by the time this does. It takes the caller's handle on the port, though
+    // files opened on it keep theirs until they're dropped. I/O still in the
+    // kernel is another matter: drop the port's files first, which cancels
+    // theirs and waits for it while the threads are still there to deliver
+    // it. Calling this from run_completions()' own thread never returns.
+    pub fn shutdown(self: Arc<Self>) -> Result<()> {
+        {
+            let _running = self.running.lock().unwrap();
+            self.closed.store(true, Ordering::Release);
+        }
+        self.stop()?;
+
+        let workers = std::mem::take(&mut *self.workers.lock().unwrap());
+        for worker in workers {
+            match worker.join() {
+                Ok(result) => result?,
+                Err(panic) => std::panic::resume_unwind(panic),
+            }
+        }
+
+        let mut running = self.running.lock().unwrap();
+        while *running != 0 {
+            running = self.exited.wait(running).unwrap();
+        }
+        Ok(())
+    }
+}
+
+// Hands one completion to its OVERLAPPED's callback. Unlike
+// GetQueuedCompletionStatus(), the batched call doesn't say how each
+// operation went; that's left in the OVERLAPPED as an NTSTATUS, which the
+// callback takes as the thread pool would pass it.
+unsafe fn dispatch(entry: &OVERLAPPED_ENTRY) {
+    let status = (*entry.lpOverlapped).Internal as u32;
+    waker_callback(status, entry.dwNumberOfBytesTransferred, entry.lpOverlapped);
+}
+
+// Counts a thread out of run_completions() however it leaves.
+struct Running<'a>(&'a CompletionPort);
+
+impl Drop for Running<'_> {
+    fn drop(&mut self) {
+        *self.0.running.lock().unwrap() -= 1;
+        self.0.exited.notify_all();
+    }
+}
+
+impl Drop for CompletionPort {
+    fn drop(&mut self) {
+        let _ = unsafe { CloseHandle(self.port) };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{pattern, TempFile};
+    use crate::win::OverlappedWrap;
+    use crate::{AsyncFile, AsyncOpenOptions};
+    use futures_util::future::join_all;
+    use std::task::{Wake, Waker};
+    use std::time::{Duration, Instant};
+
+    async fn open_on(port: &Arc<CompletionPort>, temp: &TempFile) -> Result<AsyncFile> {
+        AsyncOpenOptions::new()
+            .read(true)
+            .completion_port(port.clone())
+            .open(temp.path())
+            .await
+    }
+
+    fn wait_running(port: &CompletionPort, count: usize) {
+        let deadline = Instant::now() + Duration::from_secs(5);
+        while *port.running.lock().unwrap() != count {
+            assert!(Instant::now() < deadline, "never reached {count} running");
+            std::thread::sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn shutdown_joins_workers_after_reads() {
+        const CHUNK: usize = 64 * 1024;
+        let data = pattern(16 * CHUNK);
+        let temp = TempFile::new(&data);
+        let port = Arc::new(CompletionPort::new(0).unwrap());
+        port.spawn_workers(4).unwrap();
+
+        let file = open_on(&port, &temp).await.unwrap();
+        let reads = (0..16).map(|i| {
+            let file = &file;
+            async move {
+                let mut buf = vec![0; CHUNK];
+                let n = file.read_at(&mut buf, (i * CHUNK) as u64).await.unwrap();
+                (i, n, buf)
+            }
+        });
+        for (i, n, buf) in join_all(reads).await {
+            assert_eq!(n, CHUNK);
+            assert_eq!(buf, data[i * CHUNK..][..CHUNK]);
+        }
+        drop(file);
+
+        let weak = Arc::downgrade(&port);
+        tokio::task::spawn_blocking(move || port.shutdown())
+            .await
+            .unwrap()
+            .unwrap();
+        // The workers' handles on the port went with their threads.
+        assert!(weak.upgrade().is_none());
+    }
+
+    #[tokio::test]
+    async fn shutdown_refuses_new_files() {
+        let temp = TempFile::new(b"data");
+        let port = Arc::new(CompletionPort::new(0).unwrap());
+        let kept = port.clone();
+        port.shutdown().unwrap();
+
+        assert!(open_on(&kept, &temp).await.is_err());
+        // Nor does a thread get to start running completions.
+        kept.run_completions().unwrap();
+    }
+
+    // Each thread takes exactly one of stop()'s packets, so a thread started
+    // afterwards doesn't find one left over and return at once.
+    #[test]
+    fn stop_leaves_nothing_behind() {
+        let port = Arc::new(CompletionPort::new(0).unwrap());
+        port.spawn_workers(3).unwrap();
+        wait_running(&port, 3);
+        port.stop().unwrap();
+        wait_running(&port, 0);
+
+        port.spawn_workers(1).unwrap();
+        wait_running(&port, 1);
+        std::thread::sleep(Duration::from_millis(50));
+        assert_eq!(*port.running.lock().unwrap(), 1);
+        port.shutdown().unwrap();
+    }
+
+    // Notes which operation's waker it was.
+    struct Tagged(usize, Arc<Mutex<Vec<usize>>>);
+
+    impl Wake for Tagged {
+        fn wake(self: Arc<Self>) {
+            self.1.lock().unwrap().push(self.0);
+        }
+    }
+
+    // Completions posted to the port by hand, all queued before a thread
+    // takes them off together, so they make up a single batch.
+    #[test]
+    fn high_priority_completions_are_woken_first() {
+        use CompletionPriority::{High, Normal};
+        let port = CompletionPort::new(1).unwrap();
+        let woken = Arc::new(Mutex::new(Vec::new()));
+        let priorities = [Normal, Normal, High, Normal, High, Normal];
+
+        let mut ops: Vec<Box<OverlappedWrap>> = priorities.iter().map(|_| Box::default()).collect();
+        for (i, (op, priority)) in ops.iter_mut().zip(priorities).enumerate() {
+            op.arm(&Waker::from(Arc::new(Tagged(i, woken.clone()))));
+            unsafe { PostQueuedCompletionStatus(port.port, 0, priority.key(), Some(&op.o)) }
+                .unwrap();
+        }
+        port.post_stop().unwrap();
+        port.run_completions().unwrap();
+
+        assert_eq!(*woken.lock().unwrap(), [2, 4, 0, 1, 3, 5]);
+        assert!(ops.iter().all(|op| !op.pending.load(Ordering::Acquire)));
+    }
+}
@@ -0,0 +1,142 @@
+use super::AsyncFileError;
+use crate::blocking::blocking;
+use std::time::Duration;
+use windows::Win32::Foundation::{
+    ERROR_NETNAME_DELETED, ERROR_NETWORK_BUSY, ERROR_SEM_TIMEOUT, ERROR_UNEXP_NET_ERR,
+    ERROR_VC_DISCONNECTED,
+};
+
+// How often read() and read_at() go back for another try when a read of a
+// network file fails in a way that tends to clear up by itself. Each retry
+// waits twice as long as the one before, starting at backoff. Anything else,
+// access denied say, fails straight away.
+#[derive(Clone, Debug)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    backoff: Duration,
+}
+
+impl RetryPolicy {
+    // max_attempts counts the first try, so 1 never retries.
+    pub fn new(max_attempts: u32, backoff: Duration) -> Self {
+        Self {
+            max_attempts: max_attempts.max(1),
+            backoff,
+        }
+    }
+
+    // Whether a read that failed with error on the given attempt, counting
+    // from 1, should be tried again.
+    pub(crate) fn should_retry(&self, error: &AsyncFileError, attempt: u32) -> bool {
+        attempt < self.max_attempts && is_transient(error)
+    }
+
+    // The wait before retrying after the given attempt failed.
+    pub(crate) fn delay(&self, attempt: u32) -> Duration {
+        self.backoff.saturating_mul(1 << (attempt - 1).min(16))
+    }
+
+    // Sits out delay(attempt). Under a tokio runtime that's its timer;
+    // otherwise a thread sleeps it out, which is fine for the odd retry of a
+    // failed read but wouldn't be for a timer per read.
+    pub(crate) async fn wait(&self, attempt: u32) {
+        let delay = self.delay(attempt);
+        #[cfg(feature = "tokio")]
+        if tokio::runtime::Handle::try_current().is_ok() {
+            return tokio::time::sleep(delay).await;
+        }
+        let _ = blocking(move || std::thread::sleep(delay)).await;
+    }
+}
+
+// Failures SMB shares report for a dropped or overloaded connection, which
+// the redirector usually recovers from given a moment.
+fn is_transient(error: &AsyncFileError) -> bool {
+    matches!(
+        error,
+        AsyncFileError::Read(
+            ERROR_NETNAME_DELETED
+                | ERROR_UNEXP_NET_ERR
+                | ERROR_NETWORK_BUSY
+                | ERROR_SEM_TIMEOUT
+                | ERROR_VC_DISCONNECTED
+        )
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempFile;
+    use crate::win::mock::{MockCompletion, MockIo};
+    use crate::{AsyncFile, AsyncOpenOptions};
+    use std::sync::Arc;
+    use windows::Win32::Foundation::ERROR_ACCESS_DENIED;
+
+    async fn mocked(temp: &TempFile, attempts: u32, mock: Arc<MockIo>) -> AsyncFile {
+        AsyncOpenOptions::new()
+            .read(true)
+            .retry(RetryPolicy::new(attempts, Duration::from_millis(1)))
+            .open(temp.path())
+            .await
+            .unwrap()
+            .with_mock(mock)
+    }
+
+    // One failure refused up front and one from the callback, then the
+    // script runs out and the third try goes to the file.
+    #[tokio::test]
+    async fn fails_twice_then_succeeds() {
+        let temp = TempFile::new(b"over the network");
+        let mock = MockIo::new([
+            MockCompletion::Refused(ERROR_NETNAME_DELETED),
+            MockCompletion::Failed(ERROR_UNEXP_NET_ERR.0, Duration::ZERO),
+        ]);
+        let file = mocked(&temp, 3, mock).await;
+
+        let mut buf = [0; 32];
+        let n = file.read_at(&mut buf, 0).await.unwrap();
+        assert_eq!(&buf[..n], b"over the network");
+        assert_eq!(file.stats().reads(), 3);
+        assert_eq!(file.stats().errors(), 2);
+    }
+
+    #[tokio::test]
+    async fn last_failure_comes_back_once_attempts_run_out() {
+        let temp = TempFile::new(b"never read");
+        let mock = MockIo::new([
+            MockCompletion::Refused(ERROR_NETNAME_DELETED),
+            MockCompletion::Refused(ERROR_NETWORK_BUSY),
+            MockCompletion::Inline(b"too late".to_vec()),
+        ]);
+        let file = mocked(&temp, 2, mock.clone()).await;
+
+        let mut buf = [0; 32];
+        assert!(matches!(
+            file.read_at(&mut buf, 0).await,
+            Err(AsyncFileError::Read(ERROR_NETWORK_BUSY))
+        ));
+        assert_eq!(mock.remaining(), 1);
+    }
+
+    #[tokio::test]
+    async fn access_denied_is_not_retried() {
+        let temp = TempFile::new(b"private");
+        let mock = MockIo::new([MockCompletion::Refused(ERROR_ACCESS_DENIED)]);
+        let file = mocked(&temp, 5, mock).await;
+
+        let mut buf = [0; 32];
+        assert!(matches!(
+            file.read_at(&mut buf, 0).await,
+            Err(AsyncFileError::Read(ERROR_ACCESS_DENIED))
+        ));
+        assert_eq!(file.stats().reads(), 1);
+    }
+
+    #[test]
+    fn backoff_doubles() {
+        let policy = RetryPolicy::new(4, Duration::from_millis(10));
+        let delays: Vec<_> = (1..4).map(|attempt| policy.delay(attempt)).collect();
+        assert_eq!(delays, [10, 20, 40].map(Duration::from_millis));
+    }
+}
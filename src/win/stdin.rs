@@ -0,0 +1,200 @@
+use super::{is_overlapped, AsyncFileError, AsyncHandle, Result};
+use std::io::{self, Read};
+use std::os::windows::io::{AsRawHandle, RawHandle};
+use windows::Win32::Foundation::ERROR_BROKEN_PIPE;
+use windows::Win32::System::Console::{GetStdHandle, STD_INPUT_HANDLE};
+
+// Size of each read the fallback thread makes.
+const STDIN_CHUNK: usize = 1024 * 64;
+
+// Chunks the fallback thread may get ahead of the reader by.
+const STDIN_BACKLOG: usize = 4;
+
+// The process's standard input, read without blocking the runtime. A handle
+// that supports overlapped I/O, such as a pipe created overlapped, is driven
+// like any AsyncHandle. Consoles and the usual synchronous pipes don't, so for
+// those a thread of its own does blocking reads and hands the bytes over.
+pub struct AsyncStdin {
+    inner: Inner,
+}
+
+enum Inner {
+    Overlapped(AsyncHandle<StdinHandle>),
+    Thread {
+        chunks: async_channel::Receiver<io::Result<Vec<u8>>>,
+        // Bytes in chunk[pos..] have been received but not yet handed out.
+        chunk: Vec<u8>,
+        pos: usize,
+    },
+}
+
+// Standard input isn't ours to close, so this only borrows the handle.
+struct StdinHandle(RawHandle);
+
+// A handle is usable from any thread.
+unsafe impl Send for StdinHandle {}
+unsafe impl Sync for StdinHandle {}
+
+impl AsRawHandle for StdinHandle {
+    fn as_raw_handle(&self) -> RawHandle {
+        self.0
+    }
+}
+
+impl AsyncStdin {
+    // Only one should be made. An overlapped handle is bound to the thread
+    // pool for good, so a second one finds it taken, and with the fallback
+    // two threads would split the input between them.
+    pub fn new() -> Result<Self> {
+        let handle = StdinHandle(unsafe { GetStdHandle(STD_INPUT_HANDLE) }?.0);
+
+        // The query fails outright on a console handle.
+        let inner = if is_overlapped(&handle).unwrap_or(false) {
+            Inner::Overlapped(AsyncHandle::new(handle)?)
+        } else {
+            Inner::Thread {
+                chunks: spawn_reader(),
+                chunk: Vec::new(),
+                pos: 0,
+            }
+        };
+        Ok(Self { inner })
+    }
+
+    // Whatever input is available, up to buf.len() bytes, waiting for some if
+    // there's none. Returns 0 once input has ended.
+    pub async fn read(&mut self, buf: &mut [u8]) -> Result<usize> {
+        match &mut self.inner {
+            Inner::Overlapped(handle) => match handle.read(buf).await {
+                // The writing end of the pipe has closed.
+                Err(AsyncFileError::Read(code)) if code == ERROR_BROKEN_PIPE => Ok(0),
+                result => result,
+            },
+            Inner::Thread { chunks, chunk, pos } => {
+                if *pos == chunk.len() {
+                    match chunks.recv().await {
+                        Ok(Ok(next)) => {
+                            *chunk = next;
+                            *pos = 0;
+                        }
+                        Ok(Err(e)) => return Err(AsyncFileError::Io(e)),
+                        Err(_) => return Ok(0),
+                    }
+                }
+
+                let n = buf.len().min(chunk.len() - *pos);
+                buf[..n].copy_from_slice(&chunk[*pos..*pos + n]);
+                *pos += n;
+                Ok(n)
+            }
+        }
+    }
+}
+
+// Reads stdin on a thread rather than the blocking pool, since a read can wait
+// for input indefinitely and the runtime can't shut down while a blocking task
+// is still running. The thread ends at EOF, after an error, or once the
+// AsyncStdin is gone and its next chunk has nowhere to go.
+fn spawn_reader() -> async_channel::Receiver<io::Result<Vec<u8>>> {
+    let (tx, rx) = async_channel::bounded(STDIN_BACKLOG);
+    std::thread::spawn(move || {
+        let mut stdin = io::stdin().lock();
+        loop {
+            let mut chunk = vec![0; STDIN_CHUNK];
+            let sent = match stdin.read(&mut chunk) {
+                Ok(0) => return,
+                Ok(n) => {
+                    chunk.truncate(n);
+                    tx.send_blocking(Ok(chunk))
+                }
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => {
+                    let _ = tx.send_blocking(Err(e));
+                    return;
+                }
+            };
+            if sent.is_err() {
+                return;
+            }
+        }
+    });
+    rx
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{pattern, unique_name};
+    use std::fs::{File, OpenOptions};
+    use std::io::Write;
+    use std::os::windows::fs::OpenOptionsExt;
+    use std::os::windows::io::FromRawHandle;
+    use windows::core::HSTRING;
+    use windows::Win32::Foundation::{ERROR_PIPE_CONNECTED, HANDLE};
+    use windows::Win32::Storage::FileSystem::{FILE_FLAG_OVERLAPPED, PIPE_ACCESS_OUTBOUND};
+    use windows::Win32::System::Console::SetStdHandle;
+    use windows::Win32::System::Pipes::{
+        ConnectNamedPipe, CreateNamedPipeW, PIPE_TYPE_BYTE, PIPE_WAIT,
+    };
+
+    // Stdin swapped for the overlapped end of a pipe, as a parent would hand
+    // one over, with more written into it than the pipe holds at once.
+    #[tokio::test]
+    async fn reads_an_overlapped_pipe_on_stdin() {
+        let name = format!(r"\\.\pipe\{}", unique_name());
+        let handle = unsafe {
+            CreateNamedPipeW(
+                &HSTRING::from(name.as_str()),
+                PIPE_ACCESS_OUTBOUND,
+                PIPE_TYPE_BYTE | PIPE_WAIT,
+                1,
+                4096,
+                4096,
+                0,
+                None,
+            )
+        };
+        assert!(!handle.is_invalid());
+        let mut server = unsafe { File::from_raw_handle(handle.0) };
+        let client = OpenOptions::new()
+            .read(true)
+            .custom_flags(FILE_FLAG_OVERLAPPED.0)
+            .open(&name)
+            .unwrap();
+
+        // Stdin is process-wide, but nothing else in the tests reads it and it
+        // only needs to be in place while new() looks.
+        let original = unsafe { GetStdHandle(STD_INPUT_HANDLE) }.unwrap();
+        unsafe { SetStdHandle(STD_INPUT_HANDLE, HANDLE(client.as_raw_handle())) }.unwrap();
+        let stdin = AsyncStdin::new();
+        unsafe { SetStdHandle(STD_INPUT_HANDLE, original) }.unwrap();
+        let mut stdin = stdin.unwrap();
+        assert!(matches!(stdin.inner, Inner::Overlapped(_)));
+
+        let data = pattern(100_000);
+        let expected = data.clone();
+        let writer = std::thread::spawn(move || {
+            match unsafe { ConnectNamedPipe(HANDLE(server.as_raw_handle()), None) } {
+                Err(e) if e.code() != ERROR_PIPE_CONNECTED.to_hresult() => panic!("{e}"),
+                _ => {}
+            }
+            server.write_all(&data).unwrap();
+        });
+
+        let mut received = Vec::new();
+        let mut buf = vec![0; 10_000];
+        loop {
+            let n = stdin.read(&mut buf).await.unwrap();
+            if n == 0 {
+                break;
+            }
+            received.extend_from_slice(&buf[..n]);
+        }
+        writer.join().unwrap();
+        assert_eq!(received, expected);
+
+        // The handle only borrows client, so it has to go first.
+        drop(stdin);
+        drop(client);
+    }
+}
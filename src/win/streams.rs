@@ -0,0 +1,118 @@
+use super::dir::FindHandle;
+use super::options::{extended_path, too_long};
+use super::{win32_code, AsyncFileError, Result};
+use crate::blocking::blocking;
+use std::ffi::{OsStr, OsString};
+use std::os::windows::ffi::{OsStrExt, OsStringExt};
+use windows::core::PCWSTR;
+use windows::Win32::Foundation::ERROR_HANDLE_EOF;
+use windows::Win32::Storage::FileSystem::{
+    FindFirstStreamW, FindNextStreamW, FindStreamInfoStandard, WIN32_FIND_STREAM_DATA,
+};
+
+// One NTFS data stream of a file, as list_streams() reports it.
+#[derive(Clone, Debug)]
+pub struct StreamInfo {
+    name: OsString,
+    len: u64,
+}
+
+impl StreamInfo {
+    // The name in the form Windows gives it, e.g. "::$DATA" for the file's
+    // main stream and ":notes:$DATA" for one called notes. Appended to the
+    // file's path it names the stream, which opens and reads like any file.
+    pub fn name(&self) -> &OsStr {
+        &self.name
+    }
+
+    pub fn len(&self) -> u64 {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+// The data streams of the file at path, its main one included. A file system
+// without alternate streams, such as FAT, reports none. FindFirstStreamW only
+// blocks, so this runs on the blocking pool.
+pub async fn list_streams(path: &str) -> Result<Vec<StreamInfo>> {
+    let path = if too_long(path) {
+        extended_path(path)
+            .map_err(AsyncFileError::Io)?
+            .into_os_string()
+    } else {
+        path.into()
+    };
+    let path: Vec<u16> = path.encode_wide().chain(Some(0)).collect();
+    blocking(move || find_streams(&path)).await?
+}
+
+fn find_streams(path: &[u16]) -> Result<Vec<StreamInfo>> {
+    let mut streams = Vec::new();
+    let mut data = WIN32_FIND_STREAM_DATA::default();
+
+    let result = unsafe {
+        FindFirstStreamW(
+            PCWSTR(path.as_ptr()),
+            FindStreamInfoStandard,
+            &mut data as *mut WIN32_FIND_STREAM_DATA as *mut _,
+            0,
+        )
+    };
+    // Both calls say there are no (more) streams with ERROR_HANDLE_EOF.
+    let handle = match result {
+        Ok(handle) => FindHandle(handle),
+        Err(error) if win32_code(&error) == ERROR_HANDLE_EOF => return Ok(streams),
+        Err(error) => return Err(AsyncFileError::from(error)),
+    };
+
+    loop {
+        let len = data
+            .cStreamName
+            .iter()
+            .position(|&c| c == 0)
+            .unwrap_or(data.cStreamName.len());
+        streams.push(StreamInfo {
+            name: OsString::from_wide(&data.cStreamName[..len]),
+            len: data.StreamSize as u64,
+        });
+
+        match unsafe {
+            FindNextStreamW(handle.0, &mut data as *mut WIN32_FIND_STREAM_DATA as *mut _)
+        } {
+            Ok(()) => {}
+            Err(error) if win32_code(&error) == ERROR_HANDLE_EOF => return Ok(streams),
+            Err(error) => return Err(AsyncFileError::from(error)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::TempFile;
+    use crate::AsyncFile;
+
+    #[tokio::test]
+    async fn named_stream_is_listed_and_read() {
+        let temp = TempFile::new(b"main");
+        let stream = format!("{}:notes", temp.path());
+        std::fs::write(&stream, b"hidden away").unwrap();
+
+        let mut names: Vec<_> = list_streams(temp.path())
+            .await
+            .unwrap()
+            .into_iter()
+            .map(|s| (s.name().to_owned(), s.len()))
+            .collect();
+        names.sort();
+        assert_eq!(names, [("::$DATA".into(), 4), (":notes:$DATA".into(), 11)]);
+
+        let file = AsyncFile::open_for_read(&stream).await.unwrap();
+        let mut buf = [0; 32];
+        let n = file.read_at(&mut buf, 0).await.unwrap();
+        assert_eq!(&buf[..n], b"hidden away");
+    }
+}
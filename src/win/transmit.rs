@@ -0,0 +1,183 @@
+use super::{
+    completion_code, handle_of, AsyncFile, AsyncFileError, AsyncHandle, OverlappedWrap, Result,
+};
+use crate::stats::IoStats;
+use std::fs::File;
+use std::future::Future;
+use std::os::windows::io::{AsRawHandle, AsRawSocket, RawHandle};
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use windows::Win32::Foundation::WIN32_ERROR;
+use windows::Win32::Networking::WinSock::{TransmitFile, WSAGetLastError, SOCKET, WSA_IO_PENDING};
+
+// Most one TransmitFile is asked to send, comfortably under its 2 GiB limit.
+const TRANSMIT_CHUNK: u64 = 1 << 30;
+
+// A socket of std's, e.g. a connected TcpStream, as a handle AsyncHandle can
+// drive. To overlapped I/O a SOCKET is a handle like any other, and std opens
+// its sockets overlapped, so AsyncHandle::new(SocketHandle::new(stream))
+// gives overlapped reads and writes on it and somewhere for transmit_to() to
+// send to.
+pub struct SocketHandle<S: AsRawSocket>(S);
+
+impl<S: AsRawSocket> SocketHandle<S> {
+    pub fn new(socket: S) -> Self {
+        Self(socket)
+    }
+
+    pub fn get_ref(&self) -> &S {
+        &self.0
+    }
+
+    pub fn into_inner(self) -> S {
+        self.0
+    }
+}
+
+impl<S: AsRawSocket> AsRawHandle for SocketHandle<S> {
+    fn as_raw_handle(&self) -> RawHandle {
+        self.0.as_raw_socket() as RawHandle
+    }
+}
+
+impl AsyncFile {
+    // Sends len bytes of the file from offset down socket with TransmitFile,
+    // straight from the cache with no copy through user space. A len of 0
+    // sends everything from offset to the end. The socket has to be an
+    // AsyncHandle as its completions arrive through it, bound to the callback
+    // or a port; they're counted in its stats() and pending_ops() as writes.
+    // Anything over 1 GiB goes as several TransmitFiles one after another.
+    pub async fn transmit_to<S: AsRawHandle>(
+        &self,
+        socket: &AsyncHandle<S>,
+        offset: u64,
+        len: u64,
+    ) -> Result<()> {
+        let end = if len == 0 {
+            self.metadata().await?.len().max(offset)
+        } else {
+            offset + len
+        };
+
+        let mut offset = offset;
+        while offset < end {
+            let chunk = (end - offset).min(TRANSMIT_CHUNK);
+            TransmitFuture {
+                socket: &socket.handle,
+                file: &self.handle,
+                stats: &socket.stats,
+                overlapped: Box::new(socket.overlapped()),
+                offset,
+                len: chunk as u32,
+                issued: false,
+            }
+            .await?;
+            offset += chunk;
+        }
+        Ok(())
+    }
+}
+
+struct TransmitFuture<'a, S: AsRawHandle> {
+    socket: &'a S,
+    file: &'a File,
+    stats: &'a IoStats,
+    overlapped: Box<OverlappedWrap>,
+    offset: u64,
+    len: u32,
+    // Whether the TransmitFile has been issued yet.
+    issued: bool,
+}
+
+impl<S: AsRawHandle> Future for TransmitFuture<'_, S> {
+    type Output = Result<()>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let this = self.get_mut();
+
+        if !this.issued {
+            this.issued = true;
+            this.overlapped.o.Anonymous.Anonymous.Offset = this.offset as u32;
+            this.overlapped.o.Anonymous.Anonymous.OffsetHigh = (this.offset >> 32) as u32;
+            this.overlapped.arm(cx.waker());
+            this.stats.write_issued();
+
+            let sent = unsafe {
+                TransmitFile(
+                    SOCKET(handle_of(this.socket).0 as usize),
+                    handle_of(this.file),
+                    this.len,
+                    0,
+                    Some(&mut this.overlapped.o),
+                    None,
+                    0,
+                )
+            };
+            if sent.as_bool() {
+                this.overlapped.complete_inline();
+            } else {
+                let code = unsafe { WSAGetLastError() };
+                if code != WSA_IO_PENDING {
+                    this.overlapped.disarm();
+                    let code = WIN32_ERROR(code.0 as u32);
+                    this.stats.record_write(&Err(code));
+                    return Poll::Ready(Err(AsyncFileError::write(code)));
+                }
+            }
+        }
+
+        let (err, len) = ready!(this.overlapped.poll_complete(cx));
+        if err != 0 {
+            let code = completion_code(err);
+            this.stats.record_write(&Err(code));
+            return Poll::Ready(Err(AsyncFileError::write(code)));
+        }
+        this.stats.record_write(&Ok::<_, ()>(len as usize));
+        Poll::Ready(Ok(()))
+    }
+}
+
+impl<S: AsRawHandle> Drop for TransmitFuture<'_, S> {
+    fn drop(&mut self) {
+        self.overlapped.cancel(self.socket);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{pattern, TempFile};
+    use std::io::Read;
+    use std::net::{TcpListener, TcpStream};
+
+    // All of the file, then a range from the middle of it, down one loopback
+    // connection, with the far end reading until it's closed.
+    #[tokio::test]
+    async fn transmits_a_file_over_loopback() {
+        let data = pattern(1 << 20);
+        let temp = TempFile::new(&data);
+        let file = AsyncFile::open_for_read(temp.path()).await.unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let receiver = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut received = Vec::new();
+            stream.read_to_end(&mut received).unwrap();
+            received
+        });
+
+        let stream = TcpStream::connect(addr).unwrap();
+        let socket = AsyncHandle::new(SocketHandle::new(stream)).unwrap();
+        file.transmit_to(&socket, 0, 0).await.unwrap();
+        file.transmit_to(&socket, 1000, 5000).await.unwrap();
+        let stats = socket.stats();
+        assert_eq!(stats.writes(), 2);
+        assert_eq!(stats.bytes_written(), (1 << 20) + 5000);
+        drop(socket);
+
+        let mut expected = data.clone();
+        expected.extend_from_slice(&data[1000..6000]);
+        assert_eq!(receiver.join().unwrap(), expected);
+    }
+}
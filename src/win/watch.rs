@@ -0,0 +1,224 @@
+use super::{
+    bind_error, completion_code, handle_of, waker_callback, win32_code, AsyncFileError,
+    OverlappedWrap, Result,
+};
+use futures_core::Stream;
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::fs::File;
+use std::os::windows::ffi::OsStringExt;
+use std::os::windows::fs::OpenOptionsExt;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::task::{ready, Context, Poll};
+use windows::Win32::Foundation::ERROR_IO_PENDING;
+use windows::Win32::Storage::FileSystem::{
+    ReadDirectoryChangesW, FILE_ACTION, FILE_ACTION_ADDED, FILE_ACTION_MODIFIED,
+    FILE_ACTION_REMOVED, FILE_ACTION_RENAMED_NEW_NAME, FILE_ACTION_RENAMED_OLD_NAME,
+    FILE_FLAG_BACKUP_SEMANTICS, FILE_FLAG_OVERLAPPED, FILE_LIST_DIRECTORY,
+    FILE_NOTIFY_CHANGE_ATTRIBUTES, FILE_NOTIFY_CHANGE_CREATION, FILE_NOTIFY_CHANGE_DIR_NAME,
+    FILE_NOTIFY_CHANGE_FILE_NAME, FILE_NOTIFY_CHANGE_LAST_WRITE, FILE_NOTIFY_CHANGE_SIZE,
+    FILE_NOTIFY_INFORMATION, FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE,
+};
+use windows::Win32::System::IO::BindIoCompletionCallback;
+
+// Bytes of notifications one ReadDirectoryChangesW can return. Changes are
+// only lost if more than this pile up between two polls of the stream. It's
+// also the most a watch on a network share will take.
+const WATCH_BUFFER: usize = 1024 * 64;
+
+// What happened to the entry a ChangeEvent names.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChangeKind {
+    Added,
+    Removed,
+    // Contents, size, attributes or timestamps changed.
+    Modified,
+    // A rename comes as a pair, the old name followed by the new one.
+    RenamedFrom,
+    RenamedTo,
+    // More changed than the buffer could hold, so some went unreported and
+    // the directory is best rescanned. The event's path is empty.
+    Overflow,
+}
+
+// One change reported by a DirectoryWatcher.
+#[derive(Clone, Debug)]
+pub struct ChangeEvent {
+    kind: ChangeKind,
+    path: PathBuf,
+}
+
+impl ChangeEvent {
+    pub fn kind(&self) -> ChangeKind {
+        self.kind
+    }
+
+    // Relative to the watched directory, so with a subtree watch it may run
+    // through subdirectories.
+    pub fn path(&self) -> &PathBuf {
+        &self.path
+    }
+}
+
+// Changes to the entries of a directory, as a stream. Each ReadDirectoryChangesW
+// is overlapped and woken from the completion callback like any read, and the
+// next is issued as soon as the last one's events have been parsed, so changes
+// made in the meantime are buffered by the kernel rather than missed. Dropping
+// the watcher cancels the watch. The stream ends after the first error.
+pub struct DirectoryWatcher {
+    dir: File,
+    subtree: bool,
+    overlapped: Box<OverlappedWrap>,
+    // u32s to keep FILE_NOTIFY_INFORMATION aligned. The kernel writes into it
+    // while a watch is in flight, so it stays put on the heap.
+    buf: Box<[u32]>,
+    in_flight: bool,
+    events: VecDeque<ChangeEvent>,
+    failed: bool,
+}
+
+impl DirectoryWatcher {
+    // Watches the directory at path, and with subtree its subdirectories too.
+    pub fn new(path: &str, subtree: bool) -> Result<Self> {
+        // Backup semantics is what lets CreateFile open a directory at all.
+        // Sharing everything keeps the watch from getting in anyone's way.
+        let dir = std::fs::OpenOptions::new()
+            .access_mode(FILE_LIST_DIRECTORY.0)
+            .share_mode(FILE_SHARE_READ.0 | FILE_SHARE_WRITE.0 | FILE_SHARE_DELETE.0)
+            .custom_flags(FILE_FLAG_BACKUP_SEMANTICS.0 | FILE_FLAG_OVERLAPPED.0)
+            .open(path)
+            .map_err(AsyncFileError::Open)?;
+
+        // Not marked to skip the callback on success as files are, since
+        // ReadDirectoryChangesW returns TRUE whether or not it went pending
+        // and so can't say whether a callback is coming. This way one always is.
+        unsafe { BindIoCompletionCallback(handle_of(&dir), Some(waker_callback), 0) }
+            .map_err(bind_error)?;
+
+        Ok(Self {
+            dir,
+            subtree,
+            overlapped: Box::default(),
+            buf: vec![0u32; WATCH_BUFFER / 4].into_boxed_slice(),
+            in_flight: false,
+            events: VecDeque::new(),
+            failed: false,
+        })
+    }
+
+    fn issue(&mut self, cx: &mut Context<'_>) -> Result<()> {
+        let filter = FILE_NOTIFY_CHANGE_FILE_NAME
+            | FILE_NOTIFY_CHANGE_DIR_NAME
+            | FILE_NOTIFY_CHANGE_ATTRIBUTES
+            | FILE_NOTIFY_CHANGE_SIZE
+            | FILE_NOTIFY_CHANGE_LAST_WRITE
+            | FILE_NOTIFY_CHANGE_CREATION;
+
+        self.overlapped.arm(cx.waker());
+        let result = unsafe {
+            ReadDirectoryChangesW(
+                handle_of(&self.dir),
+                self.buf.as_mut_ptr().cast(),
+                WATCH_BUFFER as u32,
+                self.subtree,
+                filter,
+                None,
+                Some(&mut self.overlapped.o),
+                None,
+            )
+        };
+        match result {
+            Ok(()) => Ok(()),
+            Err(e) if win32_code(&e) == ERROR_IO_PENDING => Ok(()),
+            Err(e) => {
+                self.overlapped.disarm();
+                Err(AsyncFileError::read(win32_code(&e)))
+            }
+        }
+    }
+
+    // Queues the events in the first len bytes of buf.
+    fn parse(&mut self, len: usize) {
+        // Nothing came back: the kernel's own buffer overflowed.
+        if len == 0 {
+            self.events.push_back(ChangeEvent {
+                kind: ChangeKind::Overflow,
+                path: PathBuf::new(),
+            });
+            return;
+        }
+
+        let base = self.buf.as_ptr() as *const u8;
+        let mut offset = 0;
+        loop {
+            // Each entry starts DWORD aligned and within the bytes returned.
+            let info = unsafe { &*(base.add(offset) as *const FILE_NOTIFY_INFORMATION) };
+            let name = unsafe {
+                std::slice::from_raw_parts(info.FileName.as_ptr(), info.FileNameLength as usize / 2)
+            };
+            if let Some(kind) = change_kind(info.Action) {
+                self.events.push_back(ChangeEvent {
+                    kind,
+                    path: PathBuf::from(OsString::from_wide(name)),
+                });
+            }
+
+            if info.NextEntryOffset == 0 || offset + info.NextEntryOffset as usize >= len {
+                break;
+            }
+            offset += info.NextEntryOffset as usize;
+        }
+    }
+}
+
+impl Stream for DirectoryWatcher {
+    type Item = Result<ChangeEvent>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+
+        loop {
+            if let Some(event) = this.events.pop_front() {
+                return Poll::Ready(Some(Ok(event)));
+            }
+            if this.failed {
+                return Poll::Ready(None);
+            }
+
+            if !this.in_flight {
+                if let Err(e) = this.issue(cx) {
+                    this.failed = true;
+                    return Poll::Ready(Some(Err(e)));
+                }
+                this.in_flight = true;
+            }
+
+            let (err, len) = ready!(this.overlapped.poll_complete(cx));
+            this.in_flight = false;
+            if err != 0 {
+                this.failed = true;
+                return Poll::Ready(Some(Err(AsyncFileError::read(completion_code(err)))));
+            }
+            this.parse(len as usize);
+        }
+    }
+}
+
+impl Drop for DirectoryWatcher {
+    fn drop(&mut self) {
+        // The kernel writes into buf until the callback has run.
+        self.overlapped.cancel(&self.dir);
+    }
+}
+
+fn change_kind(action: FILE_ACTION) -> Option<ChangeKind> {
+    match action {
+        FILE_ACTION_ADDED => Some(ChangeKind::Added),
+        FILE_ACTION_REMOVED => Some(ChangeKind::Removed),
+        FILE_ACTION_MODIFIED => Some(ChangeKind::Modified),
+        FILE_ACTION_RENAMED_OLD_NAME => Some(ChangeKind::RenamedFrom),
+        FILE_ACTION_RENAMED_NEW_NAME => Some(ChangeKind::RenamedTo),
+        _ => None,
+    }
+}